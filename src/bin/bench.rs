@@ -0,0 +1,40 @@
+// Searches a fixed set of positions and reports node counts, speed and move ordering quality.
+//
+// usage: bench [depth] [threads]
+
+use std::{env, time::Instant};
+
+use chess::bot::naive_bot::{analyze, SearchOptions, SearchStats};
+use chess::chessboard::ChessBoard;
+
+const POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+];
+
+fn main() {
+    let depth = env::args().nth(1).and_then(|x| x.parse().ok()).unwrap_or(5);
+    let threads = env::args().nth(2).and_then(|x| x.parse().ok()).unwrap_or(1);
+    let mut total = SearchStats::default();
+    let start = Instant::now();
+    for fen in POSITIONS {
+        let board = ChessBoard::from_fen(fen).unwrap();
+        let t = Instant::now();
+        let result = analyze(&board, SearchOptions { max_depth: depth, threads, ..SearchOptions::default() });
+        let elapsed = t.elapsed().as_secs_f64();
+        println!("{}", fen);
+        let best_move = board.mreq_to_uci(result.best_move);
+        println!("    best move {}, score {}, {} nodes, {:.0} nps, first move cutoffs {:.1}%, {} aspiration re-searches, {} table cutoffs",
+            best_move, result.score, result.stats.nodes, result.stats.nodes as f64 / elapsed,
+            100.0 * result.stats.first_move_cutoff_rate(), result.stats.aspiration_researches, result.stats.tt_cutoffs);
+        total.nodes += result.stats.nodes;
+        total.cutoffs += result.stats.cutoffs;
+        total.first_move_cutoffs += result.stats.first_move_cutoffs;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("total: {} nodes, {:.0} nps, first move cutoffs {:.1}%", total.nodes, total.nodes as f64 / elapsed, 100.0 * total.first_move_cutoff_rate());
+}
@@ -0,0 +1,42 @@
+// Self-play harness estimating the Elo of the skill levels, printing a table for `skill::SKILL_ELO`.
+//
+// usage: calibrate [games per pairing] [last level]
+//
+// Level 0 plays the random bot, and every other level the level below it; the rating differences are chained so that
+// the random bot is rated 0. Colors alternate between games.
+
+use std::env;
+
+use chess::bot::random_bot::RANDOM_BOT;
+use chess::bot::skill::{SkillBot, MAX_SKILL_LEVEL};
+use chess::bot::tournament::{play_game, Adjudication, MatchScore};
+use chess::bot::ChessMover;
+use chess::chessboard::ChessBoard;
+
+fn main() {
+    let games: usize = env::args().nth(1).and_then(|x| x.parse().ok()).unwrap_or(10);
+    let last_level: usize = env::args().nth(2).and_then(|x| x.parse().ok()).unwrap_or(MAX_SKILL_LEVEL).min(MAX_SKILL_LEVEL);
+    let start = ChessBoard::new();
+    // games are played out: the random bot does not evaluate, and the noisy evaluations of weak levels are unreliable.
+    let adjudication = Adjudication { resign_plies: usize::MAX, draw_plies: usize::MAX, ..Adjudication::default() };
+    let mut elo = 0.0;
+    let mut table = vec![];
+    for level in 0..=last_level {
+        let mut bot = SkillBot::new(level);
+        let mut opponent: Box<dyn ChessMover> = if level == 0 { Box::new(RANDOM_BOT) } else { Box::new(SkillBot::new(level - 1)) };
+        let mut score = MatchScore::default();
+        for game in 0..games {
+            score.add(if game % 2 == 0 {
+                play_game(&mut bot, opponent.as_mut(), &start, &adjudication).result.white_score()
+            } else {
+                1.0 - play_game(opponent.as_mut(), &mut bot, &start, &adjudication).result.white_score()
+            });
+        }
+        let (diff, error) = score.elo();
+        elo += diff;
+        println!("level {:2}: {:4.1}/{} against {}, {:+5.0} ± {:3.0} -> {:5.0}",
+            level, score.points(), games, if level == 0 { "random".to_string() } else { format!("level {}", level - 1) }, diff, error, elo);
+        table.push(elo.round() as i32);
+    }
+    println!("{:?}", table);
+}
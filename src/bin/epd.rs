@@ -0,0 +1,102 @@
+// Runs the naive bot on an EPD test suite such as WAC or STS and reports which positions it solves.
+//
+// usage: epd <file> [--depth N] [--time MS] [--threads N]
+//
+// A position is solved when the bot plays one of its `bm` moves and none of its `am` moves. Without --time the search
+// runs to the given depth (5 by default); with --time it runs until the time is up.
+
+use std::{env, fs, process, time::Instant};
+
+use chess::bot::naive_bot::{NaiveBot, SearchOptions};
+use chess::chessboard::epd::EpdRecord;
+use chess::chessboard::MoveRequest;
+
+// deepest iteration when searching by time
+const MAX_DEPTH: usize = 32;
+
+fn usage() -> ! {
+    eprintln!("usage: epd <file> [--depth N] [--time MS] [--threads N]");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(path) = args.first() else { usage() };
+    let mut options = SearchOptions::default();
+    let mut i = 1;
+    while i < args.len() {
+        let value = args.get(i + 1).unwrap_or_else(|| usage());
+        match args[i].as_str() {
+            "--depth" => options.max_depth = value.parse().unwrap_or_else(|_| usage()),
+            "--time" => options.time_limit = Some(value.parse().unwrap_or_else(|_| usage())),
+            "--threads" => options.threads = value.parse().unwrap_or_else(|_| usage()),
+            _ => usage(),
+        }
+        i += 2;
+    }
+    if options.time_limit.is_some() && !args.iter().any(|a| a == "--depth") {
+        options.max_depth = MAX_DEPTH;
+    }
+    let text = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    });
+
+    let mut bot = NaiveBot::new(options);
+    let (mut solved, mut failed, mut skipped) = (0, 0, 0);
+    let start = Instant::now();
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let name = format!("line {}", line_number + 1);
+        let record = match EpdRecord::parse(line) {
+            Ok(record) => record,
+            Err(e) => {
+                println!("{}: skipped, {}", name, e);
+                skipped += 1;
+                continue;
+            },
+        };
+        let name = record.id().map_or(name, str::to_string);
+        let (best_moves, avoid_moves) = match (record.best_moves(), record.avoid_moves()) {
+            (Ok(bm), Ok(am)) => (bm, am),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{}: skipped, {}", name, e);
+                skipped += 1;
+                continue;
+            },
+        };
+        if (best_moves.is_empty() && avoid_moves.is_empty()) || record.board.possible_moves().len() == 1 {
+            println!("{}: skipped, nothing to solve", name);
+            skipped += 1;
+            continue;
+        }
+
+        bot.new_game();
+        let t = Instant::now();
+        let result = bot.search(&record.board);
+        let elapsed = t.elapsed().as_secs_f64();
+        let ok = (best_moves.is_empty() || best_moves.contains(&result.best_move)) && !avoid_moves.contains(&result.best_move);
+        if ok {
+            solved += 1;
+        } else {
+            failed += 1;
+        }
+        let san = |moves: &[MoveRequest]| moves.iter().map(|m| record.board.move_to_san(*m)).collect::<Vec<_>>().join(" ");
+        let mut expected = vec![];
+        if !best_moves.is_empty() {
+            expected.push(format!("bm {}", san(&best_moves)));
+        }
+        if !avoid_moves.is_empty() {
+            expected.push(format!("am {}", san(&avoid_moves)));
+        }
+        println!("{}: {}, played {} ({}), expected {}, depth {}, {} nodes, {:.3}s{}",
+            name, if ok { "solved" } else { "failed" }, record.board.move_to_san(result.best_move), result.score,
+            expected.join(", "), result.depth, result.stats.nodes, elapsed,
+            record.comment().map_or(String::new(), |c| format!(" ({})", c)));
+    }
+    let total = solved + failed;
+    println!("solved {}/{} ({:.1}%), {} skipped, {:.1}s",
+        solved, total, if total == 0 { 0.0 } else { 100.0 * solved as f64 / total as f64 }, skipped, start.elapsed().as_secs_f64());
+}
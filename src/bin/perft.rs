@@ -0,0 +1,88 @@
+// Verifies the move generator against published perft numbers, for standard chess, Chess960 and the variants.
+//
+// usage: perft [max depth]              run the built-in suite up to the given depth (4 by default)
+//        perft <fen> <depth>             print the node count of each move and the total
+
+use std::{env, process, time::Instant};
+
+use chess::chessboard::ChessBoard;
+use chess::chessboard::variant::Variant;
+
+// FEN, then node counts at depth 1, 2, ...
+const SUITE: [(&str, &[u64]); 10] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281, 4865609]),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862, 4085603]),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238, 674624]),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467, 422333]),
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379, 2103487]),
+    // Chess960
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672, 8146062]),
+    ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002, 667366, 16253601]),
+    ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471, 273318, 6417013]),
+    ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440, 382958, 9183776]),
+    ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", &[28, 1120, 31058, 1171749, 34030312]),
+];
+
+// variants whose move generation differs from orthodox chess
+const VARIANT_SUITE: [(Variant, &str, &[u64]); 6] = [
+    (Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", &[20, 400, 8067, 153299, 2732672]),
+    (Variant::Crazyhouse, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1", &[20, 400, 8902, 197281, 4888832]),
+    (Variant::Atomic, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197326, 4864979]),
+    (Variant::Horde, "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1", &[8, 128, 1274, 23310, 265223]),
+    (Variant::RacingKings, "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1", &[21, 421, 11264, 296242, 9472927]),
+    (Variant::Capablanca, "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1", &[28, 784, 25228, 805128, 28741319]),
+];
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() == 2 {
+        let board = ChessBoard::from_fen(&args[0]).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let depth: usize = args[1].parse().unwrap_or(1).max(1);
+        let mut total = 0;
+        for m in board.possible_moves().into_iter().skip(1) {
+            let mut t = board;
+            t.do_move(m);
+            let n = t.perft(depth - 1);
+            println!("{}: {}", board.mreq_to_uci(m), n);
+            total += n;
+        }
+        println!("total: {}", total);
+        return;
+    }
+    let max_depth: usize = args.first().and_then(|x| x.parse().ok()).unwrap_or(4);
+    let mut failures = 0;
+    let suite = SUITE.iter().map(|(fen, counts)| (Variant::Standard, *fen, *counts)).chain(VARIANT_SUITE);
+    for (variant, fen, counts) in suite {
+        let board = ChessBoard::from_variant_fen(fen, variant).unwrap();
+        let name = if variant == Variant::Standard { String::new() } else { format!("{} ", variant.name()) };
+        for (depth, expected) in counts.iter().enumerate().map(|(i, n)| (i + 1, *n)).take(max_depth) {
+            let t = Instant::now();
+            let n = board.perft(depth);
+            let ok = n == expected;
+            if !ok {
+                failures += 1;
+            }
+            println!("{}{} depth {}: {} (expected {}) {} {:.2}s", name, fen, depth, n, expected, if ok { "ok" } else { "FAILED" }, t.elapsed().as_secs_f64());
+        }
+    }
+    // the 960 start positions must all be distinct and include the standard one
+    let mut starts: Vec<String> = (0..960).map(|i| ChessBoard::new_chess960(i).to_fen()).collect();
+    if ChessBoard::new_chess960(518).to_fen() != ChessBoard::new().to_fen() {
+        println!("Chess960 position 518 is not the standard start position: {}", ChessBoard::new_chess960(518).to_fen());
+        failures += 1;
+    }
+    starts.sort();
+    starts.dedup();
+    if starts.len() != 960 {
+        println!("only {} distinct Chess960 start positions", starts.len());
+        failures += 1;
+    }
+    if failures > 0 {
+        println!("{} failures", failures);
+        process::exit(1);
+    }
+    println!("all ok");
+}
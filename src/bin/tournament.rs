@@ -0,0 +1,154 @@
+// Plays two bots against each other and reports the score, Elo difference and SPRT verdict.
+//
+// usage: tournament <bot> <bot> [--games N] [--openings file] [--pgn file] [--variant name] [--elo0 X] [--elo1 X]
+//                   [--alpha X] [--beta X]
+//
+// bots: random, skill:<level>, or naive with optional settings, e.g. naive:depth=4,flags=3,threads=2,time=100,nodes=5000
+// (flags being a combination of naive_bot::*_FLAG, time in milliseconds per move).
+// Each opening, one FEN per line, is played twice with colors swapped. Statistics are from the first bot's point of
+// view; the match stops early once the SPRT of elo0 against elo1 is decided. Variants are named as in UCI_Variant,
+// e.g. kingofthehill, 3check or antichess.
+
+use std::{env, fs, process};
+
+use chess::bot::naive_bot::{NaiveBot, SearchOptions};
+use chess::bot::random_bot::RANDOM_BOT;
+use chess::bot::skill::SkillBot;
+use chess::bot::tournament::*;
+use chess::bot::ChessMover;
+use chess::chessboard::ChessBoard;
+use chess::chessboard::variant::Variant;
+
+const OPENINGS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+    "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+    "rnbqkbnr/pppp1ppp/4p3/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+    "rnbqkbnr/ppp1pppp/8/3p4/2PP4/8/PP2PPPP/RNBQKBNR b KQkq c3 0 2",
+    "rnbqkb1r/pppppp1p/5np1/8/2PP4/8/PP2PPPP/RNBQKBNR w KQkq - 0 3",
+    "rnbqkbnr/pp1ppppp/2p5/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+    "rnbqkbnr/pppppppp/8/8/2P5/8/PP1PPPPP/RNBQKBNR b KQkq c3 0 1",
+];
+
+fn usage() -> ! {
+    eprintln!("usage: tournament <bot> <bot> [--games N] [--openings file] [--pgn file] [--variant name] [--elo0 X] [--elo1 X] [--alpha X] [--beta X]");
+    process::exit(1);
+}
+
+fn parse_bot(spec: &str) -> Option<Box<dyn ChessMover>> {
+    let (name, settings) = spec.split_once(':').unwrap_or((spec, ""));
+    match name {
+        "random" => Some(Box::new(RANDOM_BOT)),
+        "skill" => Some(Box::new(SkillBot::new(settings.parse().ok()?))),
+        "naive" => {
+            let mut options = SearchOptions::default();
+            for setting in settings.split(',').filter(|s| !s.is_empty()) {
+                let (key, value) = setting.split_once('=')?;
+                match key {
+                    "depth" => options.max_depth = value.parse().ok()?,
+                    "flags" => options = options.with_flags(value.parse().ok()?),
+                    "threads" => options.threads = value.parse().ok()?,
+                    "time" => options.time_limit = Some(value.parse().ok()?),
+                    "nodes" => options.max_nodes = Some(value.parse().ok()?),
+                    _ => return None,
+                }
+            }
+            Some(Box::new(NaiveBot::new(options)))
+        },
+        _ => None,
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        usage();
+    }
+    let names = [args[0].clone(), args[1].clone()];
+    let mut bots = [
+        parse_bot(&names[0]).unwrap_or_else(|| usage()),
+        parse_bot(&names[1]).unwrap_or_else(|| usage()),
+    ];
+    let mut games = 2 * OPENINGS.len();
+    let mut openings: Option<Vec<String>> = None;
+    let mut variant = Variant::Standard;
+    let mut pgn_path = None;
+    let (mut elo0, mut elo1, mut alpha, mut beta) = (0.0, 10.0, 0.05, 0.05);
+    let mut i = 2;
+    while i < args.len() {
+        let value = args.get(i + 1).unwrap_or_else(|| usage());
+        match args[i].as_str() {
+            "--games" => games = value.parse().unwrap_or_else(|_| usage()),
+            "--openings" => {
+                let text = fs::read_to_string(value).unwrap_or_else(|e| {
+                    eprintln!("{}: {}", value, e);
+                    process::exit(1);
+                });
+                openings = Some(text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).map(str::to_string).collect());
+            },
+            "--variant" => variant = Variant::from_name(value).unwrap_or_else(|| usage()),
+            "--pgn" => pgn_path = Some(value.clone()),
+            "--elo0" => elo0 = value.parse().unwrap_or_else(|_| usage()),
+            "--elo1" => elo1 = value.parse().unwrap_or_else(|_| usage()),
+            "--alpha" => alpha = value.parse().unwrap_or_else(|_| usage()),
+            "--beta" => beta = value.parse().unwrap_or_else(|_| usage()),
+            _ => usage(),
+        }
+        i += 2;
+    }
+
+    // variants with their own start position play it rather than the orthodox openings
+    let openings = openings.unwrap_or_else(|| match variant.start_fen() {
+        Some(fen) => vec![fen.to_string()],
+        None => OPENINGS.iter().map(|fen| fen.to_string()).collect(),
+    });
+    let openings: Vec<ChessBoard> = openings.iter().filter_map(|fen| {
+        ChessBoard::from_variant_fen(fen, variant).map_err(|e| eprintln!("skipping opening {}: {}", fen, e)).ok()
+    }).collect();
+    if openings.is_empty() {
+        eprintln!("no valid openings");
+        process::exit(1);
+    }
+    let adjudication = Adjudication::default();
+    let mut score = MatchScore::default();
+    let mut pgn = String::new();
+    let event = format!("{} vs {}", names[0], names[1]);
+    let mut verdict = SprtVerdict::Continue;
+    for game in 0..games {
+        let start = &openings[(game / 2) % openings.len()];
+        // the first bot is white in even games
+        let first_white = game % 2 == 0;
+        let [a, b] = &mut bots;
+        let mut record = if first_white {
+            play_game(a.as_mut(), b.as_mut(), start, &adjudication)
+        } else {
+            play_game(b.as_mut(), a.as_mut(), start, &adjudication)
+        };
+        let (white, black) = if first_white { (0, 1) } else { (1, 0) };
+        record.white = names[white].clone();
+        record.black = names[black].clone();
+        pgn.push_str(&record.to_pgn(&event, game + 1));
+        let white_score = record.result.white_score();
+        score.add(if first_white { white_score } else { 1.0 - white_score });
+
+        let llr = score.sprt_llr(elo0, elo1);
+        verdict = sprt_verdict(llr, alpha, beta);
+        println!("game {} ({} white): {} by {}, score +{} -{} ={}, llr {:.2}",
+            game + 1, record.white, record.result.to_pgn(), record.termination, score.wins, score.losses, score.draws, llr);
+        if verdict != SprtVerdict::Continue {
+            break;
+        }
+    }
+
+    if let Some(path) = pgn_path {
+        fs::write(&path, pgn).unwrap_or_else(|e| eprintln!("{}: {}", path, e));
+    }
+    let (elo, error) = score.elo();
+    println!("{} vs {}: {:.1}/{} (+{} -{} ={})", names[0], names[1], score.points(), score.games(), score.wins, score.losses, score.draws);
+    println!("elo difference: {:+.1} ± {:.1}", elo, error);
+    println!("sprt (elo0 {}, elo1 {}, alpha {}, beta {}): {}", elo0, elo1, alpha, beta, match verdict {
+        SprtVerdict::AcceptH1 => "H1 accepted",
+        SprtVerdict::AcceptH0 => "H0 accepted",
+        SprtVerdict::Continue => "inconclusive",
+    });
+}
@@ -0,0 +1,210 @@
+// Texel-style tuner for the handcrafted evaluation of the naive bot.
+//
+// usage: tune <positions file> <output file> [--init <params file>] [--iterations N] [--lr X]
+//
+// Every line of the positions file holds a quiet position and the result of the game it was taken from, either as
+// EPD with a c9 opcode (`<fen> c9 "1-0";`), or as a FEN with all six fields followed by `1-0` / `0-1` / `1/2-1/2` / `[1.0]` /
+// `[0.5]` / `[0.0]`.
+// The result is always from white's perspective. The tuned parameters are written in the format of `EvalParams::serialize`,
+// and can be loaded with `naive_bot::load_eval_params`, or in the web UI with `?params=<url of the file>`.
+
+use std::{env, fs, process};
+
+use chess::bot::eval_params::*;
+use chess::bot::naive_bot::{eval_features, DEFAULT_EVAL_PARAMS};
+use chess::chessboard::ChessBoard;
+
+// evaluate() is in 1/16 centipawn.
+const EVAL_SCALE: f64 = 16.0 * 400.0;
+
+struct Sample {
+    features: Vec<(usize, i32)>,
+    result: f64,
+}
+
+fn parse_result(token: &str) -> Option<f64> {
+    match token.trim_matches(|c| c == '"' || c == ';' || c == '[' || c == ']') {
+        "1-0" | "1.0" => Some(1.0),
+        "0-1" | "0.0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+fn parse_line(line: &str) -> Result<(ChessBoard, f64), String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 5 {
+        return Err("too few fields".to_string());
+    }
+    // halfmove and fullmove are only present in FEN, EPD carries opcodes instead.
+    let mut fen_len = 4;
+    if tokens.len() > 6 && tokens[4].parse::<usize>().is_ok() && tokens[5].parse::<usize>().is_ok() {
+        fen_len = 6;
+    }
+    // the result is the operand of c9, or the only field after the six of a FEN.
+    let rest = &tokens[fen_len..];
+    let result = match rest.iter().position(|t| *t == "c9") {
+        Some(i) => rest.get(i + 1).and_then(|t| parse_result(t)),
+        None if fen_len == 6 && rest.len() == 1 => parse_result(rest[0]),
+        None => None,
+    }.ok_or("missing game result")?;
+    let board = ChessBoard::from_fen(&tokens[..fen_len].join(" "))?;
+    Ok((board, result))
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / EVAL_SCALE))
+}
+
+fn evaluate(sample: &Sample, params: &[f64]) -> f64 {
+    sample.features.iter().map(|(i, c)| *c as f64 * params[*i]).sum()
+}
+
+fn mean_squared_error(samples: &[Sample], params: &[f64], k: f64) -> f64 {
+    samples.iter().map(|s| {
+        let diff = s.result - sigmoid(evaluate(s, params), k);
+        diff * diff
+    }).sum::<f64>() / samples.len() as f64
+}
+
+// the error is convex enough in K to do a ternary search.
+fn fit_k(samples: &[Sample], params: &[f64]) -> f64 {
+    let mut lo = 0.0;
+    let mut hi = 4.0;
+    for _ in 0..50 {
+        let m1 = lo + (hi - lo) / 3.0;
+        let m2 = hi - (hi - lo) / 3.0;
+        if mean_squared_error(samples, params, m1) < mean_squared_error(samples, params, m2) {
+            hi = m2;
+        } else {
+            lo = m1;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+fn gradient(samples: &[Sample], params: &[f64], k: f64) -> Vec<f64> {
+    let mut ans = vec![0.0; params.len()];
+    let factor = k * std::f64::consts::LN_10 / EVAL_SCALE;
+    for s in samples {
+        let p = sigmoid(evaluate(s, params), k);
+        let d = -2.0 * (s.result - p) * p * (1.0 - p) * factor;
+        for (i, c) in &s.features {
+            ans[*i] += d * *c as f64;
+        }
+    }
+    ans.iter_mut().for_each(|g| *g /= samples.len() as f64);
+    ans
+}
+
+fn usage() -> ! {
+    eprintln!("usage: tune <positions file> <output file> [--init <params file>] [--iterations N] [--lr X]");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut positional = vec![];
+    let mut init = DEFAULT_EVAL_PARAMS;
+    let mut iterations = 1000;
+    let mut lr = 1.0;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--init" => {
+                let path = args.get(i + 1).unwrap_or_else(|| usage());
+                let data = fs::read(path).unwrap_or_else(|e| { eprintln!("cannot read {}: {}", path, e); process::exit(1) });
+                init = EvalParams::deserialize(&data).unwrap_or_else(|| { eprintln!("{} is not a parameter file", path); process::exit(1) });
+                i += 1;
+            },
+            "--iterations" => {
+                iterations = args.get(i + 1).and_then(|x| x.parse().ok()).unwrap_or_else(|| usage());
+                i += 1;
+            },
+            "--lr" => {
+                lr = args.get(i + 1).and_then(|x| x.parse().ok()).unwrap_or_else(|| usage());
+                i += 1;
+            },
+            x => positional.push(x.to_string()),
+        }
+        i += 1;
+    }
+    if positional.len() != 2 {
+        usage();
+    }
+
+    let text = fs::read_to_string(&positional[0]).unwrap_or_else(|e| { eprintln!("cannot read {}: {}", positional[0], e); process::exit(1) });
+    let mut samples = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line) {
+            Ok((board, result)) => samples.push(Sample { features: eval_features(&board), result }),
+            Err(e) => eprintln!("line {}: {}, skipped", n + 1, e),
+        }
+    }
+    if samples.is_empty() {
+        eprintln!("no positions loaded");
+        process::exit(1);
+    }
+    println!("loaded {} positions", samples.len());
+
+    let mut params: Vec<f64> = init.to_vec().into_iter().map(|x| x as f64).collect();
+    let k = fit_k(&samples, &params);
+    println!("K = {:.4}, initial error = {:.6}", k, mean_squared_error(&samples, &params, k));
+
+    // Adam; the king value is skipped since both sides always have exactly one king.
+    let (beta1, beta2, eps) = (0.9, 0.999, 1e-8);
+    let mut m = vec![0.0; params.len()];
+    let mut v = vec![0.0; params.len()];
+    for t in 1..=iterations {
+        let g = gradient(&samples, &params, k);
+        for j in 0..params.len() {
+            if j == NUM_PIECE_VALUES - 1 {
+                continue;
+            }
+            m[j] = beta1 * m[j] + (1.0 - beta1) * g[j];
+            v[j] = beta2 * v[j] + (1.0 - beta2) * g[j] * g[j];
+            let m_hat = m[j] / (1.0 - f64::powi(beta1, t));
+            let v_hat = v[j] / (1.0 - f64::powi(beta2, t));
+            params[j] -= lr * m_hat / (v_hat.sqrt() + eps);
+        }
+        if t % 100 == 0 || t == iterations {
+            println!("iteration {}: error = {:.6}", t, mean_squared_error(&samples, &params, k));
+        }
+    }
+
+    let rounded: Vec<i32> = params.iter().map(|x| x.round() as i32).collect();
+    let tuned = EvalParams::from_slice(&rounded).unwrap();
+    let rounded_f: Vec<f64> = rounded.iter().map(|x| *x as f64).collect();
+    println!("final error = {:.6}", mean_squared_error(&samples, &rounded_f, k));
+    fs::write(&positional[1], tuned.serialize()).unwrap_or_else(|e| { eprintln!("cannot write {}: {}", positional[1], e); process::exit(1) });
+    println!("written to {}", positional[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -";
+        for (line, result) in [
+            (format!("{} 1-0", fen), 1.0),
+            (format!("{} [0.5]", fen), 0.5),
+            (format!("{} 0.0", fen), 0.0),
+            (format!("{} c9 \"1/2-1/2\";", epd), 0.5),
+            (format!("{} id \"x\"; c9 \"0-1\";", epd), 0.0),
+            (format!("{} c9 \"1-0\";", fen), 1.0),
+        ] {
+            assert_eq!(parse_line(&line).map(|(_, r)| r), Ok(result), "{}", line);
+        }
+        // a FEN without a result is not labelled by its move numbers.
+        for line in [fen.to_string(), format!("{} 1", fen), format!("{} 1 0", epd), format!("{} 1-0 1", fen), format!("{} c9", epd)] {
+            assert_eq!(parse_line(&line).map(|(_, r)| r), Err("missing game result".to_string()), "{}", line);
+        }
+    }
+}
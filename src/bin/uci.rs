@@ -0,0 +1,140 @@
+// Universal Chess Interface front end for the naive bot, for use with chess GUIs and match runners.
+//
+// Supported: uci, isready, ucinewgame, setoption (MultiPV, Depth, Threads, UCI_Chess960, UCI_Variant),
+// position startpos|fen ... [moves ...],
+// go [depth N] [movetime MS], quit. The search is not interruptible, so `stop` is ignored, and `go ponder` is searched
+// as a normal search. The transposition table and history are kept between moves until `ucinewgame`.
+
+use std::io::{self, BufRead, Write};
+
+use chess::bot::naive_bot::{NaiveBot, Score, SearchOptions};
+use chess::chessboard::{ChessBoard, Player};
+use chess::chessboard::variant::{Variant, VARIANTS};
+
+const MAX_MULTI_PV: usize = 64;
+const MAX_DEPTH: usize = 32;
+const MAX_THREADS: usize = 256;
+
+// UCI scores are from the side to move, in centipawns or moves to mate.
+fn uci_score(score: Score, player: Player) -> String {
+    let sign = if player == Player::White { 1 } else { -1 };
+    match score {
+        Score::Eval(value) => format!("cp {}", sign * value / 16),
+        Score::Mate(n) => format!("mate {}", sign * n),
+    }
+}
+
+fn parse_position(tokens: &[&str], chess960: bool, variant: Variant) -> Option<ChessBoard> {
+    let (mut board, rest) = match tokens.first() {
+        Some(&"startpos") => (ChessBoard::new_variant(variant), &tokens[1..]),
+        Some(&"fen") => {
+            let end = tokens.iter().position(|t| *t == "moves").unwrap_or(tokens.len());
+            (ChessBoard::from_variant_fen(&tokens[1..end].join(" "), variant).ok()?, &tokens[end..])
+        },
+        _ => return None,
+    };
+    board.set_chess960(chess960);
+    if rest.first() == Some(&"moves") {
+        for m in &rest[1..] {
+            let mreq = board.parse_uci_move(m)?;
+            board.do_move(mreq);
+        }
+    }
+    Some(board)
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut out = io::stdout();
+    let mut board = ChessBoard::new();
+    let mut options = SearchOptions::default();
+    let mut bot = NaiveBot::new(options);
+    // castling moves are written as the king taking its rook
+    let mut chess960 = false;
+    let mut variant = Variant::Standard;
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else { break };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                writeln!(out, "id name chess naive bot").unwrap();
+                writeln!(out, "id author endaytrer").unwrap();
+                writeln!(out, "option name MultiPV type spin default 1 min 1 max {}", MAX_MULTI_PV).unwrap();
+                writeln!(out, "option name Depth type spin default {} min 1 max {}", options.max_depth, MAX_DEPTH).unwrap();
+                writeln!(out, "option name Threads type spin default 1 min 1 max {}", MAX_THREADS).unwrap();
+                writeln!(out, "option name UCI_Chess960 type check default false").unwrap();
+                let vars: Vec<String> = VARIANTS.iter().map(|v| format!("var {}", v.name())).collect();
+                writeln!(out, "option name UCI_Variant type combo default {} {}", Variant::Standard.name(), vars.join(" ")).unwrap();
+                writeln!(out, "uciok").unwrap();
+            },
+            Some(&"isready") => writeln!(out, "readyok").unwrap(),
+            Some(&"ucinewgame") => {
+                board = ChessBoard::new_variant(variant);
+                board.set_chess960(chess960);
+                bot.new_game();
+            },
+            Some(&"setoption") => {
+                // setoption name <name> value <value>
+                let name = tokens.iter().position(|t| *t == "name").and_then(|i| tokens.get(i + 1));
+                let value = tokens.iter().position(|t| *t == "value").and_then(|i| tokens.get(i + 1));
+                if name.is_some_and(|n| n.eq_ignore_ascii_case("UCI_Chess960")) {
+                    chess960 = value.is_some_and(|v| *v == "true");
+                    board.set_chess960(chess960);
+                    out.flush().unwrap();
+                    continue;
+                }
+                if name.is_some_and(|n| n.eq_ignore_ascii_case("UCI_Variant")) {
+                    match value.and_then(|v| Variant::from_name(v)) {
+                        Some(v) => {
+                            variant = v;
+                            board.set_variant(variant);
+                        },
+                        None => writeln!(out, "info string unsupported variant").unwrap(),
+                    }
+                    out.flush().unwrap();
+                    continue;
+                }
+                let value = value.and_then(|v| v.parse::<usize>().ok());
+                match (name.map(|n| n.to_ascii_lowercase()).as_deref(), value) {
+                    (Some("multipv"), Some(v)) => options.multi_pv = v.clamp(1, MAX_MULTI_PV),
+                    (Some("depth"), Some(v)) => options.max_depth = v.clamp(1, MAX_DEPTH),
+                    (Some("threads"), Some(v)) => options.threads = v.clamp(1, MAX_THREADS),
+                    _ => writeln!(out, "info string unsupported option").unwrap(),
+                }
+            },
+            Some(&"position") => match parse_position(&tokens[1..], chess960, variant) {
+                Some(b) => board = b,
+                None => writeln!(out, "info string invalid position").unwrap(),
+            },
+            Some(&"go") => {
+                let mut search_options = options;
+                if let Some(i) = tokens.iter().position(|t| *t == "depth") {
+                    if let Some(depth) = tokens.get(i + 1).and_then(|d| d.parse::<usize>().ok()) {
+                        search_options.max_depth = depth.clamp(1, MAX_DEPTH);
+                    }
+                }
+                if let Some(i) = tokens.iter().position(|t| *t == "movetime") {
+                    if let Some(ms) = tokens.get(i + 1).and_then(|d| d.parse::<u32>().ok()) {
+                        search_options.time_limit = Some(ms);
+                        search_options.max_depth = MAX_DEPTH;
+                    }
+                }
+                if board.possible_moves().len() == 1 {
+                    writeln!(out, "bestmove 0000").unwrap();
+                    continue;
+                }
+                bot.set_options(search_options);
+                let result = bot.search(&board);
+                for (i, line) in result.lines.iter().enumerate() {
+                    let pv: Vec<String> = line.moves.iter().map(|m| board.mreq_to_uci(*m)).collect();
+                    writeln!(out, "info depth {} multipv {} score {} nodes {} pv {}",
+                        result.depth, i + 1, uci_score(line.score, board.player), result.stats.nodes, pv.join(" ")).unwrap();
+                }
+                writeln!(out, "bestmove {}", board.mreq_to_uci(result.best_move)).unwrap();
+            },
+            Some(&"quit") => break,
+            _ => {},
+        }
+        out.flush().unwrap();
+    }
+}
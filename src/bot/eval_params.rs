@@ -0,0 +1,74 @@
+// Weights of the handcrafted evaluation, stored as a flat list of integers so that they can be tuned and saved.
+// Layout of the flat list: 6 piece values, then 6 * 64 opening square values, then 6 * 64 endgame square values.
+// Square tables are indexed from white's perspective (a1 = 0, h8 = 63).
+
+pub const NUM_PIECE_VALUES: usize = 6;
+pub const NUM_POS_VALUES: usize = 6 * 64;
+pub const START_POS_OFFSET: usize = NUM_PIECE_VALUES;
+pub const END_POS_OFFSET: usize = START_POS_OFFSET + NUM_POS_VALUES;
+pub const NUM_EVAL_PARAMS: usize = END_POS_OFFSET + NUM_POS_VALUES;
+
+const PARAMS_MAGIC: &[u8; 4] = b"CEP1";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalParams {
+    pub piece_values: [i32; 6],
+    pub start_pos_values: [[i32; 64]; 6],
+    pub end_pos_values: [[i32; 64]; 6],
+}
+
+impl EvalParams {
+    pub fn to_vec(&self) -> Vec<i32> {
+        let mut ans = Vec::with_capacity(NUM_EVAL_PARAMS);
+        ans.extend_from_slice(&self.piece_values);
+        self.start_pos_values.iter().for_each(|table| ans.extend_from_slice(table));
+        self.end_pos_values.iter().for_each(|table| ans.extend_from_slice(table));
+        ans
+    }
+
+    pub fn from_slice(values: &[i32]) -> Option<Self> {
+        if values.len() != NUM_EVAL_PARAMS {
+            return None;
+        }
+        let mut ans = EvalParams {
+            piece_values: [0; 6],
+            start_pos_values: [[0; 64]; 6],
+            end_pos_values: [[0; 64]; 6],
+        };
+        ans.piece_values.copy_from_slice(&values[..NUM_PIECE_VALUES]);
+        for piece in 0..6 {
+            let start = START_POS_OFFSET + piece * 64;
+            let end = END_POS_OFFSET + piece * 64;
+            ans.start_pos_values[piece].copy_from_slice(&values[start..start + 64]);
+            ans.end_pos_values[piece].copy_from_slice(&values[end..end + 64]);
+        }
+        Some(ans)
+    }
+
+    /**
+     * Serialize in format: "CEP1", number of parameters (u32), parameters (i32 each), all little endian
+     */
+    pub fn serialize(&self) -> Vec<u8> {
+        let values = self.to_vec();
+        let mut ans = Vec::with_capacity(8 + 4 * values.len());
+        ans.extend_from_slice(PARAMS_MAGIC);
+        ans.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        values.iter().for_each(|v| ans.extend_from_slice(&v.to_le_bytes()));
+        ans
+    }
+
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 || &data[..4] != PARAMS_MAGIC {
+            return None;
+        }
+        let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        if len != NUM_EVAL_PARAMS || data.len() != 8 + 4 * len {
+            return None;
+        }
+        let values: Vec<i32> = data[8..]
+            .chunks_exact(4)
+            .map(|c| i32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        Self::from_slice(&values)
+    }
+}
@@ -2,26 +2,35 @@ use crate::ChessBoard;
 
 pub mod random_bot;
 pub mod naive_bot;
+pub mod eval_params;
+pub mod nnue;
+pub mod ordering;
+pub mod tt;
+pub mod timer;
+pub mod skill;
+pub mod tournament;
 
 type ChessBot = fn(&ChessBoard) -> i64;
 
-pub struct SyncChessMover {
-    make_move: ChessBot,
-    board: *mut ChessBoard
-}
-
-impl SyncChessMover {
-    pub fn new(bot: ChessBot, cb: *mut ChessBoard) -> Self {
-        SyncChessMover {
-            make_move: bot,
-            board: cb
-        }
-    }
+// A bot playing a game; it may keep state between moves and think during the opponent's turn.
+pub trait ChessMover {
     /**
      * lower 32 bit is the movement, and higher 32 bit is the evaluation.
      */
-    pub fn sync_make_move(&mut self) -> i64 {
-        (self.make_move)(unsafe {&mut (*self.board).clone()})
+    fn make_move(&mut self, board: &ChessBoard) -> i64;
+
+    // a bounded amount of work while the opponent is to move. returns false when there is nothing left to do.
+    fn ponder(&mut self) -> bool {
+        false
     }
+
+    // forget everything learned in the previous game.
+    fn new_game(&mut self) {}
 }
 
+// stateless bots
+impl<F: FnMut(&ChessBoard) -> i64> ChessMover for F {
+    fn make_move(&mut self, board: &ChessBoard) -> i64 {
+        self(board)
+    }
+}
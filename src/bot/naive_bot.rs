@@ -1,9 +1,19 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
 use crate::chessboard::*;
-use crate::get_pos0;
-use crate::get_pos1;
+use crate::chessboard::variant::{Variant, POCKET_PIECES};
+use crate::game::status::Status;
 use super::ChessBot;
+use super::eval_params::*;
+use super::nnue::{Accumulator, Network};
+use super::ordering::{is_quiet, MoveOrdering, MAX_PLY};
+use super::tt::{Bound, TranspositionTable, TtEntry, DEFAULT_TT_SIZE};
+use super::timer::now_ms;
+use super::ChessMover;
 
 const PIECE_VALUES: [i32; 6] = [100, 500, 300, 300, 900, 0];
+// values beyond this are forced checkmates, see `Searcher::search`.
 const FORCE_CHECKMATE_LIMIT: i32 = 1 << 30;
 const START_POS_VALUES: [[i32; 64]; 6] = [
     [
@@ -129,176 +139,1037 @@ const END_POS_VALUES: [[i32; 64]; 6] = [
         -20, -10, -10, -10, -10, -10, -10, -20,
     ]
 ];
+pub const DEFAULT_EVAL_PARAMS: EvalParams = EvalParams {
+    piece_values: PIECE_VALUES,
+    start_pos_values: START_POS_VALUES,
+    end_pos_values: END_POS_VALUES,
+};
+
+// parameters used by the bot, replaced at runtime by `set_eval_params` / `load_eval_params`.
+static EVAL_PARAMS: RwLock<EvalParams> = RwLock::new(DEFAULT_EVAL_PARAMS);
+
+pub fn eval_params() -> EvalParams {
+    *EVAL_PARAMS.read().unwrap()
+}
+
+pub fn set_eval_params(params: EvalParams) {
+    *EVAL_PARAMS.write().unwrap() = params;
+}
+
+// load parameters in the format of `EvalParams::serialize`; returns false and keeps the current ones if malformed.
+pub fn load_eval_params(data: &[u8]) -> bool {
+    match EvalParams::deserialize(data) {
+        Some(params) => {
+            set_eval_params(params);
+            true
+        },
+        None => false,
+    }
+}
+
+pub fn reset_eval_params() {
+    set_eval_params(DEFAULT_EVAL_PARAMS);
+}
+
+// network used instead of the handcrafted evaluation once loaded with `load_nnue` and enabled with `use_nnue`.
+static NNUE: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+static USE_NNUE: AtomicBool = AtomicBool::new(false);
+
+// load a network in the format described in `nnue.rs`; returns false and keeps the current one if malformed.
+pub fn load_nnue(data: &[u8]) -> bool {
+    match Network::deserialize(data) {
+        Some(network) => {
+            *NNUE.write().unwrap() = Some(Arc::new(network));
+            true
+        },
+        None => false,
+    }
+}
+
+// select the network (true) or the handcrafted evaluation (false). returns false if no network is loaded.
+pub fn use_nnue(enable: bool) -> bool {
+    if enable && NNUE.read().unwrap().is_none() {
+        return false;
+    }
+    USE_NNUE.store(enable, Ordering::Relaxed);
+    true
+}
+
+// evaluation backend of one search, fixed when the search starts.
+#[derive(Clone)]
+enum Evaluator {
+    Handcrafted(Box<EvalParams>),
+    // with the handcrafted parameters for positions the network does not support.
+    Nnue(Arc<Network>, Box<EvalParams>),
+}
+
+impl Evaluator {
+    fn current() -> Self {
+        if USE_NNUE.load(Ordering::Relaxed) {
+            if let Some(network) = NNUE.read().unwrap().as_ref() {
+                return Evaluator::Nnue(network.clone(), Box::new(eval_params()));
+            }
+        }
+        Evaluator::Handcrafted(Box::new(eval_params()))
+    }
+
+    fn root(&self, board: &ChessBoard) -> Option<Accumulator> {
+        match self {
+            Evaluator::Handcrafted(_) => None,
+            Evaluator::Nnue(network, _) if network.supports(board) => Some(network.refresh(board)),
+            Evaluator::Nnue(..) => None,
+        }
+    }
+
+    // accumulator of the child reached by `mres` from `board`.
+    fn child(&self, acc: Option<&Accumulator>, board: &ChessBoard, mres: MoveResult) -> Option<Accumulator> {
+        match self {
+            Evaluator::Handcrafted(_) => None,
+            Evaluator::Nnue(network, _) => acc.map(|acc| network.update(acc, board, mres)),
+        }
+    }
+
+    fn evaluate(&self, board: &ChessBoard, acc: Option<&Accumulator>) -> i32 {
+        match self {
+            Evaluator::Handcrafted(params) => evaluate_with(board, params),
+            Evaluator::Nnue(network, params) => {
+                if acc.is_none() && !network.supports(board) {
+                    return evaluate_with(board, params);
+                }
+                let value = match acc {
+                    Some(acc) => network.evaluate(acc, board.player),
+                    None => network.evaluate(&network.refresh(board), board.player),
+                };
+                // same scale as evaluate_with: 1/16 centipawn, white is max
+                if board.player == Player::White { 16 * value } else { -16 * value }
+            }
+        }
+    }
+}
+
+// the orthodox pieces a piece is evaluated as; the archbishop and the chancellor count as both of theirs.
+fn piece_parts(piece: Piece) -> &'static [Piece] {
+    match piece {
+        Piece::Pawn => &[Piece::Pawn],
+        Piece::Rook => &[Piece::Rook],
+        Piece::Knight => &[Piece::Knight],
+        Piece::Bishop => &[Piece::Bishop],
+        Piece::Queen => &[Piece::Queen],
+        Piece::King => &[Piece::King],
+        Piece::Archbishop => &[Piece::Bishop, Piece::Knight],
+        Piece::Chancellor => &[Piece::Rook, Piece::Knight],
+    }
+}
+
+// the square of the 8x8 tables standing for `pos`, as seen by white.
+fn table_square(board: &ChessBoard, pos: usize) -> usize {
+    pos / board.files() * 8 / board.ranks() * 8 + pos % board.files() * 8 / board.files()
+}
+
+// number of pieces of each player, at most 16 so that boards with more pieces count as the start of the game.
+fn num_pieces(board: &ChessBoard) -> (i32, i32) {
+    let count = |player: Player| board.board.iter().flatten().filter(|(p, _)| *p == player).count().min(16) as i32;
+    (count(Player::White), count(Player::Black))
+}
+
 // white is max, black is min
-fn evaluate(board: &ChessBoard) -> i32 {
+pub fn evaluate_with(board: &ChessBoard, params: &EvalParams) -> i32 {
     let mut ans = 0;
-    let mut num_white = 0;
-    let mut num_black = 0;
-    for pos in 0..64 {
-        match board.board[pos] {
-            Some((Player::White, _)) => num_white += 1,
-            Some((Player::Black, _)) => num_black += 1,
-            None => continue,
-        }
-    }
-    
-    for pos in 0..64 {
-        match board.board[pos] {
-            Some((Player::White, piece)) => {
-                ans += 16 * PIECE_VALUES[piece as usize];
-                ans += num_white * START_POS_VALUES[piece as usize][pos] + (16 - num_white) * END_POS_VALUES[piece as usize][pos];
-            },
-            Some((Player::Black, piece)) => {
-                ans -= 16 * PIECE_VALUES[piece as usize];
-                ans -= num_black * START_POS_VALUES[piece as usize][63 - pos] + (16 - num_black) * END_POS_VALUES[piece as usize][63 - pos];
-            },
-            None => continue,
+    let (num_white, num_black) = num_pieces(board);
+    for pos in 0..board.squares() {
+        let Some((player, piece)) = board.board[pos] else { continue };
+        let square = table_square(board, pos);
+        for part in piece_parts(piece) {
+            let part = *part as usize;
+            match player {
+                Player::White => {
+                    ans += 16 * params.piece_values[part];
+                    ans += num_white * params.start_pos_values[part][square] + (16 - num_white) * params.end_pos_values[part][square];
+                },
+                Player::Black => {
+                    ans -= 16 * params.piece_values[part];
+                    ans -= num_black * params.start_pos_values[part][63 - square] + (16 - num_black) * params.end_pos_values[part][63 - square];
+                },
+            }
         }
     }
     ans
 }
-// move, value
-fn search(board: &ChessBoard, depth: usize, mut alpha: i32, mut beta: i32, maximize: bool) -> (Option<MoveRequest>, i32) {
-    if depth == 0 {
-        return (None, evaluate(board));
-    }
-    let mut arr = board.possible_moves();
-    let checked = arr[0];
-    let moves = &mut arr[1..];
-    if checked == MOVES_CHECKED_LEADER && moves.len() == 0 {
-        if board.player == Player::White {
-            return (None, -i32::MAX);
+
+// bonus for a king this many squares away from the hill, in king of the hill.
+const HILL_DISTANCE_BONUS: [i32; 4] = [16 * 300, 16 * 150, 16 * 60, 0];
+// bonus for having given this many checks, in three-check.
+const CHECKS_GIVEN_BONUS: [i32; 4] = [0, 16 * 150, 16 * 400, 16 * 400];
+// in antichess every piece is a liability, the king included.
+const ANTICHESS_PIECE_VALUE: i32 = 16 * 100;
+// on top of the value of a piece in a crazyhouse pocket
+const POCKET_BONUS: i32 = 16 * 20;
+// for each rank the king has advanced, in racing kings.
+const RACE_RANK_BONUS: i32 = 16 * 80;
+
+// adjust an evaluation made with the orthodox rules to the variant of the board, white is max.
+pub fn variant_evaluate(board: &ChessBoard, value: i32) -> i32 {
+    let side = |player: Player| match board.variant() {
+        Variant::Standard | Variant::Atomic | Variant::Horde | Variant::Capablanca | Variant::Gothic => 0,
+        Variant::KingOfTheHill => {
+            let Some(pos) = board.board.iter().position(|c| *c == Some((player, Piece::King))) else { return 0 };
+            // distance to the nearest of the center squares, d4, e4, d5 and e5 on 8x8
+            let distance = |x: usize, n: usize| if x < n / 2 - 1 { n / 2 - 1 - x } else { x.saturating_sub(n / 2) };
+            HILL_DISTANCE_BONUS[distance(pos / board.files(), board.ranks()).max(distance(pos % board.files(), board.files())).min(3)]
+        },
+        Variant::ThreeCheck => CHECKS_GIVEN_BONUS[board.checks_given(player).min(3)],
+        Variant::Antichess => -ANTICHESS_PIECE_VALUE * board.board.iter().flatten().filter(|(p, _)| *p == player).count() as i32,
+        // a piece in hand can be dropped anywhere, so it is worth a bit more than on the board.
+        Variant::Crazyhouse => POCKET_PIECES.iter().map(|piece| {
+            let value: i32 = piece_parts(*piece).iter().map(|part| PIECE_VALUES[*part as usize]).sum();
+            board.pocket(player, *piece) as i32 * (16 * value + POCKET_BONUS)
+        }).sum(),
+        Variant::RacingKings => {
+            let Some(pos) = board.board.iter().position(|c| *c == Some((player, Piece::King))) else { return 0 };
+            RACE_RANK_BONUS * (pos / board.files()) as i32
+        },
+    };
+    let value = if board.variant() == Variant::Antichess { 0 } else { value };
+    value + side(Player::White) - side(Player::Black)
+}
+
+/**
+ * `evaluate_with` is linear in the parameters; this returns the (parameter index, coefficient) pairs such that
+ * evaluate_with(board, params) == sum of coefficient * params.to_vec()[index]. Used by the tuner.
+ */
+pub fn eval_features(board: &ChessBoard) -> Vec<(usize, i32)> {
+    let mut coefficients = [0i32; NUM_EVAL_PARAMS];
+    let (num_white, num_black) = num_pieces(board);
+    for pos in 0..board.squares() {
+        let Some((player, piece)) = board.board[pos] else { continue };
+        let square = table_square(board, pos);
+        for part in piece_parts(piece) {
+            let part = *part as usize;
+            match player {
+                Player::White => {
+                    coefficients[part] += 16;
+                    coefficients[START_POS_OFFSET + part * 64 + square] += num_white;
+                    coefficients[END_POS_OFFSET + part * 64 + square] += 16 - num_white;
+                },
+                Player::Black => {
+                    coefficients[part] -= 16;
+                    coefficients[START_POS_OFFSET + part * 64 + 63 - square] -= num_black;
+                    coefficients[END_POS_OFFSET + part * 64 + 63 - square] -= 16 - num_black;
+                },
+            }
+        }
+    }
+    coefficients.iter().enumerate().filter(|(_, c)| **c != 0).map(|(i, c)| (i, *c)).collect()
+}
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    // number of beta cutoffs, and how many of them were caused by the first move searched.
+    pub cutoffs: u64,
+    pub first_move_cutoffs: u64,
+    // number of times the root was searched again after falling outside the aspiration window.
+    pub aspiration_researches: u64,
+    // number of nodes decided by the transposition table without searching them.
+    pub tt_cutoffs: u64,
+}
+
+impl SearchStats {
+    // the fraction of cutoffs happening on the first move, a measure of move ordering quality.
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        if self.cutoffs == 0 {
+            0.0
         } else {
-            return (None, i32::MAX);
-        }
-    }
-    if moves.len() == 0 {
-        return (None, 0);
-    }
-    moves.sort_unstable_by_key(|f| {
-        let mut ans = 0;
-        let pos0 = board.board[get_pos0!(*f)];
-        let pos1 = board.board[get_pos1!(*f)];
-        if let Some((_, piece)) = pos1 {
-            ans += 2 * PIECE_VALUES[piece as usize];
-            ans -= PIECE_VALUES[pos0.unwrap().1 as usize];
-        }
-        -ans
-    });
-
-    if maximize {
-        let mut maxmove = moves[0];
-        let mut maxval = -i32::MAX;
-        
-        for m in moves {
-            let mut t = board.clone();
-            t.do_move(*m);
-            let (_, value) = search(&mut t, depth - 1, alpha, beta, false);
-            let value = if value > FORCE_CHECKMATE_LIMIT {
-                value - 1
-            } else if value < -FORCE_CHECKMATE_LIMIT {
-                value + 1
+            self.first_move_cutoffs as f64 / self.cutoffs as f64
+        }
+    }
+}
+
+// Score of a position, white is max.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Score {
+    // in 1/16 centipawn, as evaluate
+    Eval(i32),
+    // forced checkmate in this many moves, positive if white mates and negative if black mates.
+    Mate(i32),
+}
+
+impl Score {
+    // `value` is a search value from the perspective of `player`.
+    fn from_value(value: i32, player: Player) -> Self {
+        let sign = if player == Player::White { 1 } else { -1 };
+        if value > FORCE_CHECKMATE_LIMIT {
+            Score::Mate(sign * (i32::MAX - value + 1) / 2)
+        } else if value < -FORCE_CHECKMATE_LIMIT {
+            Score::Mate(-sign * (i32::MAX + value + 1) / 2)
+        } else {
+            Score::Eval(sign * value)
+        }
+    }
+
+    // the raw search value, white is max.
+    pub fn to_value(&self) -> i32 {
+        match *self {
+            Score::Eval(value) => value,
+            Score::Mate(n) if n > 0 => i32::MAX - (2 * n - 1),
+            Score::Mate(n) => -i32::MAX + (-2 * n),
+        }
+    }
+}
+
+impl std::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Score::Eval(value) => write!(f, "{:+.2}", *value as f64 / 1600.0),
+            Score::Mate(n) => write!(f, "mate in {}{}", if *n < 0 { "-" } else { "" }, n.abs()),
+        }
+    }
+}
+
+// a root move with its score and principal variation, starting with the move itself.
+#[derive(Debug, Clone)]
+pub struct PvLine {
+    pub score: Score,
+    pub moves: Vec<MoveRequest>,
+}
+
+pub struct SearchResult {
+    pub best_move: MoveRequest,
+    pub score: Score,
+    // depth of the last completed iteration
+    pub depth: usize,
+    // the best `multi_pv` root moves, best first.
+    pub lines: Vec<PvLine>,
+    pub stats: SearchStats,
+}
+
+pub const NULL_MOVE_FLAG: u32 = 1;
+pub const LATE_MOVE_REDUCTIONS_FLAG: u32 = 2;
+pub const CHECK_EXTENSIONS_FLAG: u32 = 4;
+pub const FUTILITY_PRUNING_FLAG: u32 = 8;
+pub const ALL_FLAGS: u32 = NULL_MOVE_FLAG | LATE_MOVE_REDUCTIONS_FLAG | CHECK_EXTENSIONS_FLAG | FUTILITY_PRUNING_FLAG;
+
+// Search depth and which selectivity techniques are enabled; turning them off gives weaker bots.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    pub max_depth: usize,
+    pub null_move: bool,
+    pub late_move_reductions: bool,
+    pub check_extensions: bool,
+    pub futility_pruning: bool,
+    // number of best root moves to report with exact scores, see `SearchResult::lines`.
+    pub multi_pv: usize,
+    // in milliseconds; the search stops with the last completed iteration when it runs out of time.
+    pub time_limit: Option<u32>,
+    // number of threads of the Lazy SMP search, ignored in wasm. with 1 thread the search is deterministic.
+    pub threads: usize,
+    // the search stops with the last completed iteration after this many nodes.
+    pub max_nodes: Option<u64>,
+    // maximum random error added to every evaluation, in 1/16 centipawn; for weaker play.
+    pub eval_noise: i32,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self::from_flags(5, ALL_FLAGS)
+    }
+}
+
+impl SearchOptions {
+    pub fn from_flags(max_depth: usize, flags: u32) -> Self {
+        SearchOptions {
+            max_depth,
+            null_move: false,
+            late_move_reductions: false,
+            check_extensions: false,
+            futility_pruning: false,
+            multi_pv: 1,
+            time_limit: None,
+            threads: 1,
+            max_nodes: None,
+            eval_noise: 0,
+        }.with_flags(flags)
+    }
+
+    // the same options with the selectivity techniques of `flags` enabled and the others disabled.
+    pub fn with_flags(self, flags: u32) -> Self {
+        SearchOptions {
+            null_move: flags & NULL_MOVE_FLAG != 0,
+            late_move_reductions: flags & LATE_MOVE_REDUCTIONS_FLAG != 0,
+            check_extensions: flags & CHECK_EXTENSIONS_FLAG != 0,
+            futility_pruning: flags & FUTILITY_PRUNING_FLAG != 0,
+            ..self
+        }
+    }
+}
+
+const NULL_MOVE_REDUCTION: usize = 2;
+// half width of the initial aspiration window, in 1/16 centipawn
+const ASPIRATION_WINDOW: i32 = 16 * 25;
+// in 1/16 centipawn, as evaluate
+const FUTILITY_MARGIN: i32 = 16 * 200;
+// the clock is only read every this many nodes.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+// milliseconds of work per call of NaiveBot::ponder, short enough to keep the page responsive.
+const PONDER_STEP_MS: f64 = 30.0;
+
+// whether the player has pieces other than pawns and king; null move is unsound in pawn endgames due to zugzwang.
+fn has_non_pawn_material(board: &ChessBoard, player: Player) -> bool {
+    board.board.iter().any(|cell| matches!(cell, Some((p, piece)) if *p == player && *piece != Piece::Pawn && *piece != Piece::King))
+}
+
+// value of a finished game for the player to move, `ply` plies from the root; None is a draw.
+fn game_over_value(board: &ChessBoard, winner: Option<Player>, ply: usize) -> i32 {
+    match winner {
+        Some(player) if player == board.player => i32::MAX - ply as i32,
+        Some(_) => -i32::MAX + ply as i32,
+        None => 0,
+    }
+}
+
+// mate values are counted from the root in the search, and from the position itself in the transposition table.
+fn value_to_tt(value: i32, ply: usize) -> i32 {
+    if value > FORCE_CHECKMATE_LIMIT {
+        value + ply as i32
+    } else if value < -FORCE_CHECKMATE_LIMIT {
+        value - ply as i32
+    } else {
+        value
+    }
+}
+
+fn value_from_tt(value: i32, ply: usize) -> i32 {
+    if value > FORCE_CHECKMATE_LIMIT {
+        value - ply as i32
+    } else if value < -FORCE_CHECKMATE_LIMIT {
+        value + ply as i32
+    } else {
+        value
+    }
+}
+
+// uniform in [-max, max], a function of `key` only.
+fn noise(key: u64, max: i32) -> i32 {
+    // splitmix64 finalizer
+    let mut x = key.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^= x >> 31;
+    (x % (2 * max as u64 + 1)) as i32 - max
+}
+
+#[derive(Clone)]
+struct RootMove {
+    mov: MoveRequest,
+    value: i32,
+    pv: Vec<MoveRequest>,
+}
+
+// State of iterative deepening at a root, so that it can be resumed later, e.g. after a ponder hit.
+#[derive(Clone)]
+struct RootSearch {
+    board: ChessBoard,
+    root_acc: Option<Accumulator>,
+    root_moves: Vec<RootMove>,
+    value: i32,
+    // depth of the last completed iteration, 0 if only the static evaluation of the root moves is known.
+    depth: usize,
+    // the next iteration, if it was cut short, to go on from where it stopped.
+    iteration: Option<Iteration>,
+}
+
+// An iteration of iterative deepening at a root in progress.
+#[derive(Clone)]
+struct Iteration {
+    // the values of the first `next` moves are from this iteration, the others from the last completed one.
+    root_moves: Vec<RootMove>,
+    next: usize,
+    // best values so far, at most multi_pv of them, best first.
+    top: Vec<i32>,
+    // the aspiration window and how much it widens on the next failure.
+    alpha: i32,
+    beta: i32,
+    delta: i32,
+}
+
+struct Searcher {
+    options: SearchOptions,
+    evaluator: Evaluator,
+    // shared with the helper threads of a parallel search
+    tt: Arc<TranspositionTable>,
+    ordering: MoveOrdering,
+    stats: SearchStats,
+    // triangular table of principal variations, pv[ply][ply..pv_len[ply]] being the one of the node at `ply`.
+    pv: [[MoveRequest; MAX_PLY + 1]; MAX_PLY + 1],
+    pv_len: [usize; MAX_PLY + 1],
+    // in milliseconds since the epoch, see timer::now_ms
+    deadline: Option<f64>,
+    // set when the deadline passed, the values returned afterwards are meaningless.
+    stopped: bool,
+    // set by the main thread to stop the helper threads of a parallel search.
+    abort: Option<Arc<AtomicBool>>,
+    // the evaluation noise of a position is a function of its hash and this seed, so that it is stable within a search.
+    noise_seed: u64,
+}
+
+impl Searcher {
+    fn new(options: SearchOptions) -> Self {
+        Searcher {
+            options,
+            evaluator: Evaluator::current(),
+            tt: Arc::new(TranspositionTable::new(DEFAULT_TT_SIZE)),
+            ordering: MoveOrdering::new(),
+            stats: SearchStats::default(),
+            pv: [[0; MAX_PLY + 1]; MAX_PLY + 1],
+            pv_len: [0; MAX_PLY + 1],
+            deadline: None,
+            stopped: false,
+            abort: None,
+            noise_seed: 0,
+        }
+    }
+
+    // a searcher for a helper thread of a parallel search, sharing the transposition table.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn helper(&self, abort: Arc<AtomicBool>) -> Self {
+        Searcher {
+            options: self.options,
+            evaluator: self.evaluator.clone(),
+            tt: self.tt.clone(),
+            ordering: MoveOrdering::new(),
+            stats: SearchStats::default(),
+            pv: [[0; MAX_PLY + 1]; MAX_PLY + 1],
+            pv_len: [0; MAX_PLY + 1],
+            deadline: self.deadline,
+            stopped: false,
+            abort: Some(abort),
+            noise_seed: self.noise_seed,
+        }
+    }
+
+    // start searching for a new move; the transposition table and history are kept from previous searches.
+    fn new_search(&mut self, options: SearchOptions) {
+        self.options = options;
+        self.evaluator = Evaluator::current();
+        self.ordering.new_search();
+        self.stats = SearchStats::default();
+        self.deadline = options.time_limit.map(|ms| now_ms() + ms as f64);
+        self.stopped = false;
+        self.noise_seed = if options.eval_noise > 0 { rand::random() } else { 0 };
+    }
+
+    // `m` raised alpha at `ply`: its variation becomes `m` followed by the child's.
+    fn update_pv(&mut self, ply: usize, m: MoveRequest) {
+        self.pv[ply][ply] = m;
+        let child_len = self.pv_len[ply + 1].max(ply + 1);
+        for k in ply + 1..child_len {
+            self.pv[ply][k] = self.pv[ply + 1][k];
+        }
+        self.pv_len[ply] = child_len;
+    }
+
+    // evaluation from the perspective of the player to move
+    fn evaluate(&self, board: &ChessBoard, acc: Option<&Accumulator>) -> i32 {
+        let mut value = if board.variant() == Variant::Antichess {
+            variant_evaluate(board, 0)
+        } else {
+            variant_evaluate(board, self.evaluator.evaluate(board, acc))
+        };
+        if self.options.eval_noise > 0 {
+            value += noise(board.hash() ^ self.noise_seed, self.options.eval_noise);
+        }
+        if board.player == Player::White { value } else { -value }
+    }
+
+    /**
+     * Negamax principal variation search, returns the value from the perspective of the player to move.
+     * Being checkmated at `ply` plies from the root is valued -i32::MAX + ply, and so are the other ways to lose.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn search(&mut self, board: &ChessBoard, acc: Option<&Accumulator>, mut depth: usize, ply: usize, prev: Option<MoveRequest>, mut alpha: i32, beta: i32) -> i32 {
+        self.stats.nodes += 1;
+        if self.options.max_nodes.is_some_and(|n| self.stats.nodes >= n) {
+            self.stopped = true;
+        }
+        if self.stats.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+            && (self.deadline.is_some_and(|d| now_ms() >= d) || self.abort.as_ref().is_some_and(|a| a.load(Ordering::Relaxed))) {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        self.pv_len[ply] = ply;
+        if let Some(winner) = board.variant_outcome() {
+            return game_over_value(board, winner, ply);
+        }
+        if ply >= MAX_PLY {
+            return self.evaluate(board, acc);
+        }
+        let in_check = board.is_checked();
+        if in_check && self.options.check_extensions {
+            depth += 1;
+        }
+        if depth == 0 {
+            return self.evaluate(board, acc);
+        }
+        let pv_node = alpha + 1 < beta;
+        // the noise seed is part of the key, as values found with other noise do not hold.
+        let key = board.hash() ^ self.noise_seed;
+        let entry = self.tt.probe(key);
+        // an entry searched deep enough decides the node, except on the principal variation that it would cut short.
+        if let Some(entry) = entry.filter(|e| !pv_node && e.depth >= depth) {
+            let value = value_from_tt(entry.value, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => value >= beta,
+                Bound::Upper => value <= alpha,
+            };
+            if usable {
+                self.stats.tt_cutoffs += 1;
+                return value;
+            }
+        }
+        let mut arr = board.possible_moves();
+        let moves = &mut arr[1..];
+        if moves.is_empty() {
+            return game_over_value(board, board.no_moves_winner(), ply);
+        }
+        let static_eval = if in_check || pv_node { None } else { Some(self.evaluate(board, acc)) };
+
+        // null move pruning: if passing still fails high, a real move will too.
+        if self.options.null_move && depth > NULL_MOVE_REDUCTION && prev.is_some() && beta.abs() < FORCE_CHECKMATE_LIMIT
+            && static_eval.is_some_and(|e| e >= beta) && has_non_pawn_material(board, board.player)
+            && board.variant() != Variant::Antichess {
+            let mut t = *board;
+            t.do_null_move();
+            let value = -self.search(&t, acc, depth - 1 - NULL_MOVE_REDUCTION, ply + 1, None, -beta, -beta + 1);
+            if value >= beta {
+                return beta;
+            }
+        }
+        // futility pruning: at the frontier, quiet moves can not raise a hopeless static evaluation above alpha.
+        let futile = self.options.futility_pruning && depth == 1 && alpha.abs() < FORCE_CHECKMATE_LIMIT
+            && static_eval.is_some_and(|e| e + FUTILITY_MARGIN <= alpha);
+
+        self.ordering.sort(board, moves, entry.map(|e| e.best_move), ply, prev);
+
+        let original_alpha = alpha;
+        let mut best_move = moves[0];
+        let mut best_value = -i32::MAX;
+        for (i, m) in moves.iter().enumerate() {
+            let quiet = is_quiet(board, *m);
+            let mut t = *board;
+            let mres = t.do_move(*m);
+            let gives_check = (futile || self.options.late_move_reductions) && quiet && t.is_checked();
+            if futile && i > 0 && quiet && !gives_check {
+                continue;
+            }
+            let child_acc = self.evaluator.child(acc, board, mres);
+            let value = if i == 0 {
+                -self.search(&t, child_acc.as_ref(), depth - 1, ply + 1, Some(*m), -beta, -alpha)
             } else {
+                // late move reductions: quiet moves ordered late are searched shallower first.
+                let reduction = if self.options.late_move_reductions && depth >= 3 && i >= 3 && quiet && !in_check && !gives_check {
+                    if i >= 6 { 2 } else { 1 }
+                } else {
+                    0
+                };
+                let reduced_depth = (depth - 1).saturating_sub(reduction);
+                let mut value = -self.search(&t, child_acc.as_ref(), reduced_depth, ply + 1, Some(*m), -alpha - 1, -alpha);
+                if value > alpha && reduction > 0 {
+                    value = -self.search(&t, child_acc.as_ref(), depth - 1, ply + 1, Some(*m), -alpha - 1, -alpha);
+                }
+                if value > alpha && value < beta {
+                    value = -self.search(&t, child_acc.as_ref(), depth - 1, ply + 1, Some(*m), -beta, -alpha);
+                }
                 value
             };
-            // board.undo_move(mres);
-            if value > maxval {
-                maxval = value;
-                maxmove = *m;
+            if self.stopped {
+                return 0;
+            }
+            if value > best_value {
+                best_value = value;
+                best_move = *m;
             }
-            if maxval > alpha {
-                alpha = maxval;
+            if best_value > alpha {
+                alpha = best_value;
+                self.update_pv(ply, *m);
             }
-            if maxval >= beta {
+            if alpha >= beta {
+                self.stats.cutoffs += 1;
+                if i == 0 {
+                    self.stats.first_move_cutoffs += 1;
+                }
+                if quiet {
+                    self.ordering.update(board, *m, depth, ply, prev);
+                }
                 break;
             }
         }
-        return (Some(maxmove), maxval);
-    } else {
-        let mut minmove = moves[0];
-        let mut minval = i32::MAX;
-        for m in moves {
-            let mut t = board.clone();
-            t.do_move(*m);
-            let (_, value) = search(&mut t, depth - 1, alpha, beta, true);
-            let value = if value > FORCE_CHECKMATE_LIMIT {
-                value - 1
-            } else if value < -FORCE_CHECKMATE_LIMIT {
-                value + 1
+        let bound = if best_value >= beta {
+            Bound::Lower
+        } else if best_value > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(key, TtEntry { best_move, value: value_to_tt(best_value, ply), bound, depth });
+        best_value
+    }
+
+    /**
+     * Search all root moves within (alpha, beta). The first `multi_pv` moves are searched with the full window, the
+     * others with a null window to check whether they beat the `multi_pv`-th best so far.
+     * `moves` is updated with the value of each move (a bound if outside the window) and reordered best first.
+     */
+    fn search_root_moves(&mut self, board: &ChessBoard, root_acc: Option<&Accumulator>, depth: usize, it: &mut Iteration) -> i32 {
+        let multi_pv = self.options.multi_pv.max(1);
+        let beta = it.beta;
+        while it.next < it.root_moves.len() {
+            let i = it.next;
+            let alpha = if it.top.len() < multi_pv { it.alpha } else { it.alpha.max(it.top[multi_pv - 1]) };
+            let mov = it.root_moves[i].mov;
+            let mut new_board = *board;
+            let mres = new_board.do_move(mov);
+            let acc = self.evaluator.child(root_acc, board, mres);
+            let value = if i < multi_pv {
+                -self.search(&new_board, acc.as_ref(), depth, 1, Some(mov), -beta, -alpha)
             } else {
-                value
+                let value = -self.search(&new_board, acc.as_ref(), depth, 1, Some(mov), -alpha - 1, -alpha);
+                if value > alpha && value < beta {
+                    -self.search(&new_board, acc.as_ref(), depth, 1, Some(mov), -beta, -alpha)
+                } else {
+                    value
+                }
             };
-            if value < minval {
-                minval = value;
-                minmove = *m;
+            if self.stopped {
+                return 0;
             }
-            if minval < beta {
-                beta = minval;
+            let root_move = &mut it.root_moves[i];
+            root_move.value = value;
+            root_move.pv.clear();
+            root_move.pv.push(mov);
+            if value > alpha {
+                root_move.pv.extend_from_slice(&self.pv[1][1..self.pv_len[1].max(1)]);
             }
-            if minval <= alpha {
+            let k = it.top.partition_point(|v| *v >= value);
+            it.top.insert(k, value);
+            it.top.truncate(multi_pv);
+            it.next += 1;
+            if it.top[0] >= beta {
                 break;
             }
+        }
+        it.root_moves.sort_by_key(|m| -m.value);
+        it.top[0]
+    }
+
+    fn new_root(&mut self, board: &ChessBoard) -> RootSearch {
+        let root_acc = self.evaluator.root(board);
+        let mut moves = board.possible_moves();
+        moves.remove(0);
+        let mut root_moves: Vec<RootMove> = vec![];
+        moves.into_iter().for_each(|mov| {
+            let mut new_board = *board;
+            let mres = new_board.do_move(mov);
+            let acc = self.evaluator.child(root_acc.as_ref(), board, mres);
+            let value = match new_board.variant_outcome() {
+                Some(winner) => -game_over_value(&new_board, winner, 1),
+                None => -self.evaluate(&new_board, acc.as_ref()),
+            };
+            root_moves.push(RootMove { mov, value, pv: vec![mov] });
+        });
+        root_moves.sort_by_key(|m| -m.value);
+        let value = root_moves[0].value;
+        RootSearch { board: *board, root_acc, root_moves, value, depth: 0, iteration: None }
+    }
+
+    // whether iterative deepening at `root` has reached the maximum depth.
+    fn finished(&self, root: &RootSearch) -> bool {
+        root.depth + 1 >= self.options.max_depth
+    }
+
+    /**
+     * One more iteration of iterative deepening, with an aspiration window around the value of the previous one.
+     * Returns false if the time ran out, leaving the result of `root` as it was and the iteration to be continued.
+     */
+    fn iterate(&mut self, root: &mut RootSearch) -> bool {
+        let depth = root.depth + 1;
+        let mut it = root.iteration.take().unwrap_or_else(|| {
+            let value = root.value;
+            // the window is only meaningful for the best move.
+            let (alpha, beta) = if depth > 1 && value.abs() < FORCE_CHECKMATE_LIMIT && self.options.multi_pv <= 1 {
+                (value.saturating_sub(ASPIRATION_WINDOW).max(-i32::MAX), value.saturating_add(ASPIRATION_WINDOW))
+            } else {
+                (-i32::MAX, i32::MAX)
+            };
+            Iteration { root_moves: root.root_moves.clone(), next: 0, top: vec![], alpha, beta, delta: ASPIRATION_WINDOW }
+        });
+        let value = loop {
+            let value = self.search_root_moves(&root.board, root.root_acc.as_ref(), depth, &mut it);
+            if self.stopped {
+                root.iteration = Some(it);
+                return false;
+            }
+            if value <= it.alpha && it.alpha > -i32::MAX {
+                self.stats.aspiration_researches += 1;
+                it.delta = it.delta.saturating_mul(2);
+                it.alpha = value.saturating_sub(it.delta).max(-i32::MAX);
+            } else if value >= it.beta && it.beta < i32::MAX {
+                self.stats.aspiration_researches += 1;
+                it.delta = it.delta.saturating_mul(2);
+                it.beta = value.saturating_add(it.delta);
+            } else {
+                break value;
+            }
+            it.next = 0;
+            it.top.clear();
+        };
+        root.root_moves = it.root_moves;
+        root.value = value;
+        root.depth = depth;
+        true
+    }
+
+    // iterate until the maximum depth or the deadline.
+    fn deepen(&mut self, root: &mut RootSearch) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.options.threads > 1 {
+            self.deepen_parallel(root);
+            return;
+        }
+        while !self.finished(root) && self.iterate(root) {}
+    }
+
+    /**
+     * Lazy SMP: helper threads run the same iterative deepening on their own, filling the shared transposition table
+     * with values and moves that cut off or reorder the search of the main thread. Odd helpers start one iteration
+     * deeper so that the threads diverge. Only the main thread's result is used, helpers are stopped when it is done.
+     */
+    #[cfg(not(target_arch = "wasm32"))]
+    fn deepen_parallel(&mut self, root: &mut RootSearch) {
+        let abort = Arc::new(AtomicBool::new(false));
+        std::thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.options.threads).map(|i| {
+                let mut helper = Box::new(self.helper(abort.clone()));
+                let mut helper_root = root.clone();
+                // the iteration left by pondering is for the main thread to finish.
+                helper_root.iteration = None;
+                helper_root.depth += i % 2;
+                scope.spawn(move || {
+                    while !helper.finished(&helper_root) && helper.iterate(&mut helper_root) {}
+                    helper.stats
+                })
+            }).collect();
+            while !self.finished(root) && self.iterate(root) {}
+            abort.store(true, Ordering::Relaxed);
+            for helper in helpers {
+                let stats = helper.join().unwrap();
+                self.stats.nodes += stats.nodes;
+            }
+        });
+    }
 
+    fn result(&self, root: &RootSearch) -> SearchResult {
+        let player = root.board.player;
+        let lines: Vec<PvLine> = root.root_moves.iter().take(self.options.multi_pv.max(1)).map(|m| PvLine {
+            score: Score::from_value(m.value, player),
+            moves: m.pv.clone(),
+        }).collect();
+        SearchResult {
+            best_move: root.root_moves[0].mov,
+            score: lines[0].score,
+            depth: root.depth + 1,
+            lines,
+            stats: self.stats,
         }
-        return (Some(minmove), minval);
     }
 
+    fn search_root(&mut self, board: &ChessBoard) -> SearchResult {
+        let mut root = self.new_root(board);
+        self.deepen(&mut root);
+        self.result(&root)
+    }
+}
+
+// search `board` with the current evaluation.
+pub fn analyze(board: &ChessBoard, options: SearchOptions) -> SearchResult {
+    let mut searcher = Searcher::new(options);
+    searcher.new_search(options);
+    searcher.search_root(board)
+}
+
+/**
+ * lower 32 bit is the movement, and higher 32 bit is the evaluation: either in 1/16 centipawn, or beyond ±(1 << 30)
+ * for a forced mate, in which case i32::MAX - |evaluation| is the number of plies to the mate.
+ */
+fn encode_result(result: &SearchResult) -> i64 {
+    ((result.score.to_value() as i64) << 32) | result.best_move as i64
 }
 
 fn naive_bot(board: &ChessBoard) -> i64 {
-    let max_depth = 5;
-    let maximize = board.player == Player::White;
-    // iterative deepening
-    let mut moves = board.possible_moves();
-    moves.remove(0);
-    let mut evaluated_moves: Vec<(MoveRequest, i32)> = vec![];
-    moves.into_iter().for_each(|mov| {
-        let mut new_board = board.clone();
-        new_board.do_move(mov);
-        evaluated_moves.push((mov, if maximize { 1 } else { -1 } * evaluate(&new_board)));
-    });
-
-    for depth in 1..max_depth {
-        let mut alpha = -i32::MAX;
-        let mut beta = i32::MAX;
-        let mut new_evaluated_moves: Vec<(MoveRequest, i32)> = vec![];
-        evaluated_moves.sort_unstable_by_key(|m| {-m.1});
-        if maximize {
-            let mut maxval = -i32::MAX;
-            for (mov, _) in evaluated_moves {
-                let mut new_board = board.clone();
-                new_board.do_move(mov);
-                let (_, value) = search(&new_board, depth, alpha, beta, false);
-                if value > maxval {
-                    maxval = value;
-                }
-                if maxval > alpha {
-                    alpha = maxval;
-                }
-                new_evaluated_moves.push((mov, value));
-            }
-        } else {
-            let mut minval = i32::MAX;
-            for (mov, _) in evaluated_moves {
-                let mut new_board = board.clone();
-                new_board.do_move(mov);
-                let (_, value) = search(&new_board, depth, alpha, beta, true);
-                if value < minval {
-                    minval = value;
-                }
-                if minval < beta {
-                    beta = minval;
-                }
-                new_evaluated_moves.push((mov, -value));
+    encode_result(&analyze(board, SearchOptions::default()))
+}
+pub static NAIVE_BOT: ChessBot = naive_bot;
+
+/**
+ * A naive bot for a whole game: the transposition table and history survive between moves, and while the opponent
+ * thinks it can ponder on the position after the reply it expects.
+ */
+pub struct NaiveBot {
+    options: SearchOptions,
+    searcher: Box<Searcher>,
+    // search of the position after our last move and the expected reply.
+    ponder: Option<RootSearch>,
+    pub ponder_hits: u32,
+    pub ponder_misses: u32,
+}
+
+impl NaiveBot {
+    pub fn new(options: SearchOptions) -> Self {
+        NaiveBot {
+            options,
+            searcher: Box::new(Searcher::new(options)),
+            ponder: None,
+            ponder_hits: 0,
+            ponder_misses: 0,
+        }
+    }
+
+    // used from the next search on.
+    pub fn set_options(&mut self, options: SearchOptions) {
+        self.options = options;
+    }
+
+    /**
+     * Best move for `board`, which must have legal moves. If it is the position pondered on, that search is continued,
+     * otherwise it is discarded but its transposition table entries are still used.
+     */
+    pub fn search(&mut self, board: &ChessBoard) -> SearchResult {
+        self.searcher.new_search(self.options);
+        let mut root = match self.ponder.take() {
+            Some(root) if root.board.hash() == board.hash() && root.board.board == board.board => {
+                self.ponder_hits += 1;
+                root
+            },
+            Some(_) => {
+                self.ponder_misses += 1;
+                self.searcher.new_root(board)
+            },
+            None => self.searcher.new_root(board),
+        };
+        self.searcher.deepen(&mut root);
+        let result = self.searcher.result(&root);
+        self.ponder = self.ponder_root(board, &result);
+        result
+    }
+
+    fn ponder_root(&mut self, board: &ChessBoard, result: &SearchResult) -> Option<RootSearch> {
+        let pv = &result.lines[0].moves;
+        if pv.len() < 2 {
+            return None;
+        }
+        let mut t = *board;
+        t.do_move(pv[0]);
+        t.do_move(pv[1]);
+        // no pondering on a game that ends there, however it ends.
+        if Status::of_position(&t, 1).is_over() {
+            return None;
+        }
+        Some(self.searcher.new_root(&t))
+    }
+
+    /**
+     * Search the expected position for about PONDER_STEP_MS, going on with the iteration the last call or search cut
+     * short. returns false when there is nothing to ponder on.
+     */
+    pub fn ponder(&mut self) -> bool {
+        let Some(root) = self.ponder.as_mut() else { return false };
+        if self.searcher.finished(root) {
+            return false;
+        }
+        self.searcher.deadline = Some(now_ms() + PONDER_STEP_MS);
+        self.searcher.stopped = false;
+        while !self.searcher.finished(root) && self.searcher.iterate(root) {}
+        !self.searcher.finished(root)
+    }
+
+    pub fn new_game(&mut self) {
+        self.searcher.tt.clear();
+        self.searcher.ordering = MoveOrdering::new();
+        self.ponder = None;
+    }
+}
+
+impl ChessMover for NaiveBot {
+    fn make_move(&mut self, board: &ChessBoard) -> i64 {
+        encode_result(&self.search(board))
+    }
+
+    fn ponder(&mut self) -> bool {
+        NaiveBot::ponder(self)
+    }
+
+    fn new_game(&mut self) {
+        NaiveBot::new_game(self)
+    }
+}
+
+// a naive bot searching with the given options, e.g. shallower or without pruning for weaker play.
+pub fn naive_bot_with_options(options: SearchOptions) -> NaiveBot {
+    NaiveBot::new(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mate_scores_round_trip() {
+        for n in 1..=100 {
+            for score in [Score::Mate(n), Score::Mate(-n)] {
+                let value = score.to_value();
+                assert!(value.abs() > FORCE_CHECKMATE_LIMIT);
+                assert_eq!(Score::from_value(value, Player::White), score);
+                assert_eq!(Score::from_value(-value, Player::Black), score);
             }
+            // mating sooner is better, being mated later is better.
+            assert!(Score::Mate(n).to_value() > Score::Mate(n + 1).to_value());
+            assert!(Score::Mate(-n).to_value() < Score::Mate(-n - 1).to_value());
         }
-        evaluated_moves = new_evaluated_moves;
-        
+        // a mate `ply` plies from the root is in (ply + 1) / 2 moves.
+        for ply in 1..=20 {
+            assert_eq!(Score::from_value(i32::MAX - ply, Player::White), Score::Mate((ply + 1) / 2));
+            assert_eq!(Score::from_value(-(i32::MAX - ply), Player::White), Score::Mate(-((ply + 1) / 2)));
+            assert_eq!(Score::from_value(i32::MAX - ply, Player::Black), Score::Mate(-((ply + 1) / 2)));
+        }
+        for value in [0, 1, -1, 1600, -FORCE_CHECKMATE_LIMIT, FORCE_CHECKMATE_LIMIT] {
+            assert_eq!(Score::from_value(value, Player::White), Score::Eval(value));
+            assert_eq!(Score::from_value(value, Player::Black), Score::Eval(-value));
+            assert_eq!(Score::Eval(value).to_value(), value);
+        }
+        assert_eq!(Score::Mate(3).to_string(), "mate in 3");
+        assert_eq!(Score::Mate(-2).to_string(), "mate in -2");
+        assert_eq!(Score::Eval(-2400).to_string(), "-1.50");
+    }
+
+    #[test]
+    fn table_keeps_mate_distances() {
+        // 1. Ra6 bxa6 2. b7#
+        let board = ChessBoard::from_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1").unwrap();
+        let mut bot = NaiveBot::new(SearchOptions { max_depth: 5, ..SearchOptions::default() });
+        for _ in 0..2 {
+            let result = bot.search(&board);
+            assert_eq!(result.score, Score::Mate(2));
+            assert_eq!(board.mreq_to_uci(result.best_move), "a1a6");
+        }
+    }
+
+    #[test]
+    fn single_thread_search_is_deterministic() {
+        let board = ChessBoard::from_fen("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10").unwrap();
+        let options = SearchOptions { max_depth: 4, ..SearchOptions::default() };
+        let (a, b) = (analyze(&board, options), analyze(&board, options));
+        assert_eq!((a.best_move, a.score, a.stats.nodes), (b.best_move, b.score, b.stats.nodes));
+        assert!(a.stats.tt_cutoffs > 0);
     }
-    evaluated_moves.sort_unstable_by_key(|m| {-m.1});
-    
-    // let (m, eval) = search(board, 5, -i32::MAX, i32::MAX, board.player == Player::White);
-    // return 
-    (((if maximize {evaluated_moves[0].1} else {-evaluated_moves[0].1}) as i64) << 32) | evaluated_moves[0].0 as i64
 }
-pub static NAIVE_BOT: ChessBot = naive_bot;
\ No newline at end of file
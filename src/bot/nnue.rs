@@ -0,0 +1,302 @@
+// Efficiently updatable neural network evaluation.
+//
+// The network is a perspective net with the simple 768 feature set: (side relative to the perspective, piece, square),
+// one hidden layer of HIDDEN_SIZE neurons per perspective with clipped ReLU, and a single output neuron fed by the
+// side to move's accumulator followed by the other one. Inference is done entirely in integers.
+//
+// Network file format (little endian): "CNN1", hidden size (u32), feature weights (i16, [768][HIDDEN_SIZE]),
+// feature biases (i16, [HIDDEN_SIZE]), output weights (i16, [2 * HIDDEN_SIZE]), output bias (i32).
+// Weights are quantized by QA for the hidden layer and QB for the output layer, the output bias by QA * QB.
+
+use crate::chessboard::*;
+use crate::get_pos0;
+use crate::get_pos1;
+use crate::get_promote;
+
+pub const HIDDEN_SIZE: usize = 256;
+pub const NUM_FEATURES: usize = 768;
+const QA: i32 = 255;
+const QB: i32 = 64;
+// output of the network is scaled to centipawns.
+const SCALE: i32 = 400;
+
+const NETWORK_MAGIC: &[u8; 4] = b"CNN1";
+
+pub struct Network {
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+// hidden layer values from white's and black's perspective.
+#[derive(Clone)]
+pub struct Accumulator {
+    white: [i16; HIDDEN_SIZE],
+    black: [i16; HIDDEN_SIZE],
+}
+
+// (white perspective, black perspective) feature index of a piece on a square.
+fn feature_index(player: Player, piece: Piece, pos: usize) -> (usize, usize) {
+    let piece = piece as usize;
+    match player {
+        Player::White => (piece * 64 + pos, 384 + piece * 64 + (pos ^ 56)),
+        Player::Black => (384 + piece * 64 + pos, piece * 64 + (pos ^ 56)),
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+mod kernels {
+    use core::arch::wasm32::*;
+    use super::HIDDEN_SIZE;
+
+    pub fn add_assign(acc: &mut [i16; HIDDEN_SIZE], weights: &[i16]) {
+        assert!(weights.len() >= HIDDEN_SIZE);
+        for i in (0..HIDDEN_SIZE).step_by(8) {
+            unsafe {
+                let a = v128_load(acc.as_ptr().add(i) as *const v128);
+                let b = v128_load(weights.as_ptr().add(i) as *const v128);
+                v128_store(acc.as_mut_ptr().add(i) as *mut v128, i16x8_add(a, b));
+            }
+        }
+    }
+
+    pub fn sub_assign(acc: &mut [i16; HIDDEN_SIZE], weights: &[i16]) {
+        assert!(weights.len() >= HIDDEN_SIZE);
+        for i in (0..HIDDEN_SIZE).step_by(8) {
+            unsafe {
+                let a = v128_load(acc.as_ptr().add(i) as *const v128);
+                let b = v128_load(weights.as_ptr().add(i) as *const v128);
+                v128_store(acc.as_mut_ptr().add(i) as *mut v128, i16x8_sub(a, b));
+            }
+        }
+    }
+
+    pub fn crelu_dot(acc: &[i16; HIDDEN_SIZE], weights: &[i16], max: i16) -> i32 {
+        assert!(weights.len() >= HIDDEN_SIZE);
+        let zero = i16x8_splat(0);
+        let max = i16x8_splat(max);
+        let mut sum = i32x4_splat(0);
+        for i in (0..HIDDEN_SIZE).step_by(8) {
+            unsafe {
+                let a = v128_load(acc.as_ptr().add(i) as *const v128);
+                let w = v128_load(weights.as_ptr().add(i) as *const v128);
+                let clipped = i16x8_min(i16x8_max(a, zero), max);
+                sum = i32x4_add(sum, i32x4_dot_i16x8(clipped, w));
+            }
+        }
+        i32x4_extract_lane::<0>(sum) + i32x4_extract_lane::<1>(sum) + i32x4_extract_lane::<2>(sum) + i32x4_extract_lane::<3>(sum)
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+mod kernels {
+    use super::HIDDEN_SIZE;
+
+    pub fn add_assign(acc: &mut [i16; HIDDEN_SIZE], weights: &[i16]) {
+        acc.iter_mut().zip(weights).for_each(|(a, w)| *a = a.wrapping_add(*w));
+    }
+
+    pub fn sub_assign(acc: &mut [i16; HIDDEN_SIZE], weights: &[i16]) {
+        acc.iter_mut().zip(weights).for_each(|(a, w)| *a = a.wrapping_sub(*w));
+    }
+
+    pub fn crelu_dot(acc: &[i16; HIDDEN_SIZE], weights: &[i16], max: i16) -> i32 {
+        acc.iter().zip(weights).map(|(a, w)| (*a).clamp(0, max) as i32 * *w as i32).sum()
+    }
+}
+
+impl Network {
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        let expected = 8 + 2 * (NUM_FEATURES * HIDDEN_SIZE + HIDDEN_SIZE + 2 * HIDDEN_SIZE) + 4;
+        if data.len() != expected || &data[..4] != NETWORK_MAGIC {
+            return None;
+        }
+        if u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize != HIDDEN_SIZE {
+            return None;
+        }
+        let mut offset = 8;
+        let mut read_i16s = |len: usize| {
+            let ans: Vec<i16> = data[offset..offset + 2 * len]
+                .chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            offset += 2 * len;
+            ans
+        };
+        let feature_weights = read_i16s(NUM_FEATURES * HIDDEN_SIZE);
+        let feature_bias = read_i16s(HIDDEN_SIZE);
+        let output_weights = read_i16s(2 * HIDDEN_SIZE);
+        let output_bias = i32::from_le_bytes(data[data.len() - 4..].try_into().unwrap());
+        Some(Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ans = Vec::new();
+        ans.extend_from_slice(NETWORK_MAGIC);
+        ans.extend_from_slice(&(HIDDEN_SIZE as u32).to_le_bytes());
+        for w in self.feature_weights.iter().chain(&self.feature_bias).chain(&self.output_weights) {
+            ans.extend_from_slice(&w.to_le_bytes());
+        }
+        ans.extend_from_slice(&self.output_bias.to_le_bytes());
+        ans
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN_SIZE..(feature + 1) * HIDDEN_SIZE]
+    }
+
+    fn add_piece(&self, acc: &mut Accumulator, player: Player, piece: Piece, pos: usize) {
+        let (w, b) = feature_index(player, piece, pos);
+        kernels::add_assign(&mut acc.white, self.weights(w));
+        kernels::add_assign(&mut acc.black, self.weights(b));
+    }
+
+    fn remove_piece(&self, acc: &mut Accumulator, player: Player, piece: Piece, pos: usize) {
+        let (w, b) = feature_index(player, piece, pos);
+        kernels::sub_assign(&mut acc.white, self.weights(w));
+        kernels::sub_assign(&mut acc.black, self.weights(b));
+    }
+
+    // the features only cover the orthodox pieces on 8x8 boards.
+    pub fn supports(&self, board: &ChessBoard) -> bool {
+        let compound = |piece: Piece| matches!(piece, Piece::Archbishop | Piece::Chancellor);
+        board.files() == 8 && board.ranks() == 8
+            && !board.board.iter().flatten().any(|(_, piece)| compound(*piece))
+            && [Player::White, Player::Black].iter().all(|player| board.pocket(*player, Piece::Archbishop) + board.pocket(*player, Piece::Chancellor) == 0)
+    }
+
+    // build the accumulator of a position from scratch.
+    pub fn refresh(&self, board: &ChessBoard) -> Accumulator {
+        let mut acc = Accumulator {
+            white: [0; HIDDEN_SIZE],
+            black: [0; HIDDEN_SIZE],
+        };
+        acc.white.copy_from_slice(&self.feature_bias);
+        acc.black.copy_from_slice(&self.feature_bias);
+        for (pos, cell) in board.board.iter().enumerate() {
+            if let Some((player, piece)) = cell {
+                self.add_piece(&mut acc, *player, *piece, pos);
+            }
+        }
+        acc
+    }
+
+    /**
+     * The accumulator after `mres` is played from `board` (the position before the move), by adding and removing only
+     * the features touched by the move. Undoing a move is done by going back to the parent's accumulator.
+     */
+    pub fn update(&self, acc: &Accumulator, board: &ChessBoard, mres: MoveResult) -> Accumulator {
+        let mut ans = acc.clone();
+        let pos0 = get_pos0!(mres);
+        let pos1 = get_pos1!(mres);
+        if mreq_is_drop(mres as MoveRequest) {
+            let (player, piece) = get_promote!(mres).unwrap();
+            self.add_piece(&mut ans, player, piece, pos1);
+            return ans;
+        }
+        let (player, piece) = board.board[pos0].unwrap();
+        if mres_get_castle(mres) {
+            let (king_from, king_to, rook_from, rook_to) = board.castling(mres as MoveRequest).unwrap();
+            self.remove_piece(&mut ans, player, Piece::King, king_from);
+            self.remove_piece(&mut ans, player, Piece::Rook, rook_from);
+            self.add_piece(&mut ans, player, Piece::King, king_to);
+            self.add_piece(&mut ans, player, Piece::Rook, rook_to);
+            return ans;
+        }
+        self.remove_piece(&mut ans, player, piece, pos0);
+        if let Some((captured_player, captured)) = mres_get_capture(mres) {
+            let captured_pos = if mres_get_en_passant(mres) { pos0 / board.files() * board.files() + pos1 % board.files() } else { pos1 };
+            self.remove_piece(&mut ans, captured_player, captured, captured_pos);
+        }
+        // an atomic capture leaves nothing on the destination
+        if mres & EXPLODED_CAPTURER_FLAG != 0 {
+            for (pos, (exploded_player, exploded)) in board.exploded_pieces(mres) {
+                self.remove_piece(&mut ans, exploded_player, exploded, pos);
+            }
+            return ans;
+        }
+        match get_promote!(mres) {
+            Some((_, promoted)) => self.add_piece(&mut ans, player, promoted, pos1),
+            None => self.add_piece(&mut ans, player, piece, pos1),
+        }
+        ans
+    }
+
+    // evaluation in centipawns, from the side to move's perspective.
+    pub fn evaluate(&self, acc: &Accumulator, player: Player) -> i32 {
+        let (us, them) = match player {
+            Player::White => (&acc.white, &acc.black),
+            Player::Black => (&acc.black, &acc.white),
+        };
+        // each half fits in an i32 (HIDDEN_SIZE * QA * i16::MAX), but not their sum.
+        let output = kernels::crelu_dot(us, &self.output_weights[..HIDDEN_SIZE], QA as i16) as i64
+            + kernels::crelu_dot(them, &self.output_weights[HIDDEN_SIZE..], QA as i16) as i64;
+        ((output + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chessboard::variant::Variant;
+
+    // a network with small pseudo-random weights, the same every run.
+    fn random_network() -> Network {
+        let mut seed: u64 = 0x9e3779b97f4a7c15;
+        let mut next = |range: i16| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) % (2 * range as u64 + 1)) as i16 - range
+        };
+        Network {
+            feature_weights: (0..NUM_FEATURES * HIDDEN_SIZE).map(|_| next(64)).collect(),
+            feature_bias: (0..HIDDEN_SIZE).map(|_| next(64)).collect(),
+            output_weights: (0..2 * HIDDEN_SIZE).map(|_| next(64)).collect(),
+            output_bias: 0,
+        }
+    }
+
+    #[test]
+    fn update_matches_refresh() {
+        let network = random_network();
+        let positions = [
+            (Variant::Standard, "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            (Variant::Standard, "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"),
+            // Chess960 with the rooks inside, castling written as the king taking its rook; the rook on g8 keeps the
+            // king off g1.
+            (Variant::Standard, "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1"),
+            (Variant::Standard, "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"),
+            (Variant::Standard, "4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 1"),
+            (Variant::Standard, "1n2k3/P7/8/8/8/8/7p/4K1N1 w - - 0 1"),
+            (Variant::Standard, "1n2k3/P7/8/8/8/8/7p/4K1N1 b - - 0 1"),
+            (Variant::Crazyhouse, "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[Nn] w KQkq - 0 1"),
+            (Variant::Atomic, "4k3/8/2nbp3/3q4/8/2nQ4/3P4/4K3 w - - 0 1"),
+        ];
+        let (mut castles, mut en_passants, mut promotions, mut drops, mut explosions) = (0, 0, 0, 0, 0);
+        for (variant, fen) in positions {
+            let board = ChessBoard::from_variant_fen(fen, variant).unwrap();
+            assert!(network.supports(&board));
+            let acc = network.refresh(&board);
+            for mreq in board.possible_moves().into_iter().skip(1) {
+                let mut after = board;
+                let mres = after.do_move(mreq);
+                castles += mres_get_castle(mres) as usize;
+                en_passants += mres_get_en_passant(mres) as usize;
+                promotions += (!mreq_is_drop(mreq) && get_promote!(mreq).is_some()) as usize;
+                drops += mreq_is_drop(mreq) as usize;
+                explosions += (mres & EXPLODED_CAPTURER_FLAG != 0) as usize;
+                let updated = network.update(&acc, &board, mres);
+                let refreshed = network.refresh(&after);
+                assert!(updated.white == refreshed.white && updated.black == refreshed.black, "{} {}", fen, board.mreq_to_uci(mreq));
+                assert_eq!(network.evaluate(&updated, after.player), network.evaluate(&refreshed, after.player));
+            }
+        }
+        assert_eq!((castles, en_passants, promotions), (5, 2, 16));
+        assert!(drops > 0 && explosions >= 3);
+    }
+}
@@ -0,0 +1,126 @@
+// Move ordering for alpha-beta: hash move, captures by MVV-LVA, promotions, killer moves, counter moves,
+// then the remaining quiet moves by history score.
+
+use crate::chessboard::*;
+use crate::get_pos0;
+use crate::get_pos1;
+use crate::get_promote;
+
+pub const MAX_PLY: usize = 64;
+
+const HASH_MOVE_SCORE: i32 = 1 << 24;
+const CAPTURE_SCORE: i32 = 1 << 22;
+const PROMOTE_SCORE: i32 = 1 << 21;
+const KILLER_SCORE: i32 = 1 << 20;
+const COUNTER_MOVE_SCORE: i32 = 1 << 19;
+// history scores are kept below the counter move score.
+const HISTORY_MAX: i32 = 1 << 18;
+
+// rank of piece for MVV-LVA, indexed by `Piece`: pawn, rook, knight, bishop, queen, king, archbishop, chancellor
+const PIECE_RANKS: [i32; 8] = [1, 4, 2, 3, 7, 8, 5, 6];
+
+pub struct MoveOrdering {
+    // two quiet moves per ply that caused a beta cutoff in a sibling node.
+    killers: [[MoveRequest; 2]; MAX_PLY],
+    // indexed by player, source and destination.
+    history: [[[i32; MAX_SQUARES]; MAX_SQUARES]; 2],
+    // quiet move that refuted the previous move, indexed by the source and destination of the previous move.
+    counter_moves: [[MoveRequest; MAX_SQUARES]; MAX_SQUARES],
+}
+
+// castling in Chess960 is encoded as the king moving onto its own rook, which is not a capture.
+fn captured(board: &ChessBoard, m: MoveRequest) -> Option<Piece> {
+    match board.board[get_pos1!(m)] {
+        Some((player, piece)) if player != board.player => Some(piece),
+        _ => None,
+    }
+}
+
+// crazyhouse drops are quiet, although they are encoded as promotions.
+pub fn is_quiet(board: &ChessBoard, m: MoveRequest) -> bool {
+    captured(board, m).is_none() && (get_promote!(m).is_none() || mreq_is_drop(m)) && !is_en_passant(board, m)
+}
+
+fn is_en_passant(board: &ChessBoard, m: MoveRequest) -> bool {
+    let pos0 = get_pos0!(m);
+    let pos1 = get_pos1!(m);
+    matches!(board.board[pos0], Some((_, Piece::Pawn))) && pos0 % board.files() != pos1 % board.files() && board.board[pos1].is_none()
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveOrdering {
+    pub fn new() -> Self {
+        MoveOrdering {
+            killers: [[0; 2]; MAX_PLY],
+            history: [[[0; MAX_SQUARES]; MAX_SQUARES]; 2],
+            counter_moves: [[0; MAX_SQUARES]; MAX_SQUARES],
+        }
+    }
+
+    pub fn score(&self, board: &ChessBoard, m: MoveRequest, hash_move: Option<MoveRequest>, ply: usize, prev: Option<MoveRequest>) -> i32 {
+        if hash_move == Some(m) {
+            return HASH_MOVE_SCORE;
+        }
+        let pos0 = get_pos0!(m);
+        let pos1 = get_pos1!(m);
+        let mut ans = 0;
+        if let Some(victim) = captured(board, m) {
+            let attacker = board.board[pos0].unwrap().1;
+            ans += CAPTURE_SCORE + 16 * PIECE_RANKS[victim as usize] - PIECE_RANKS[attacker as usize];
+        } else if is_en_passant(board, m) {
+            ans += CAPTURE_SCORE + 16 * PIECE_RANKS[Piece::Pawn as usize] - PIECE_RANKS[Piece::Pawn as usize];
+        }
+        match get_promote!(m) {
+            Some((_, promote)) if !mreq_is_drop(m) => ans += PROMOTE_SCORE + PIECE_RANKS[promote as usize],
+            _ => {},
+        }
+        if ans != 0 {
+            return ans;
+        }
+        if ply < MAX_PLY {
+            if self.killers[ply][0] == m {
+                return KILLER_SCORE + 1;
+            }
+            if self.killers[ply][1] == m {
+                return KILLER_SCORE;
+            }
+        }
+        if let Some(prev) = prev {
+            if self.counter_moves[get_pos0!(prev)][get_pos1!(prev)] == m {
+                return COUNTER_MOVE_SCORE;
+            }
+        }
+        self.history[board.player as usize][pos0][pos1]
+    }
+
+    pub fn sort(&self, board: &ChessBoard, moves: &mut [MoveRequest], hash_move: Option<MoveRequest>, ply: usize, prev: Option<MoveRequest>) {
+        moves.sort_by_cached_key(|m| -self.score(board, *m, hash_move, ply, prev));
+    }
+
+    // called when the quiet move `m` caused a beta cutoff.
+    pub fn update(&mut self, board: &ChessBoard, m: MoveRequest, depth: usize, ply: usize, prev: Option<MoveRequest>) {
+        if ply < MAX_PLY && self.killers[ply][0] != m {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = m;
+        }
+        if let Some(prev) = prev {
+            self.counter_moves[get_pos0!(prev)][get_pos1!(prev)] = m;
+        }
+        let entry = &mut self.history[board.player as usize][get_pos0!(m)][get_pos1!(m)];
+        *entry += (depth * depth) as i32;
+        if *entry >= HISTORY_MAX {
+            self.history.iter_mut().flatten().flatten().for_each(|h| *h /= 2);
+        }
+    }
+
+    // killers refer to plies of the previous search, history is kept but aged.
+    pub fn new_search(&mut self) {
+        self.killers = [[0; 2]; MAX_PLY];
+        self.history.iter_mut().flatten().flatten().for_each(|h| *h /= 8);
+    }
+}
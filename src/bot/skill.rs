@@ -0,0 +1,109 @@
+// Weaker versions of the naive bot: shallower and node limited search, noisy evaluation, and picking among the best
+// few moves rather than always the best one.
+
+use rand::Rng;
+
+use crate::chessboard::*;
+use super::ChessMover;
+use super::naive_bot::{NaiveBot, SearchOptions, SearchResult};
+
+// the full strength naive bot
+pub const MAX_SKILL_LEVEL: usize = 6;
+
+/**
+ * Elo of each level relative to the random bot: every level played the level below it (level 0 the random bot) and
+ * the rating differences were chained, with `calibrate 400 1` for the first two levels and `calibrate 60` for the
+ * others. The steps measured were +94 ± 16, +482 ± 51, +207 ± 71, +436 ± 152, +215 ± 73, +161 ± 68 and +101 ± 76.
+ */
+pub const SKILL_ELO: [i32; MAX_SKILL_LEVEL + 1] = [94, 576, 783, 1219, 1434, 1595, 1696];
+
+// number of moves the weakened bot chooses from
+const SKILL_MULTI_PV: usize = 4;
+
+/**
+ * Depth, node limit, and evaluation noise and move margin in centipawns of the levels below full strength. Each one
+ * is measurably stronger than the one before; a depth 1 level with half the noise of level 0 could not be told apart
+ * from it in 60 games, and was merged into it.
+ */
+const SKILL_SETTINGS: [(usize, u64, i32, i32); MAX_SKILL_LEVEL] = [
+    (1, 100, 400, 300),
+    (2, 1000, 120, 100),
+    (2, 2000, 60, 60),
+    (3, 5000, 40, 40),
+    (4, 10000, 20, 20),
+    (5, 30000, 10, 10),
+];
+
+pub fn skill_options(level: usize) -> SearchOptions {
+    let level = level.min(MAX_SKILL_LEVEL);
+    if level == MAX_SKILL_LEVEL {
+        return SearchOptions::default();
+    }
+    let (max_depth, max_nodes, noise, _) = SKILL_SETTINGS[level];
+    SearchOptions {
+        max_depth,
+        max_nodes: Some(max_nodes),
+        eval_noise: 16 * noise,
+        multi_pv: SKILL_MULTI_PV,
+        ..SearchOptions::default()
+    }
+}
+
+// in 1/16 centipawn: moves at most this much worse than the best one may be played.
+fn skill_margin(level: usize) -> i32 {
+    SKILL_SETTINGS.get(level).map_or(0, |(_, _, _, margin)| 16 * margin)
+}
+
+/**
+ * Pick one of the analyzed lines: each line within `margin` of the best gets a random bonus of up to `margin`, and
+ * the highest total wins. Returns the index of the line.
+ */
+pub fn pick_line(result: &SearchResult, player: Player, margin: i32, rng: &mut impl Rng) -> usize {
+    let value = |i: usize| {
+        let v = result.lines[i].score.to_value();
+        if player == Player::White { v } else { -v }
+    };
+    let best = value(0);
+    if margin <= 0 {
+        return 0;
+    }
+    (0..result.lines.len())
+        .filter(|i| value(*i) >= best.saturating_sub(margin))
+        .max_by_key(|i| value(*i).saturating_add(rng.gen_range(0..=margin)))
+        .unwrap_or(0)
+}
+
+pub struct SkillBot {
+    level: usize,
+    bot: NaiveBot,
+}
+
+impl SkillBot {
+    pub fn new(level: usize) -> Self {
+        let level = level.min(MAX_SKILL_LEVEL);
+        SkillBot {
+            level,
+            bot: NaiveBot::new(skill_options(level)),
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn elo(&self) -> i32 {
+        SKILL_ELO[self.level]
+    }
+}
+
+impl ChessMover for SkillBot {
+    fn make_move(&mut self, board: &ChessBoard) -> i64 {
+        let result = self.bot.search(board);
+        let line = &result.lines[pick_line(&result, board.player, skill_margin(self.level), &mut rand::thread_rng())];
+        ((line.score.to_value() as i64) << 32) | line.moves[0] as i64
+    }
+
+    fn new_game(&mut self) {
+        self.bot.new_game()
+    }
+}
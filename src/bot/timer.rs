@@ -0,0 +1,25 @@
+// Wall clock for search time limits: Date.now() in the browser, the system clock elsewhere.
+
+#[cfg(target_arch = "wasm32")]
+mod js {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = Date)]
+        pub fn now() -> f64;
+    }
+}
+
+// milliseconds since the unix epoch
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    js::now()
+}
+
+// milliseconds since the unix epoch
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64() * 1000.0).unwrap_or(0.0)
+}
@@ -0,0 +1,316 @@
+// Bot-vs-bot games with adjudication, PGN output, and the statistics to compare two bots: Elo difference and SPRT.
+
+use std::collections::HashMap;
+
+use crate::chessboard::*;
+use crate::chessboard::variant::Variant;
+use crate::game::status::Status;
+use super::ChessMover;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn to_pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    // score of white
+    pub fn white_score(&self) -> f64 {
+        match self {
+            GameResult::WhiteWins => 1.0,
+            GameResult::BlackWins => 0.0,
+            GameResult::Draw => 0.5,
+        }
+    }
+}
+
+/**
+ * When to end games early, based on the evaluations reported by both bots (white is max, in 1/16 centipawn).
+ * A game is won when the evaluation stays beyond `resign_score` for `resign_plies` plies, and drawn after
+ * `draw_min_ply` if it stays within `draw_score` for `draw_plies` plies.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Adjudication {
+    pub resign_score: i32,
+    pub resign_plies: usize,
+    pub draw_score: i32,
+    pub draw_plies: usize,
+    pub draw_min_ply: usize,
+    // games still running after this many plies are drawn
+    pub max_plies: usize,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication {
+            resign_score: 16 * 1000,
+            resign_plies: 8,
+            draw_score: 16 * 10,
+            draw_plies: 16,
+            draw_min_ply: 80,
+            max_plies: 400,
+        }
+    }
+}
+
+pub struct GameRecord {
+    pub white: String,
+    pub black: String,
+    pub start: ChessBoard,
+    pub moves: Vec<MoveRequest>,
+    pub result: GameResult,
+    // why the game ended, e.g. "checkmate" or "adjudication"
+    pub termination: String,
+}
+
+pub fn play_game(white: &mut dyn ChessMover, black: &mut dyn ChessMover, start: &ChessBoard, adjudication: &Adjudication) -> GameRecord {
+    white.new_game();
+    black.new_game();
+    let mut board = *start;
+    let mut moves = vec![];
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    // number of consecutive plies the evaluation was decisive (signed, positive for white) or drawish.
+    let mut decisive_plies: i64 = 0;
+    let mut drawish_plies = 0;
+    let end = |result: GameResult, termination: &str, moves: Vec<MoveRequest>| GameRecord {
+        white: String::new(),
+        black: String::new(),
+        start: *start,
+        moves,
+        result,
+        termination: termination.to_string(),
+    };
+    let win = |player: Player| if player == Player::White { GameResult::WhiteWins } else { GameResult::BlackWins };
+    for ply in 0..adjudication.max_plies {
+        let repetitions = seen.entry(board.hash()).or_insert(0);
+        *repetitions += 1;
+        let status = Status::of_position(&board, *repetitions);
+        if status.is_over() {
+            return end(status.winner().map_or(GameResult::Draw, win), status.termination(), moves);
+        }
+        let mover: &mut dyn ChessMover = if board.player == Player::White { &mut *white } else { &mut *black };
+        let ans = mover.make_move(&board);
+        let mreq = (ans & 0xffffffff) as MoveRequest;
+        let evaluation = (ans >> 32) as i32;
+        board.do_move(mreq);
+        moves.push(mreq);
+
+        if evaluation >= adjudication.resign_score {
+            decisive_plies = decisive_plies.max(0) + 1;
+        } else if evaluation <= -adjudication.resign_score {
+            decisive_plies = decisive_plies.min(0) - 1;
+        } else {
+            decisive_plies = 0;
+        }
+        if decisive_plies.unsigned_abs() as usize >= adjudication.resign_plies {
+            return end(win(if decisive_plies > 0 { Player::White } else { Player::Black }), "adjudication", moves);
+        }
+        drawish_plies = if evaluation.abs() <= adjudication.draw_score { drawish_plies + 1 } else { 0 };
+        if ply >= adjudication.draw_min_ply && drawish_plies >= adjudication.draw_plies {
+            return end(GameResult::Draw, "adjudication", moves);
+        }
+    }
+    end(GameResult::Draw, "move limit", moves)
+}
+
+impl GameRecord {
+    pub fn to_pgn(&self, event: &str, round: usize) -> String {
+        let mut ans = String::new();
+        ans.push_str(&format!("[Event \"{}\"]\n", event));
+        ans.push_str(&format!("[Round \"{}\"]\n", round));
+        ans.push_str(&format!("[White \"{}\"]\n", self.white));
+        ans.push_str(&format!("[Black \"{}\"]\n", self.black));
+        ans.push_str(&format!("[Result \"{}\"]\n", self.result.to_pgn()));
+        let variant = self.start.variant();
+        if variant != Variant::Standard {
+            ans.push_str(&format!("[Variant \"{}\"]\n", variant.pgn_name()));
+        }
+        let fen = self.start.to_fen();
+        if fen != ChessBoard::new_variant(variant).to_fen() {
+            ans.push_str("[SetUp \"1\"]\n");
+            ans.push_str(&format!("[FEN \"{}\"]\n", fen));
+        }
+        ans.push_str(&format!("[Termination \"{}\"]\n\n", self.termination));
+        let mut board = self.start;
+        let mut tokens = vec![];
+        for (i, mreq) in self.moves.iter().enumerate() {
+            if board.player == Player::White {
+                tokens.push(format!("{}.", board.full_move()));
+            } else if i == 0 {
+                tokens.push(format!("{}...", board.full_move()));
+            }
+            tokens.push(board.move_to_san(*mreq));
+            board.do_move(*mreq);
+        }
+        tokens.push(self.result.to_pgn().to_string());
+        // lines of at most 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                ans.push_str(&line);
+                ans.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        ans.push_str(&line);
+        ans.push_str("\n\n");
+        ans
+    }
+}
+
+// wins, draws and losses of the first bot
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchScore {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl MatchScore {
+    pub fn add(&mut self, score: f64) {
+        if score > 0.5 {
+            self.wins += 1;
+        } else if score < 0.5 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + 0.5 * self.draws as f64
+    }
+
+    /**
+     * Mean and variance of the score of one game. When all games ended the same way there is no variance, so half
+     * a win and half a loss are added to keep the error bars and the SPRT meaningful.
+     */
+    fn mean_variance(&self) -> (f64, f64) {
+        let n = self.games();
+        let (wins, draws, losses) = if self.wins == n || self.draws == n || self.losses == n {
+            (self.wins as f64 + 0.5, self.draws as f64, self.losses as f64 + 0.5)
+        } else {
+            (self.wins as f64, self.draws as f64, self.losses as f64)
+        };
+        let n = wins + draws + losses;
+        let mean = (wins + 0.5 * draws) / n;
+        let variance = (wins * (1.0 - mean).powi(2) + draws * (0.5 - mean).powi(2) + losses * mean.powi(2)) / n;
+        (mean, variance)
+    }
+
+    /**
+     * Elo difference and the half width of its 95% confidence interval. A clean sweep is counted as half a game
+     * short of it, so that the result stays finite.
+     */
+    pub fn elo(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return (0.0, 0.0);
+        }
+        let bound = 0.5 / n;
+        let mean = self.points() / n;
+        let (_, variance) = self.mean_variance();
+        let margin = 1.96 * (variance / n).sqrt();
+        let clamp = |x: f64| x.clamp(bound, 1.0 - bound);
+        let elo = elo_from_score(clamp(mean));
+        let low = elo_from_score(clamp(mean - margin));
+        let high = elo_from_score(clamp(mean + margin));
+        (elo, (high - low) / 2.0)
+    }
+
+    // log likelihood ratio of H1: elo = elo1 against H0: elo = elo0, with the normal approximation of the score.
+    pub fn sprt_llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return 0.0;
+        }
+        let (mean, variance) = self.mean_variance();
+        let s0 = expected_score(elo0);
+        let s1 = expected_score(elo1);
+        (s1 - s0) * (2.0 * mean - s0 - s1) * n / (2.0 * variance)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SprtVerdict {
+    // elo1 is more likely
+    AcceptH1,
+    // elo0 is more likely
+    AcceptH0,
+    Continue,
+}
+
+// `alpha` and `beta` are the probabilities of false positives and false negatives.
+pub fn sprt_verdict(llr: f64, alpha: f64, beta: f64) -> SprtVerdict {
+    let lower = (beta / (1.0 - alpha)).ln();
+    let upper = ((1.0 - beta) / alpha).ln();
+    if llr >= upper {
+        SprtVerdict::AcceptH1
+    } else if llr <= lower {
+        SprtVerdict::AcceptH0
+    } else {
+        SprtVerdict::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn elo() {
+        // a score of 0.7 is 400 * log10(7 / 3) Elo, and the variance of one game 0.16.
+        let score = MatchScore { wins: 60, draws: 20, losses: 20 };
+        let (elo, error) = score.elo();
+        assert!(close(elo, 147.190714), "{}", elo);
+        assert!(close(error, 66.014639), "{}", error);
+        assert_eq!(MatchScore { wins: 10, draws: 80, losses: 10 }.elo().0, 0.0);
+        assert_eq!(MatchScore::default().elo(), (0.0, 0.0));
+        // a clean sweep of 10 games is counted as 9.5 points.
+        assert!(close(MatchScore { wins: 10, draws: 0, losses: 0 }.elo().0, 511.501440));
+        assert!(close(MatchScore { wins: 0, draws: 0, losses: 10 }.elo().0, -511.501440));
+    }
+
+    #[test]
+    fn sprt() {
+        let score = MatchScore { wins: 60, draws: 20, losses: 20 };
+        assert!(close(score.sprt_llr(0.0, 10.0), 1.733713), "{}", score.sprt_llr(0.0, 10.0));
+        assert!(close(score.sprt_llr(-5.0, 5.0), 1.798770));
+        let even = MatchScore { wins: 10, draws: 80, losses: 10 };
+        assert!(close(even.sprt_llr(0.0, 10.0), -0.206991));
+        assert_eq!(MatchScore::default().sprt_llr(0.0, 10.0), 0.0);
+        // bounds at ln(19) and -ln(19) for alpha = beta = 0.05.
+        assert_eq!(sprt_verdict(2.95, 0.05, 0.05), SprtVerdict::AcceptH1);
+        assert_eq!(sprt_verdict(-2.95, 0.05, 0.05), SprtVerdict::AcceptH0);
+        assert_eq!(sprt_verdict(2.9, 0.05, 0.05), SprtVerdict::Continue);
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::chessboard::MoveRequest;
+
+// number of entries, a power of two.
+pub const DEFAULT_TT_SIZE: usize = 1 << 18;
+
+// the key is stored xor the data, so that an entry torn by concurrent writes does not match any position.
+#[derive(Default)]
+struct Entry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// whether the value of an entry is the exact value of the position, or only a bound on it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Exact,
+    // the search failed high: the value is at least this.
+    Lower,
+    // the search failed low: the value is at most this.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtEntry {
+    pub best_move: MoveRequest,
+    // from the perspective of the player to move, with mates counted from this position rather than from the root.
+    pub value: i32,
+    pub bound: Bound,
+    pub depth: usize,
+}
+
+// data layout: best move in bits 0-18, bound in bits 19-20, depth in bits 21-28, value in bits 32-63.
+const MOVE_MASK: u64 = 0x7ffff;
+
+fn pack(entry: TtEntry) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    entry.best_move as u64 & MOVE_MASK | bound << 19 | (entry.depth.min(u8::MAX as usize) as u64) << 21
+        | (entry.value as u32 as u64) << 32
+}
+
+fn unpack(data: u64) -> TtEntry {
+    TtEntry {
+        best_move: (data & MOVE_MASK) as MoveRequest,
+        value: (data >> 32) as u32 as i32,
+        bound: match (data >> 19) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        },
+        depth: (data >> 21) as u8 as usize,
+    }
+}
+
+/**
+ * Remembers the best move and the value found for a position, so that the best move is searched first next time the
+ * position is visited, and the search can stop there if it was deep enough. Lock-free, so that it can be shared by
+ * the threads of a parallel search.
+ */
+pub struct TranspositionTable {
+    entries: Vec<Entry>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two());
+        TranspositionTable {
+            entries: (0..size).map(|_| Entry::default()).collect(),
+            mask: size - 1,
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let entry = &self.entries[key as usize & self.mask];
+        let data = entry.data.load(Ordering::Relaxed);
+        let ans = unpack(data);
+        if entry.key.load(Ordering::Relaxed) ^ data == key && ans.best_move != 0 {
+            Some(ans)
+        } else {
+            None
+        }
+    }
+
+    // replaces entries of other positions, and entries of the same position searched at a lower depth.
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let slot = &self.entries[key as usize & self.mask];
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+        if old_key != key || entry.depth >= unpack(old_data).depth {
+            let data = pack(entry);
+            slot.key.store(key ^ data, Ordering::Relaxed);
+            slot.data.store(data, Ordering::Relaxed);
+        }
+    }
+
+    pub fn clear(&self) {
+        for entry in &self.entries {
+            entry.key.store(0, Ordering::Relaxed);
+            entry.data.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_round_trip() {
+        for value in [0, 1, -1, 1234, i32::MAX - 3, -i32::MAX + 8] {
+            for bound in [Bound::Exact, Bound::Lower, Bound::Upper] {
+                let entry = TtEntry { best_move: 0x7c000 | 127 << 7 | 5, value, bound, depth: 17 };
+                assert_eq!(unpack(pack(entry)), entry);
+            }
+        }
+        let deep = TtEntry { best_move: 12 | 28 << 7, value: 0, bound: Bound::Exact, depth: 1000 };
+        assert_eq!(unpack(pack(deep)).depth, u8::MAX as usize);
+    }
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(16);
+        let entry = |depth| TtEntry { best_move: 12 | 28 << 7, value: -50, bound: Bound::Upper, depth };
+        assert_eq!(tt.probe(3), None);
+        tt.store(3, entry(4));
+        assert_eq!(tt.probe(3), Some(entry(4)));
+        // another position in the same slot does not match.
+        assert_eq!(tt.probe(3 + 16), None);
+        // the same position searched shallower does not replace it, another position does.
+        tt.store(3, entry(2));
+        assert_eq!(tt.probe(3), Some(entry(4)));
+        tt.store(3 + 16, entry(1));
+        assert_eq!(tt.probe(3), None);
+        assert_eq!(tt.probe(3 + 16), Some(entry(1)));
+        tt.clear();
+        assert_eq!(tt.probe(3 + 16), None);
+    }
+}
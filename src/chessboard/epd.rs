@@ -0,0 +1,152 @@
+// Extended Position Description: the first four fields of a FEN followed by operations such as
+// `bm Nf3; id "WAC.001"; c0 "comment";`.
+
+use super::*;
+
+pub struct EpdRecord {
+    pub board: ChessBoard,
+    // opcode and operands, in order of appearance; quotes around string operands are removed.
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+// split on whitespace, keeping quoted strings together.
+fn split_operands(s: &str) -> Result<Vec<String>, String> {
+    let mut ans = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut operand = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => operand.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            ans.push(operand);
+        } else {
+            let mut operand = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                operand.push(c);
+                chars.next();
+            }
+            ans.push(operand);
+        }
+    }
+    Ok(ans)
+}
+
+// split on the semicolons ending operations, except those inside strings.
+fn split_operations(s: &str) -> Vec<&str> {
+    let mut ans = vec![];
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => {
+                ans.push(&s[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    ans.push(&s[start..]);
+    ans.into_iter().filter(|op| !op.trim().is_empty()).collect()
+}
+
+impl EpdRecord {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        // the four position fields, each followed by whitespace
+        let mut rest = line;
+        let mut fields = vec![];
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return Err("too few fields".to_string());
+            }
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        let board = ChessBoard::from_fen(&fields.join(" "))?;
+        let mut operations = vec![];
+        for op in split_operations(rest) {
+            let mut operands = split_operands(op)?;
+            if operands.is_empty() {
+                continue;
+            }
+            let opcode = operands.remove(0);
+            operations.push((opcode, operands));
+        }
+        Ok(EpdRecord { board, operations })
+    }
+
+    // operands of the first operation with this opcode
+    pub fn get(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("id").and_then(|o| o.first()).map(String::as_str)
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.get("c0").and_then(|o| o.first()).map(String::as_str)
+    }
+
+    // moves of a `bm` or `am` operation; an error names a move that is not legal in the position.
+    pub fn moves(&self, opcode: &str) -> Result<Vec<MoveRequest>, String> {
+        self.get(opcode).unwrap_or_default().iter().map(|san| {
+            self.board.parse_san(san).ok_or_else(|| format!("illegal move {} in {}", san, opcode))
+        }).collect()
+    }
+
+    pub fn best_moves(&self) -> Result<Vec<MoveRequest>, String> {
+        self.moves("bm")
+    }
+
+    pub fn avoid_moves(&self) -> Result<Vec<MoveRequest>, String> {
+        self.moves("am")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_001: &str = r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; am Qh4 Nxf7; id "WAC.001"; c0 "mate; in 3";"#;
+
+    #[test]
+    fn parse_wac() {
+        let record = EpdRecord::parse(WAC_001).unwrap();
+        assert_eq!(record.board.to_fen(), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+        assert_eq!(record.id(), Some("WAC.001"));
+        assert_eq!(record.comment(), Some("mate; in 3"));
+        assert_eq!(record.get("bm"), Some(&["Qg6".to_string()][..]));
+        let uci = |moves: Vec<MoveRequest>| moves.iter().map(|m| record.board.mreq_to_uci(*m)).collect::<Vec<_>>();
+        assert_eq!(uci(record.best_moves().unwrap()), ["g3g6"]);
+        assert_eq!(uci(record.avoid_moves().unwrap()), ["g3h4", "e5f7"]);
+        assert_eq!(record.operations.len(), 4);
+        assert_eq!(record.get("pv"), None);
+        assert_eq!(record.moves("pv"), Ok(vec![]));
+    }
+
+    #[test]
+    fn errors() {
+        let record = EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Qh8;").unwrap();
+        assert_eq!(record.best_moves(), Err("illegal move Qh8 in bm".to_string()));
+        assert!(EpdRecord::parse("4k3/8/8/8/8/8/8/4K3 w -").is_err());
+        assert!(EpdRecord::parse(r#"4k3/8/8/8/8/8/8/4K3 w - - id "WAC.001;"#).is_err());
+        // operations without a semicolon after the last one.
+        let record = EpdRecord::parse(r#"4k3/8/8/8/8/8/8/4K3 w - - id "x"; bm Kd2"#).unwrap();
+        assert_eq!(record.best_moves().map(|m| m.len()), Ok(1));
+    }
+}
@@ -0,0 +1,228 @@
+// Checking a move request from outside, e.g. the UI or the network, and telling why it is illegal.
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoveError {
+    // bits set outside the squares and the promotion piece, or a promotion piece that is no piece.
+    Malformed(MoveRequest),
+    OffBoard(usize),
+    // the game is over, e.g. by the rules of the variant, so no move is legal.
+    GameOver,
+    NoPiece(usize),
+    WrongColor(usize),
+    // the piece can not move there.
+    IllegalDestination { from: usize, to: usize },
+    LeavesKingInCheck,
+    // a pawn reaching the last rank without saying what it becomes.
+    MissingPromotion,
+    // a piece to promote to on a move that does not promote, or one the pawn can not become.
+    InvalidPromotion,
+    // a crazyhouse drop of a piece not in the pocket, on an occupied square or a pawn on a back rank.
+    IllegalDrop(usize),
+    // allowed by how the piece moves but not by the variant, e.g. not capturing when a capture is forced in antichess.
+    ForbiddenByVariant,
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MoveError::Malformed(mreq) => write!(f, "malformed move request {:#x}", mreq),
+            MoveError::OffBoard(pos) => write!(f, "square {} is off the board", pos),
+            MoveError::GameOver => write!(f, "the game is over"),
+            MoveError::NoPiece(pos) => write!(f, "no piece on square {}", pos),
+            MoveError::WrongColor(pos) => write!(f, "the piece on square {} belongs to the opponent", pos),
+            MoveError::IllegalDestination { from, to } => write!(f, "the piece on square {} can not move to square {}", from, to),
+            MoveError::LeavesKingInCheck => write!(f, "the move leaves the king in check"),
+            MoveError::MissingPromotion => write!(f, "no piece to promote to"),
+            MoveError::InvalidPromotion => write!(f, "invalid piece to promote to"),
+            MoveError::IllegalDrop(pos) => write!(f, "can not drop on square {}", pos),
+            MoveError::ForbiddenByVariant => write!(f, "the move is forbidden by the variant"),
+        }
+    }
+}
+
+impl ChessBoard {
+    /**
+     * Ok if the move is in possible_moves, otherwise why not. Any request is safe to check, so that do_move, which
+     * trusts its input, is only given legal ones.
+     */
+    pub fn check_move(&self, mreq: MoveRequest) -> Result<(), MoveError> {
+        let moves = self.possible_moves();
+        if moves[1..].contains(&mreq) {
+            return Ok(());
+        }
+        if self.variant_outcome().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        self.check_request(mreq)?;
+        let (pos0, pos1, promote) = (get_pos0!(mreq), get_pos1!(mreq), get_promote!(mreq));
+
+        let mut pseudo_legal = vec![];
+        if mreq_is_drop(mreq) {
+            if self.variant == Variant::Crazyhouse {
+                self.drop_moves(&mut pseudo_legal);
+            }
+            return Err(if pseudo_legal.contains(&mreq) { MoveError::LeavesKingInCheck } else { MoveError::IllegalDrop(pos1) });
+        }
+        match self.board[pos0] {
+            None => return Err(MoveError::NoPiece(pos0)),
+            Some((player, _)) if player != self.player => return Err(MoveError::WrongColor(pos0)),
+            _ => (),
+        }
+
+        let (enemy_range, _) = self.get_attacking_range(self.player.opponent());
+        self.pseudo_legal_moves(enemy_range, &mut pseudo_legal);
+        if pseudo_legal.contains(&mreq) {
+            if self.variant == Variant::Antichess {
+                return Err(MoveError::ForbiddenByVariant);
+            }
+            let mut new_board = *self;
+            new_board.move_pieces(mreq);
+            return Err(if new_board.get_attacking_range(self.player.opponent()).1 {
+                MoveError::LeavesKingInCheck
+            } else {
+                MoveError::ForbiddenByVariant
+            });
+        }
+        // right squares, wrong promotion.
+        if moves[1..].iter().chain(&pseudo_legal).any(|m| get_pos0!(*m) == pos0 && get_pos1!(*m) == pos1) {
+            return Err(if promote.is_none() { MoveError::MissingPromotion } else { MoveError::InvalidPromotion });
+        }
+        Err(MoveError::IllegalDestination { from: pos0, to: pos1 })
+    }
+
+    // whether the request could be a move on this board at all: its squares on it and the promotion piece a piece.
+    pub fn check_request(&self, mreq: MoveRequest) -> Result<(), MoveError> {
+        let raw = mreq as MoveResult;
+        if raw & !(POS_0_FLAG | POS_1_FLAG | PROMOTE_FLAG) != 0 || (raw & PROMOTE_FLAG) >> PROMOTE_SHIFT > 16 {
+            return Err(MoveError::Malformed(mreq));
+        }
+        match [get_pos0!(mreq), get_pos1!(mreq)].into_iter().find(|pos| *pos >= self.squares()) {
+            Some(pos) => Err(MoveError::OffBoard(pos)),
+            None => Ok(()),
+        }
+    }
+
+    // do_move after check_move, leaving the board as it was if the move is illegal.
+    pub fn try_move(&mut self, mreq: MoveRequest) -> Result<MoveResult, MoveError> {
+        self.check_move(mreq)?;
+        Ok(self.do_move(mreq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chessboard::variant::Variant;
+
+    fn request(board: &ChessBoard, uci: &str) -> MoveRequest {
+        mreq_new(board.pos_from_string(&uci[..2]).unwrap(), board.pos_from_string(&uci[2..4]).unwrap())
+    }
+
+    fn play(board: &mut ChessBoard, moves: &[&str]) {
+        for uci in moves {
+            let mreq = board.parse_uci_move(uci).unwrap();
+            assert_eq!(board.check_move(mreq), Ok(()));
+            board.do_move(mreq);
+        }
+    }
+
+    #[test]
+    fn legal_moves() {
+        let mut board = ChessBoard::new();
+        for mreq in board.possible_moves().into_iter().skip(1) {
+            assert_eq!(board.check_move(mreq), Ok(()));
+        }
+        let e4 = request(&board, "e2e4");
+        assert!(board.try_move(e4).is_ok());
+        assert_eq!(board.player, Player::Black);
+    }
+
+    #[test]
+    fn wrong_pieces_and_squares() {
+        let mut board = ChessBoard::new();
+        let e3 = board.pos_from_string("e3").unwrap();
+        let e7 = board.pos_from_string("e7").unwrap();
+        assert_eq!(board.check_move(request(&board, "e3e4")), Err(MoveError::NoPiece(e3)));
+        assert_eq!(board.check_move(request(&board, "e7e5")), Err(MoveError::WrongColor(e7)));
+        let e2e5 = request(&board, "e2e5");
+        assert_eq!(board.check_move(e2e5), Err(MoveError::IllegalDestination { from: get_pos0!(e2e5), to: get_pos1!(e2e5) }));
+        // an illegal move leaves the board as it was.
+        let before = board.to_fen();
+        assert!(board.try_move(e2e5).is_err());
+        assert_eq!(board.to_fen(), before);
+    }
+
+    #[test]
+    fn malformed_and_off_board() {
+        let board = ChessBoard::new();
+        assert_eq!(board.check_move(0xffff_ffff), Err(MoveError::Malformed(0xffff_ffff)));
+        // no cell is encoded as 31.
+        let mreq = request(&board, "e2e4") | (31 << PROMOTE_SHIFT);
+        assert_eq!(board.check_move(mreq), Err(MoveError::Malformed(mreq)));
+        assert_eq!(board.check_move(mreq_new(100, 5)), Err(MoveError::OffBoard(100)));
+        assert_eq!(board.check_move(mreq_new(12, 64)), Err(MoveError::OffBoard(64)));
+    }
+
+    #[test]
+    fn leaves_king_in_check() {
+        // the king is in check from the rook on h1, and the rook on e2 does not block it.
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/4R3/4K2r w - - 0 1").unwrap();
+        assert_eq!(board.check_move(request(&board, "e2e3")), Err(MoveError::LeavesKingInCheck));
+        assert_eq!(board.check_move(request(&board, "e2e1")), Err(MoveError::IllegalDestination {
+            from: board.pos_from_string("e2").unwrap(),
+            to: board.pos_from_string("e1").unwrap(),
+        }));
+        let pinned = ChessBoard::from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1").unwrap();
+        assert_eq!(pinned.check_move(request(&pinned, "e2d3")), Err(MoveError::LeavesKingInCheck));
+    }
+
+    #[test]
+    fn promotions() {
+        let board = ChessBoard::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let (a7, a8) = (board.pos_from_string("a7").unwrap(), board.pos_from_string("a8").unwrap());
+        assert_eq!(board.check_move(mreq_new(a7, a8)), Err(MoveError::MissingPromotion));
+        assert_eq!(board.check_move(mreq_new_with_promote(a7, a8, &Some((Player::White, Piece::Queen)))), Ok(()));
+        for promote in [(Player::White, Piece::King), (Player::White, Piece::Pawn), (Player::Black, Piece::Queen)] {
+            assert_eq!(board.check_move(mreq_new_with_promote(a7, a8, &Some(promote))), Err(MoveError::InvalidPromotion));
+        }
+        // a promotion piece on a move that does not promote.
+        let e1 = board.pos_from_string("e1").unwrap();
+        let e2 = board.pos_from_string("e2").unwrap();
+        assert_eq!(board.check_move(mreq_new_with_promote(e1, e2, &Some((Player::White, Piece::Queen)))), Err(MoveError::InvalidPromotion));
+    }
+
+    #[test]
+    fn drops() {
+        let board = ChessBoard::new();
+        let e4 = board.pos_from_string("e4").unwrap();
+        assert_eq!(board.check_move(mreq_new_drop(e4, &Some((Player::White, Piece::Knight)))), Err(MoveError::IllegalDrop(e4)));
+
+        // white has a knight in the pocket after taking one.
+        let mut board = ChessBoard::new_variant(Variant::Crazyhouse);
+        play(&mut board, &["e2e4", "g8f6", "e4e5", "f6e4", "d2d3", "e4c3", "b2c3", "b8c6"]);
+        let knight = Some((Player::White, Piece::Knight));
+        assert_eq!(board.check_move(mreq_new_drop(e4, &knight)), Ok(()));
+        let e5 = board.pos_from_string("e5").unwrap();
+        assert_eq!(board.check_move(mreq_new_drop(e5, &knight)), Err(MoveError::IllegalDrop(e5)));
+        assert_eq!(board.check_move(mreq_new_drop(e4, &Some((Player::White, Piece::Queen)))), Err(MoveError::IllegalDrop(e4)));
+        let a8 = board.pos_from_string("a8").unwrap();
+        assert_eq!(board.check_move(mreq_new_drop(a8, &Some((Player::Black, Piece::Knight)))), Err(MoveError::IllegalDrop(a8)));
+    }
+
+    #[test]
+    fn forbidden_by_variant() {
+        // a capture is forced in antichess.
+        let mut board = ChessBoard::new_variant(Variant::Antichess);
+        play(&mut board, &["e2e4", "d7d5"]);
+        assert_eq!(board.check_move(request(&board, "g1f3")), Err(MoveError::ForbiddenByVariant));
+        assert_eq!(board.check_move(request(&board, "e4d5")), Ok(()));
+    }
+
+    #[test]
+    fn game_over() {
+        let board = ChessBoard::from_variant_fen("4k3/8/8/8/4K3/8/8/8 b - - 0 1", Variant::KingOfTheHill).unwrap();
+        assert_eq!(board.check_move(request(&board, "e8d8")), Err(MoveError::GameOver));
+    }
+}
@@ -1,17 +1,31 @@
 #[macro_use]
 pub mod macros;
+mod zobrist;
+pub mod epd;
+pub mod variant;
+pub mod setup;
+pub mod legality;
 
-const SIZE: usize = 8;
-const SIZE_2: usize = SIZE * SIZE;
+use variant::Variant;
+
+// squares are numbered rank by rank from a1, files * ranks of them; boards are 8x8 unless set up by FEN, e.g. 10x8 for
+// Capablanca chess. a square fits in the 7 bits of a move request.
+pub const MAX_SQUARES: usize = 128;
+// files are named a to p.
+const MAX_FILES: usize = 16;
+const MAX_RANKS: usize = 16;
+const MIN_SIZE: usize = 4;
 const WHITE_KING_SIDE: usize = 0;
 const WHITE_QUEEN_SIDE: usize = 1;
 const BLACK_KING_SIDE: usize = 2;
 const BLACK_QUEEN_SIDE: usize = 3;
 
-const WHITE_KING_SIDE_POS: usize = 6;
-const WHITE_QUEEN_SIDE_POS: usize = 2;
-const BLACK_KING_SIDE_POS: usize = 62;
-const BLACK_QUEEN_SIDE_POS: usize = 58;
+// files the king and rook end up on after castling, counted from the queen side or (`_FROM_END`) the king side, in
+// standard chess and Chess960 alike: g and f, c and d on 8 files, i and h, c and d on 10.
+const KING_SIDE_KING_FILE_FROM_END: usize = 2;
+const KING_SIDE_ROOK_FILE_FROM_END: usize = 3;
+const QUEEN_SIDE_KING_FILE: usize = 2;
+const QUEEN_SIDE_ROOK_FILE: usize = 3;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Piece {
@@ -20,7 +34,10 @@ pub enum Piece {
     Knight,
     Bishop,
     Queen,
-    King
+    King,
+    // compound pieces of Capablanca chess: bishop and knight, rook and knight.
+    Archbishop,
+    Chancellor,
 }
 
 fn piece_from_u8(value: u8) -> Piece {
@@ -29,6 +46,7 @@ fn piece_from_u8(value: u8) -> Piece {
 
 type Cell = Option<(Player, Piece)>;
 const PLAYER_FLAG: u8 = 8;
+// 0 for an empty square, 1 to 8 for white pieces and 9 to 16 for black ones, so 5 bits.
 pub fn cell_to_u8(cell: &Cell) -> u8 {
     match cell {
         None => 0,
@@ -39,11 +57,58 @@ pub fn cell_from_u8(cell: u8) -> Cell {
     if cell == 0 {
         return None;
     }
-    let player = if cell & PLAYER_FLAG == 0 { Player::White } else { Player::Black };
+    let player = if (cell - 1) & PLAYER_FLAG == 0 { Player::White } else { Player::Black };
     let piece = piece_from_u8((cell - 1) & !PLAYER_FLAG);
     Some((player, piece))
 }
 
+pub fn cell_to_char(cell: &Cell) -> char {
+    match cell {
+        None => '.',
+        Some((player, piece)) => {
+            let c = match piece {
+                Piece::Pawn => 'p',
+                Piece::Rook => 'r',
+                Piece::Knight => 'n',
+                Piece::Bishop => 'b',
+                Piece::Queen => 'q',
+                Piece::King => 'k',
+                Piece::Archbishop => 'a',
+                Piece::Chancellor => 'c',
+            };
+            if *player == Player::White { c.to_ascii_uppercase() } else { c }
+        }
+    }
+}
+pub fn cell_from_char(c: char) -> Option<Cell> {
+    let piece = match c.to_ascii_lowercase() {
+        'p' => Piece::Pawn,
+        'r' => Piece::Rook,
+        'n' => Piece::Knight,
+        'b' => Piece::Bishop,
+        'q' => Piece::Queen,
+        'k' => Piece::King,
+        'a' => Piece::Archbishop,
+        'c' => Piece::Chancellor,
+        _ => return None,
+    };
+    let player = if c.is_ascii_uppercase() { Player::White } else { Player::Black };
+    Some(Some((player, piece)))
+}
+
+// name of a square on a board with `files` files, e.g. e4 or j10.
+fn square_to_string(pos: usize, files: usize) -> String {
+    format!("{}{}", (b'a' + (pos % files) as u8) as char, pos / files + 1)
+}
+fn square_from_string(s: &str, files: usize, ranks: usize) -> Option<usize> {
+    let file = s.chars().next().filter(|c| c.is_ascii_lowercase())? as usize - 'a' as usize;
+    let rank: usize = s[1..].parse().ok().filter(|r| (1..=ranks).contains(r))?;
+    if file >= files || s[1..].starts_with('0') {
+        return None;
+    }
+    Some((rank - 1) * files + file)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Player {
     White,
@@ -60,31 +125,54 @@ impl Player {
 
 
 
-pub type MoveRequest = u16;
+// source in bits 0-6, destination in bits 7-13, the piece promoted to (as cell_to_u8) in bits 14-18.
+pub type MoveRequest = u32;
 
 pub const MOVES_UNCHECKED_LEADER: MoveRequest = 0;
 pub const MOVES_CHECKED_LEADER: MoveRequest = 1;
 
 pub fn mreq_new(pos0: usize, pos1: usize) -> MoveRequest {
-    (pos0 as MoveRequest) | ((pos1 << 6) as MoveRequest)
+    (pos0 as MoveRequest) | ((pos1 << POS_1_SHIFT) as MoveRequest)
 }
 
 pub fn mreq_new_with_promote(pos0: usize, pos1: usize, promote: &Cell) -> MoveRequest {
-    (pos0 as MoveRequest) | ((pos1 << 6) as MoveRequest) | ((cell_to_u8(promote) as MoveRequest) << 12)
+    mreq_new(pos0, pos1) | ((cell_to_u8(promote) as MoveRequest) << PROMOTE_SHIFT)
+}
+
+// a piece dropped from the pocket in crazyhouse is encoded as a move from the square to itself, promoting to the piece.
+pub fn mreq_new_drop(pos: usize, piece: &Cell) -> MoveRequest {
+    mreq_new_with_promote(pos, pos, piece)
 }
 
-pub type MoveResult = u32;
+pub fn mreq_is_drop(mreq: MoveRequest) -> bool {
+    get_pos0!(mreq) == get_pos1!(mreq) && get_promote!(mreq).is_some()
+}
+
+// the move request in the low 19 bits, then what undo_move needs to take it back. the low 32 bits are all but atomic.
+pub type MoveResult = u128;
 
-pub const POS_0_FLAG: u32 = 0x3f;
-pub const POS_1_FLAG: u32 = 0xfc0;
-pub const EN_PASSANT_FLAG: u32 = 0x100000;
-pub const CASTLE_FLAG: u32 = 0x200000;
-pub const PREV_CASTLE_RIGHT_WK: u32 = 0x400000;
-pub const PREV_CASTLE_RIGHT_WQ: u32 = 0x800000;
-pub const PREV_CASTLE_RIGHT_BK: u32 = 0x1000000;
-pub const PREV_CASTLE_RIGHT_BQ: u32 = 0x2000000;
-pub const PROMOTE_FLAG: u32 = 0xf000;
-pub const CAPTURE_FLAG: u32 = 0xf0000;
+pub const POS_0_FLAG: MoveResult = 0x7f;
+pub const POS_1_FLAG: MoveResult = 0x3f80;
+pub const POS_1_SHIFT: usize = 7;
+pub const PROMOTE_FLAG: MoveResult = 0x7c000;
+pub const PROMOTE_SHIFT: usize = 14;
+pub const CAPTURE_FLAG: MoveResult = 0xf80000;
+pub const CAPTURE_SHIFT: usize = 19;
+pub const EN_PASSANT_FLAG: MoveResult = 0x1000000;
+pub const CASTLE_FLAG: MoveResult = 0x2000000;
+pub const PREV_CASTLE_RIGHT_WK: MoveResult = 0x4000000;
+pub const PREV_CASTLE_RIGHT_WQ: MoveResult = 0x8000000;
+pub const PREV_CASTLE_RIGHT_BK: MoveResult = 0x10000000;
+pub const PREV_CASTLE_RIGHT_BQ: MoveResult = 0x20000000;
+// the move gave a check counted in three-check.
+pub const GAVE_CHECK_FLAG: MoveResult = 0x40000000;
+// the captured piece had been promoted, so it went to the pocket as a pawn in crazyhouse.
+pub const CAPTURED_PROMOTED_FLAG: MoveResult = 0x80000000;
+// in atomic, the capturing piece, exploded along with the pieces around the destination.
+pub const EXPLODED_CAPTURER_FLAG: MoveResult = 0x1f00000000;
+pub const EXPLODED_CAPTURER_SHIFT: usize = 32;
+// in atomic, the pieces exploded around the destination, 5 bits each (as cell_to_u8) from here, see `neighbors`.
+pub const EXPLODED_SHIFT: usize = 37;
 
 pub fn mres_new(mreq: MoveRequest) -> MoveResult {
     mreq as MoveResult
@@ -92,12 +180,12 @@ pub fn mres_new(mreq: MoveRequest) -> MoveResult {
 
 pub fn mres_set_promote(res: &mut MoveResult, promote: &Cell) {
     *res &= !PROMOTE_FLAG;
-    *res |= (cell_to_u8(promote) as u32) << 12;
+    *res |= (cell_to_u8(promote) as MoveResult) << PROMOTE_SHIFT;
 }
 
 pub fn mres_set_capture(res: &mut MoveResult, capture: &Cell) {
     *res &= !CAPTURE_FLAG;
-    *res |= (cell_to_u8(capture) as u32) << 16;
+    *res |= (cell_to_u8(capture) as MoveResult) << CAPTURE_SHIFT;
 }
 
 pub fn mres_set_en_passant(res: &mut MoveResult) {
@@ -109,7 +197,7 @@ pub fn mres_set_castle(res: &mut MoveResult) {
 }
 
 pub fn mres_get_capture(res: MoveResult) -> Cell {
-    cell_from_u8(((res & CAPTURE_FLAG) >> 16) as u8)
+    cell_from_u8(((res & CAPTURE_FLAG) >> CAPTURE_SHIFT) as u8)
 }
 
 pub fn mres_get_en_passant(res: MoveResult) -> bool {
@@ -139,65 +227,645 @@ pub fn mres_get_prev_castle_right_bq(res: MoveResult) -> bool {
 
 #[derive(Clone, Copy)]
 pub struct ChessBoard {
-    pub board: [Cell; SIZE_2],
+    pub board: [Cell; MAX_SQUARES], // only the first files * ranks squares are on the board.
     pub player: Player,
+    files: usize,
+    ranks: usize,
     castle_rights: [bool; 4], // [0] = white king side, [1] = white queen side, [2] = black king side, [3] = black queen side
+    castle_files: [usize; 4], // starting file of the rook of each castling right, the corners unless in Chess960.
+    chess960: bool, // castling is encoded as the king taking its own rook, and FEN uses X-FEN castling rights.
+    variant: Variant,
+    checks: [u8; 2], // checks given by white and black, in three-check.
+    pockets: [[u8; 8]; 2], // pieces in hand of white and black in crazyhouse, indexed by `Piece`.
+    promoted: u128, // squares of pieces that were promoted from a pawn, get i-th by ((x >> i) & 1).
     en_passant: Option<usize>, // the position of possible en passant.
     half_move: usize,
     full_move: usize
 }
+impl Default for ChessBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl ChessBoard {
     pub fn new() -> Self {
         use Player::*;
         use Piece::*;
-        ChessBoard {
-            board: [Some((White, Rook)), Some((White, Knight)), Some((White, Bishop)), Some((White, Queen)), Some((White, King)), Some((White, Bishop)), Some((White, Knight)), Some((White, Rook)),
+        let mut board = [None; MAX_SQUARES];
+        board[..64].copy_from_slice(&[Some((White, Rook)), Some((White, Knight)), Some((White, Bishop)), Some((White, Queen)), Some((White, King)), Some((White, Bishop)), Some((White, Knight)), Some((White, Rook)),
                     Some((White, Pawn)), Some((White, Pawn)),   Some((White, Pawn)),   Some((White, Pawn)),  Some((White, Pawn)), Some((White, Pawn)),   Some((White, Pawn)),   Some((White, Pawn)),
                     None,        None,          None,          None,         None,        None,          None,          None,
                     None,        None,          None,          None,         None,        None,          None,          None,
                     None,        None,          None,          None,         None,        None,          None,          None,
                     None,        None,          None,          None,         None,        None,          None,          None,
                     Some((Black, Pawn)), Some((Black, Pawn)),   Some((Black, Pawn)),   Some((Black, Pawn)),  Some((Black, Pawn)), Some((Black, Pawn)),   Some((Black, Pawn)),   Some((Black, Pawn)),
-                    Some((Black, Rook)), Some((Black, Knight)), Some((Black, Bishop)), Some((Black, Queen)), Some((Black, King)), Some((Black, Bishop)), Some((Black, Knight)), Some((Black, Rook))],
+                    Some((Black, Rook)), Some((Black, Knight)), Some((Black, Bishop)), Some((Black, Queen)), Some((Black, King)), Some((Black, Bishop)), Some((Black, Knight)), Some((Black, Rook))]);
+        ChessBoard {
+            board,
             player: Player::White,
+            files: 8,
+            ranks: 8,
             castle_rights: [true, true, true, true],
+            castle_files: [7, 0, 7, 0],
+            chess960: false,
+            variant: Variant::Standard,
+            checks: [0, 0],
+            pockets: [[0; 8]; 2],
+            promoted: 0,
             en_passant: Option::None,
             half_move: 0,
             full_move: 1
         }
     }
+
     /**
-     * Serialize the chessboard in format ([x1, x2] is one byte, x1 is high four bits, x2 is low four bits)
-     * [B1,A1], [D1,C1], [F1,E1], [H1,G1], [B2,A2], ..., [H8,G8], [En passant column(3bits),qkQK,(w/b)], [en passant possible(1bit), halfmove(7bits)], fullmove(16bits), little endian
+     * Chess960 start position by its standard (Scharnagl) index in 0..960; 518 is the usual start position.
      */
-    pub fn serialize(&self) -> [u8; 36] { 
-        let mut ans = [0u8; 36];
-        for i in 0..32 {
-            let c1 = &self.board[2 * i];
-            let c2 = &self.board[2 * i + 1];
-            let tmp: &mut u8 = &mut ans[i];
-            *tmp |= cell_to_u8(c1);
-            *tmp |= cell_to_u8(c2) << 4;
-        }
-        ans[32] = (self.player as u8) | ((self.castle_rights[WHITE_KING_SIDE] as u8) << 1) | ((self.castle_rights[WHITE_QUEEN_SIDE] as u8) << 2) | ((self.castle_rights[BLACK_KING_SIDE] as u8) << 3) | ((self.castle_rights[BLACK_QUEEN_SIDE] as u8) << 4);
-        ans[33] = self.half_move as u8 & 0x7f; // no more than 128 half moves.
+    pub fn new_chess960(index: usize) -> Self {
+        use Piece::*;
+        const FILES: usize = 8;
+        let mut n = index % 960;
+        let mut rank: [Option<Piece>; FILES] = [None; FILES];
+        rank[2 * (n % 4) + 1] = Some(Bishop);
+        n /= 4;
+        rank[2 * (n % 4)] = Some(Bishop);
+        n /= 4;
+        // the queen and knights go to the n-th empty file
+        let place = |rank: &mut [Option<Piece>; FILES], k: usize, piece: Piece| {
+            let file = (0..FILES).filter(|j| rank[*j].is_none()).nth(k).unwrap();
+            rank[file] = Some(piece);
+        };
+        place(&mut rank, n % 6, Queen);
+        n /= 6;
+        const KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+        let (k1, k2) = KNIGHTS[n];
+        // the second knight is placed first so that the index of the first one is unaffected
+        place(&mut rank, k2, Knight);
+        place(&mut rank, k1, Knight);
+        place(&mut rank, 0, Rook);
+        place(&mut rank, 0, King);
+        place(&mut rank, 0, Rook);
+        let mut ans = ChessBoard::new();
+        for (j, piece) in rank.iter().enumerate() {
+            ans.board[j] = Some((Player::White, piece.unwrap()));
+            ans.board[7 * FILES + j] = Some((Player::Black, piece.unwrap()));
+        }
+        let rooks: Vec<usize> = (0..FILES).filter(|j| rank[*j] == Some(Rook)).collect();
+        ans.castle_files = [rooks[1], rooks[0], rooks[1], rooks[0]];
+        ans.chess960 = true;
+        ans
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    pub fn ranks(&self) -> usize {
+        self.ranks
+    }
+
+    // number of squares on the board, which are 0 to squares() - 1.
+    pub fn squares(&self) -> usize {
+        self.files * self.ranks
+    }
+
+    // the first square of the last rank
+    fn last_rank(&self) -> usize {
+        self.squares() - self.files
+    }
+
+    pub fn pos_to_string(&self, pos: usize) -> String {
+        square_to_string(pos, self.files)
+    }
+
+    pub fn pos_from_string(&self, s: &str) -> Option<usize> {
+        square_from_string(s, self.files, self.ranks)
+    }
+
+    // long algebraic notation as used by UCI, e.g. e2e4, e7e8q, and N@f7 for drops
+    pub fn mreq_to_uci(&self, mreq: MoveRequest) -> String {
+        if mreq_is_drop(mreq) {
+            return format!("{}@{}", cell_to_char(&get_promote!(mreq).map(|c| (Player::White, c.1))), self.pos_to_string(get_pos1!(mreq)));
+        }
+        let mut ans = self.pos_to_string(get_pos0!(mreq)) + &self.pos_to_string(get_pos1!(mreq));
+        if let Some(cell) = get_promote!(mreq) {
+            ans.push(cell_to_char(&Some((Player::Black, cell.1))));
+        }
+        ans
+    }
+
+    // switch the castling move encoding and FEN output between standard chess and Chess960.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    // (king from, king to, rook from, rook to) of castling with the given right, if the king and rook are in place.
+    fn castle_squares(&self, side: usize) -> Option<(usize, usize, usize, usize)> {
+        let (player, base) = if side == WHITE_KING_SIDE || side == WHITE_QUEEN_SIDE { (Player::White, 0) } else { (Player::Black, self.last_rank()) };
+        let king_side = side == WHITE_KING_SIDE || side == BLACK_KING_SIDE;
+        let king_from = (base..base + self.files).find(|p| self.board[*p] == Some((player, Piece::King)))?;
+        let rook_from = base + self.castle_files[side];
+        if self.board[rook_from] != Some((player, Piece::Rook)) || (rook_from > king_from) != king_side {
+            return None;
+        }
+        let (king_to, rook_to) = self.castle_destinations(base, king_side);
+        Some((king_from, king_to, rook_from, rook_to))
+    }
+
+    // (king to, rook to) of castling on the rank starting at `base`.
+    fn castle_destinations(&self, base: usize, king_side: bool) -> (usize, usize) {
+        if king_side {
+            (base + self.files - KING_SIDE_KING_FILE_FROM_END, base + self.files - KING_SIDE_ROOK_FILE_FROM_END)
+        } else {
+            (base + QUEEN_SIDE_KING_FILE, base + QUEEN_SIDE_ROOK_FILE)
+        }
+    }
+
+    // (king from, king to, rook from, rook to) if `mreq` is a castling move of the player to move.
+    pub fn castling(&self, mreq: MoveRequest) -> Option<(usize, usize, usize, usize)> {
+        let pos0 = get_pos0!(mreq);
+        let pos1 = get_pos1!(mreq);
+        if self.board[pos0] != Some((self.player, Piece::King)) {
+            return None;
+        }
+        let sides = match self.player {
+            Player::White => [WHITE_KING_SIDE, WHITE_QUEEN_SIDE],
+            Player::Black => [BLACK_KING_SIDE, BLACK_QUEEN_SIDE],
+        };
+        sides.into_iter().filter(|side| self.castle_rights[*side]).filter_map(|side| self.castle_squares(side)).find(|(king_from, king_to, rook_from, _)| {
+            *king_from == pos0 && if self.chess960 { pos1 == *rook_from } else { pos1 == *king_to && pos0.abs_diff(pos1) >= 2 }
+        })
+    }
+    /**
+     * Serialize the chessboard in format
+     * files, ranks, [qkQK,(w/b)], halfmove (at most 255), en passant square + 1 (0 for none), fullmove (16 bits, little
+     * endian), then cell_to_u8 of each square: A1, B1, ..., A2, ...
+     */
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ans = vec![self.files as u8, self.ranks as u8];
+        ans.push((self.player as u8) | ((self.castle_rights[WHITE_KING_SIDE] as u8) << 1) | ((self.castle_rights[WHITE_QUEEN_SIDE] as u8) << 2) | ((self.castle_rights[BLACK_KING_SIDE] as u8) << 3) | ((self.castle_rights[BLACK_QUEEN_SIDE] as u8) << 4));
+        ans.push(self.half_move.min(u8::MAX as usize) as u8);
+        ans.push(self.en_passant.map_or(0, |pos| pos as u8 + 1));
+        ans.extend_from_slice(&(self.full_move as u16).to_le_bytes());
+        ans.extend(self.board[..self.squares()].iter().map(cell_to_u8));
+        ans
+    }
+
+    /**
+     * Parse a position in Forsyth-Edwards Notation. The halfmove and fullmove fields may be omitted (as in EPD).
+     */
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        Self::from_variant_fen(fen, Variant::Standard)
+    }
+
+    /**
+     * Parse a position of the given variant. Three-check positions may have the remaining checks of each player
+     * before the halfmove clock, e.g. `3+2`, and crazyhouse positions the pockets after the pieces, e.g. `[Qn]`, with
+     * promoted pieces followed by `~`; either implies its variant. The size of the board follows from the placement,
+     * e.g. `rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR` for Capablanca chess.
+     */
+    pub fn from_variant_fen(fen: &str, mut variant: Variant) -> Result<Self, String> {
+        let mut fields: Vec<&str> = fen.split_whitespace().collect();
+        let mut checks = [0, 0];
+        if let Some((white, black)) = fields.get(4).and_then(|f| f.split_once('+')) {
+            let remaining = |x: &str| x.parse::<u8>().ok().filter(|x| *x <= 3).ok_or(format!("invalid remaining checks '{}'", fields[4]));
+            checks = [3 - remaining(white)?, 3 - remaining(black)?];
+            variant = Variant::ThreeCheck;
+            fields.remove(4);
+        }
+        if fields.len() < 4 {
+            return Err(format!("expected at least 4 fields, got {}", fields.len()));
+        }
+        let mut board = [None; MAX_SQUARES];
+        let mut pockets = [[0; 8]; 2];
+        let mut promoted = 0;
+        let placement = match fields[0].split_once('[') {
+            Some((placement, pocket)) => {
+                variant = Variant::Crazyhouse;
+                for c in pocket.strip_suffix(']').ok_or("unterminated pocket".to_string())?.chars() {
+                    match cell_from_char(c) {
+                        Some(Some((player, piece))) if piece != Piece::King => pockets[player as usize][piece as usize] += 1,
+                        _ => return Err(format!("invalid piece '{}' in pocket", c)),
+                    }
+                }
+                placement
+            },
+            None => fields[0],
+        };
+        // each rank as (cell, promoted) from the a-file, top rank first; empty squares may be counted in several digits.
+        let rows: Vec<&str> = placement.split('/').collect();
+        let ranks = rows.len();
+        let mut cells: Vec<Vec<(Cell, bool)>> = vec![];
+        for (k, row) in rows.iter().enumerate() {
+            let mut rank = vec![];
+            let mut empty = 0;
+            for c in row.chars() {
+                if let Some(d) = c.to_digit(10) {
+                    empty = empty * 10 + d as usize;
+                    if empty > MAX_FILES {
+                        return Err(format!("rank {} is too long", ranks - k));
+                    }
+                    continue;
+                }
+                rank.extend(std::iter::repeat_n((None, false), empty));
+                empty = 0;
+                if c == '~' {
+                    match rank.last_mut() {
+                        Some((Some(_), promoted)) => *promoted = true,
+                        _ => return Err(format!("'~' without a piece on rank {}", ranks - k)),
+                    }
+                } else {
+                    rank.push((cell_from_char(c).ok_or(format!("invalid piece '{}'", c))?, false));
+                }
+            }
+            rank.extend(std::iter::repeat_n((None, false), empty));
+            cells.push(rank);
+        }
+        let files = cells[0].len();
+        if !(MIN_SIZE..=MAX_FILES).contains(&files) || !(MIN_SIZE..=MAX_RANKS).contains(&ranks) || files * ranks > MAX_SQUARES {
+            return Err(format!("a board of {} files and {} ranks is not supported", files, ranks));
+        }
+        for (k, rank) in cells.iter().enumerate() {
+            let i = ranks - 1 - k;
+            if rank.len() != files {
+                return Err(format!("rank {} has {} files, expected {}", i + 1, rank.len(), files));
+            }
+            for (j, (cell, is_promoted)) in rank.iter().enumerate() {
+                board[i * files + j] = *cell;
+                if *is_promoted {
+                    promoted |= 1 << (i * files + j);
+                }
+            }
+        }
+        let player = match fields[1] {
+            "w" => Player::White,
+            "b" => Player::Black,
+            other => return Err(format!("invalid side to move '{}'", other)),
+        };
+        // castling rights: KQkq for the outermost rook on each side of the king (X-FEN), or the file of the rook
+        // (Shredder-FEN, e.g. HAha).
+        let mut castle_rights = [false; 4];
+        let mut castle_files = [files - 1, 0, files - 1, 0];
+        let mut chess960 = false;
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (player, base) = if c.is_ascii_uppercase() { (Player::White, 0) } else { (Player::Black, (ranks - 1) * files) };
+                let (king_side, queen_side) = if player == Player::White { (WHITE_KING_SIDE, WHITE_QUEEN_SIDE) } else { (BLACK_KING_SIDE, BLACK_QUEEN_SIDE) };
+                let king_file = (0..files).find(|j| board[base + j] == Some((player, Piece::King)))
+                    .ok_or(format!("castling right '{}' without a king on the back rank", c))?;
+                let is_rook = |j: &usize| *j < files && board[base + j] == Some((player, Piece::Rook));
+                let (side, file) = match c.to_ascii_lowercase() {
+                    'k' => (king_side, (king_file + 1..files).rev().find(is_rook)),
+                    'q' => (queen_side, (0..king_file).find(is_rook)),
+                    f @ 'a'..='p' => {
+                        let file = f as usize - 'a' as usize;
+                        chess960 = true;
+                        (if file > king_file { king_side } else { queen_side }, Some(file))
+                    },
+                    _ => return Err(format!("invalid castling right '{}'", c)),
+                };
+                let file = file.ok_or(format!("castling right '{}' without a rook", c))?;
+                if file == king_file || !is_rook(&file) {
+                    return Err(format!("castling right '{}' without a rook", c));
+                }
+                // on boards narrower than 8 files the king would castle by a single step, like an ordinary move.
+                if king_file != files / 2 || (file != 0 && file != files - 1) || files < 8 {
+                    chess960 = true;
+                }
+                castle_rights[side] = true;
+                castle_files[side] = file;
+            }
+        }
+        let en_passant = if fields[3] == "-" {
+            None
+        } else {
+            Some(square_from_string(fields[3], files, ranks).ok_or(format!("invalid en passant square '{}'", fields[3]))?)
+        };
+        let half_move = match fields.get(4) {
+            Some(x) => x.parse().map_err(|_| format!("invalid halfmove clock '{}'", x))?,
+            None => 0,
+        };
+        let full_move = match fields.get(5) {
+            Some(x) => x.parse().map_err(|_| format!("invalid fullmove number '{}'", x))?,
+            None => 1,
+        };
+        let mut ans = ChessBoard {
+            board,
+            player,
+            files,
+            ranks,
+            castle_rights,
+            castle_files,
+            chess960,
+            variant: Variant::Standard,
+            checks: [0, 0],
+            pockets: [[0; 8]; 2],
+            promoted: 0,
+            en_passant,
+            half_move,
+            full_move
+        };
+        ans.set_variant(variant);
+        ans.checks = checks;
+        ans.pockets = pockets;
+        ans.promoted = promoted;
+        Ok(ans)
+    }
+
+    // castling rights are written as KQkq, except in Chess960 where inner rooks are named by their file (X-FEN).
+    pub fn to_fen(&self) -> String {
+        self.fen_with_castling(false)
+    }
+
+    // castling rights are written as the files of the rooks, e.g. HAha.
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen_with_castling(true)
+    }
+
+    fn fen_with_castling(&self, shredder: bool) -> String {
+        let mut ans = String::new();
+        for i in (0..self.ranks).rev() {
+            let mut empty = 0;
+            for j in 0..self.files {
+                match self.board[i * self.files + j] {
+                    None => empty += 1,
+                    cell => {
+                        if empty > 0 {
+                            ans.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        ans.push(cell_to_char(&cell));
+                        if self.variant == Variant::Crazyhouse && self.promoted >> (i * self.files + j) & 1 == 1 {
+                            ans.push('~');
+                        }
+                    }
+                }
+            }
+            if empty > 0 {
+                ans.push_str(&empty.to_string());
+            }
+            if i != 0 {
+                ans.push('/');
+            }
+        }
+        if self.variant == Variant::Crazyhouse {
+            ans.push('[');
+            for player in [Player::White, Player::Black] {
+                for piece in variant::POCKET_PIECES {
+                    for _ in 0..self.pockets[player as usize][piece as usize] {
+                        ans.push(cell_to_char(&Some((player, piece))));
+                    }
+                }
+            }
+            ans.push(']');
+        }
+        ans.push_str(if self.player == Player::White { " w " } else { " b " });
+        let mut castle = String::new();
+        for (side, letter) in [(WHITE_KING_SIDE, 'K'), (WHITE_QUEEN_SIDE, 'Q'), (BLACK_KING_SIDE, 'k'), (BLACK_QUEEN_SIDE, 'q')] {
+            if !self.castle_rights[side] {
+                continue;
+            }
+            let Some((_, _, rook_from, _)) = self.castle_squares(side) else { continue };
+            // another rook further out on the same side makes KQkq ambiguous
+            let base = rook_from / self.files * self.files;
+            let outer = if side == WHITE_KING_SIDE || side == BLACK_KING_SIDE { rook_from + 1..base + self.files } else { base..rook_from };
+            let ambiguous = outer.into_iter().any(|p| self.board[p] == self.board[rook_from]);
+            let file = (b'a' + (rook_from % self.files) as u8) as char;
+            castle.push(if shredder || (self.chess960 && ambiguous) {
+                if letter.is_ascii_uppercase() { file.to_ascii_uppercase() } else { file }
+            } else {
+                letter
+            });
+        }
+        if castle.is_empty() { castle.push('-'); }
+        ans.push_str(&castle);
+        ans.push(' ');
+        match self.en_passant {
+            Some(pos) => ans.push_str(&self.pos_to_string(pos)),
+            None => ans.push('-'),
+        }
+        if self.variant == Variant::ThreeCheck {
+            ans.push_str(&format!(" {}+{}", 3 - self.checks[0], 3 - self.checks[1]));
+        }
+        ans.push_str(&format!(" {} {}", self.half_move, self.full_move));
+        ans
+    }
+
+    // Zobrist hash of the position; move counters are not part of it.
+    pub fn hash(&self) -> u64 {
+        let mut ans = 0;
+        for (pos, cell) in self.board.iter().enumerate() {
+            if cell.is_some() {
+                ans ^= zobrist::PIECE_KEYS[cell_to_u8(cell) as usize * MAX_SQUARES + pos];
+            }
+        }
+        for (i, right) in self.castle_rights.iter().enumerate() {
+            if *right {
+                ans ^= zobrist::CASTLE_KEYS[i];
+            }
+        }
         if let Some(pos) = self.en_passant {
-            ans[32] |= ((pos as u8) & 0x7) << 5;
-            ans[33] |= 1 << 7;
+            ans ^= zobrist::EN_PASSANT_KEYS[pos % self.files];
+        }
+        if self.player == Player::Black {
+            ans ^= zobrist::BLACK_TO_MOVE_KEY;
+        }
+        for (player, checks) in self.checks.iter().enumerate() {
+            if *checks > 0 {
+                ans ^= zobrist::CHECK_KEYS[player * 4 + (*checks as usize).min(3)];
+            }
+        }
+        for (i, count) in self.pockets.iter().flatten().enumerate() {
+            if *count > 0 {
+                ans ^= zobrist::POCKET_KEYS[i * 32 + (*count as usize).min(31)];
+            }
+        }
+        let mut promoted = if self.variant == Variant::Crazyhouse { self.promoted } else { 0 };
+        while promoted != 0 {
+            ans ^= zobrist::PROMOTED_KEYS[promoted.trailing_zeros() as usize];
+            promoted &= promoted - 1;
+        }
+        ans
+    }
+
+    // number of half moves since the last capture or pawn move, for the fifty-move rule.
+    pub fn half_move(&self) -> usize {
+        self.half_move
+    }
+
+    pub fn full_move(&self) -> usize {
+        self.full_move
+    }
+
+    // pass the turn without moving, used by null move pruning.
+    pub fn do_null_move(&mut self) {
+        self.en_passant = None;
+        self.player = self.player.opponent();
+    }
+
+    // whether the player to move is in check; never in antichess, where the king is an ordinary piece, nor in atomic
+    // while the kings touch.
+    pub fn is_checked(&self) -> bool {
+        match self.variant {
+            Variant::Antichess => false,
+            Variant::Atomic if self.kings_adjacent() => false,
+            _ => self.get_attacking_range(self.player.opponent()).1,
+        }
+    }
+
+    // find the legal move written in long algebraic notation.
+    pub fn parse_uci_move(&self, s: &str) -> Option<MoveRequest> {
+        self.possible_moves().into_iter().skip(1).find(|m| self.mreq_to_uci(*m) == s)
+    }
+
+    // standard algebraic notation of a legal move, e.g. Nbd7, exd5, O-O, e8=Q+, N@f7
+    pub fn move_to_san(&self, mreq: MoveRequest) -> String {
+        let pos0 = get_pos0!(mreq);
+        let pos1 = get_pos1!(mreq);
+        let piece = self.board[pos0].map_or(Piece::Pawn, |c| c.1);
+        let mut ans = if mreq_is_drop(mreq) {
+            self.mreq_to_uci(mreq)
+        } else if let Some((king_from, _, rook_from, _)) = self.castling(mreq) {
+            if rook_from > king_from { "O-O".to_string() } else { "O-O-O".to_string() }
+        } else {
+            let capture = self.board[pos1].is_some() || (piece == Piece::Pawn && pos0 % self.files != pos1 % self.files);
+            let square = self.pos_to_string(pos1);
+            let mut ans = String::new();
+            if piece == Piece::Pawn {
+                if capture {
+                    ans.push_str(&self.pos_to_string(pos0)[..1]);
+                }
+            } else {
+                ans.push(cell_to_char(&Some((Player::White, piece))));
+                // other pieces of the same kind that can reach the destination
+                let others: Vec<usize> = self.possible_moves().into_iter().skip(1)
+                    .filter(|m| get_pos1!(*m) == pos1 && get_pos0!(*m) != pos0 && self.board[get_pos0!(*m)] == self.board[pos0])
+                    .map(|m| get_pos0!(m))
+                    .collect();
+                if !others.is_empty() {
+                    let from = self.pos_to_string(pos0);
+                    if others.iter().all(|p| p % self.files != pos0 % self.files) {
+                        ans.push_str(&from[..1]);
+                    } else if others.iter().all(|p| p / self.files != pos0 / self.files) {
+                        ans.push_str(&from[1..]);
+                    } else {
+                        ans.push_str(&from);
+                    }
+                }
+            }
+            if capture {
+                ans.push('x');
+            }
+            ans.push_str(&square);
+            if let Some(cell) = get_promote!(mreq) {
+                ans.push('=');
+                ans.push(cell_to_char(&Some((Player::White, cell.1))));
+            }
+            ans
+        };
+        let mut t = *self;
+        t.do_move(mreq);
+        if t.is_checked() {
+            ans.push(if t.possible_moves().len() == 1 { '#' } else { '+' });
         }
-        ans[34] = (self.full_move as u16 & 0xff) as u8;
-        ans[35] = (self.full_move as u16 >> 8) as u8;
         ans
     }
 
+    /**
+     * Find the legal move written in standard algebraic notation. Check marks, annotations and the promotion '=' are
+     * optional, long algebraic notation, "0-0" castling and pawn drops without the P (@e4) are accepted too.
+     */
+    pub fn parse_san(&self, s: &str) -> Option<MoveRequest> {
+        let normalize = |s: &str| -> String {
+            let s = s.trim();
+            let s = if s.starts_with("0-0") { s.replace('0', "O") } else { s.to_string() };
+            let s = if s.starts_with('@') { format!("P{}", s) } else { s };
+            s.chars().filter(|c| !matches!(c, '+' | '#' | '!' | '?' | '=')).collect()
+        };
+        let wanted = normalize(s);
+        let moves: Vec<MoveRequest> = self.possible_moves().into_iter().skip(1).collect();
+        moves.iter().copied().find(|m| normalize(&self.move_to_san(*m)) == wanted)
+            .or_else(|| moves.iter().copied().find(|m| self.mreq_to_uci(*m).eq_ignore_ascii_case(s.trim())))
+    }
+
+    // number of leaf nodes of the legal move tree of the given depth, for move generator verification.
+    pub fn perft(&self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.possible_moves();
+        if depth == 1 {
+            return moves.len() as u64 - 1;
+        }
+        moves.into_iter().skip(1).map(|m| {
+            let mut t = *self;
+            t.do_move(m);
+            t.perft(depth - 1)
+        }).sum()
+    }
+
     // return 
     pub fn do_move(&mut self, mreq: MoveRequest) -> MoveResult {
+        let mut ans = self.move_pieces(mreq);
+        if self.variant == Variant::ThreeCheck && self.is_checked() {
+            self.checks[self.player.opponent() as usize] += 1;
+            ans |= GAVE_CHECK_FLAG;
+        }
+        ans
+    }
+
+    // do_move without the bookkeeping of the variant.
+    fn move_pieces(&mut self, mreq: MoveRequest) -> MoveResult {
         // deal with special cases
         let mut ans: MoveResult = mres_new(mreq);
+        for (right, flag) in self.castle_rights.iter().zip([PREV_CASTLE_RIGHT_WK, PREV_CASTLE_RIGHT_WQ, PREV_CASTLE_RIGHT_BK, PREV_CASTLE_RIGHT_BQ]) {
+            if *right {
+                ans |= flag;
+            }
+        }
         self.half_move += 1;
-        let pos0 = (ans & POS_0_FLAG) as usize;
-        let pos1 = ((ans & POS_1_FLAG) >> 6) as usize;
-        let promote_to = cell_from_u8(((ans & PROMOTE_FLAG) >> 12) as u8);
+        let pos0 = get_pos0!(ans);
+        let pos1 = get_pos1!(ans);
+        let promote_to = get_promote!(ans);
+
+        if mreq_is_drop(mreq) {
+            let (player, piece) = promote_to.unwrap();
+            self.pockets[player as usize][piece as usize] -= 1;
+            self.board[pos1] = promote_to;
+            self.en_passant = None;
+            if self.player == Player::Black {
+                self.full_move += 1;
+            }
+            self.player = self.player.opponent();
+            return ans;
+        }
+
+        // castling, where the king may land on the rook's square or the other way round in Chess960
+        if let Some((king_from, king_to, rook_from, rook_to)) = self.castling(mreq) {
+            mres_set_castle(&mut ans);
+            self.board[king_from] = None;
+            self.board[rook_from] = None;
+            self.board[king_to] = Some((self.player, Piece::King));
+            self.board[rook_to] = Some((self.player, Piece::Rook));
+            match self.player {
+                Player::White => {
+                    self.castle_rights[WHITE_KING_SIDE] = false;
+                    self.castle_rights[WHITE_QUEEN_SIDE] = false;
+                },
+                Player::Black => {
+                    self.castle_rights[BLACK_KING_SIDE] = false;
+                    self.castle_rights[BLACK_QUEEN_SIDE] = false;
+                    self.full_move += 1;
+                }
+            }
+            self.en_passant = None;
+            self.player = self.player.opponent();
+            return ans;
+        }
 
         if self.board[pos0].unwrap().1 == Piece::Pawn {
             self.half_move = 0; // every pawn advance comes with a halfmove update
@@ -205,7 +873,7 @@ impl ChessBoard {
             // capture enemy
             if let Some(pos) = self.en_passant {
                 if pos == pos1 {
-                    let captured_pos = pos0 / SIZE * SIZE + pos1 % SIZE;
+                    let captured_pos = pos0 / self.files * self.files + pos1 % self.files;
                     mres_set_capture(&mut ans, &self.board[captured_pos]);
                     mres_set_en_passant(&mut ans);
                     self.board[captured_pos] = None;
@@ -214,15 +882,15 @@ impl ChessBoard {
             // update en passant of self
             self.en_passant = match self.player {
                 Player::White => {
-                    if pos0 / SIZE == 1 && pos1 - pos0 == 2 * SIZE {
-                        Some(pos0 + SIZE)
+                    if pos0 / self.files == 1 && pos1 - pos0 == 2 * self.files {
+                        Some(pos0 + self.files)
                     } else {
                         None
                     }
                 },
                 Player::Black => {
-                    if pos0 / SIZE == (SIZE - 2) && pos0 - pos1 == 2 * SIZE {
-                        Some(pos0 - SIZE)
+                    if pos0 / self.files == self.ranks - 2 && pos0 - pos1 == 2 * self.files {
+                        Some(pos0 - self.files)
                     } else {
                         None
                     }
@@ -231,14 +899,14 @@ impl ChessBoard {
             // 2 promote
             match self.player {
                 Player::White => {
-                    if pos1 / SIZE == (SIZE - 1) {
+                    if pos1 >= self.last_rank() {
                         // update pos0 since we will update later.
                         mres_set_promote(&mut ans, &promote_to);
                         self.board[pos0] = promote_to;
                     }
                 },
                 Player::Black => {
-                    if pos1 / SIZE == 0 {
+                    if pos1 < self.files {
                         mres_set_promote(&mut ans, &promote_to);
                         self.board[pos0] = promote_to;
                     }
@@ -247,96 +915,42 @@ impl ChessBoard {
         } else {
             self.en_passant = None;
         }
-        // 3. castle
-        // castle first
-        
-        match self.player {
-            Player::White => {
-                if self.castle_rights[WHITE_KING_SIDE] && pos0 == WHITE_KING_SIDE_POS - 2 && pos1 == WHITE_KING_SIDE_POS {
-
-                    self.castle_rights[WHITE_KING_SIDE] = false;
-                    self.castle_rights[WHITE_QUEEN_SIDE] = false;
-
-                    // move castle to 5
-                    mres_set_castle(&mut ans);
-                    self.board[WHITE_KING_SIDE_POS - 1] = self.board[7];
-                    self.board[7] = None;
-
-                    // king will be automatically moved
-                }
-
-                if self.castle_rights[WHITE_QUEEN_SIDE] && pos0 == WHITE_QUEEN_SIDE_POS + 2 && pos1 == WHITE_QUEEN_SIDE_POS {
-                    
-                    self.castle_rights[WHITE_KING_SIDE] = false;
-                    self.castle_rights[WHITE_QUEEN_SIDE] = false;
-
-                    // move castle to 3
-                    mres_set_castle(&mut ans);
-                    self.board[WHITE_QUEEN_SIDE_POS + 1] = self.board[0];
-                    self.board[0] = None;
-
-                    // king will be automatically moved
-                }
-            },
-            Player::Black => {
-                if self.castle_rights[BLACK_KING_SIDE] && pos0 == BLACK_KING_SIDE_POS - 2 && pos1 == BLACK_KING_SIDE_POS {
-
-                    self.castle_rights[BLACK_KING_SIDE] = false;
-                    self.castle_rights[BLACK_QUEEN_SIDE] = false;
-
-                    // move castle to 61
-                    mres_set_castle(&mut ans);
-                    self.board[BLACK_KING_SIDE_POS - 1] = self.board[63];
-                    self.board[63] = None;
-
-                    // king will be automatically moved
-                }
-
-                if self.castle_rights[BLACK_QUEEN_SIDE] && pos0 == BLACK_QUEEN_SIDE_POS + 2 && pos1 == BLACK_QUEEN_SIDE_POS {
-                    self.castle_rights[WHITE_KING_SIDE] = false;
-                    self.castle_rights[WHITE_QUEEN_SIDE] = false;
-
-                    // move castle to 59
-                    mres_set_castle(&mut ans);
-                    self.board[BLACK_QUEEN_SIDE_POS + 1] = self.board[56];
-                    self.board[56] = None;
-
-                    // king will be automatically moved
-                }
-            }
-        }
-        // then check if this move make castle impossible. both source and destination to/from 0, 4, 7, 56, 60, 63
-        if self.castle_rights[WHITE_KING_SIDE] {
-            if pos0 == 4 || pos0 == 7 || pos1 == 4 || pos1 == 7 {
-                self.castle_rights[WHITE_KING_SIDE] = false;
+        // 3. castling rights are lost when the king or the rook moves, or the rook is captured.
+        for side in [WHITE_KING_SIDE, WHITE_QUEEN_SIDE, BLACK_KING_SIDE, BLACK_QUEEN_SIDE] {
+            let (player, base) = if side == WHITE_KING_SIDE || side == WHITE_QUEEN_SIDE { (Player::White, 0) } else { (Player::Black, self.last_rank()) };
+            let rook = base + self.castle_files[side];
+            if pos0 == rook || pos1 == rook || self.board[pos0] == Some((player, Piece::King)) {
+                self.castle_rights[side] = false;
             }
         }
-        if self.castle_rights[WHITE_QUEEN_SIDE] {
-            if pos0 == 0 || pos0 == 4 || pos1 == 0 || pos1 == 4 {
-                self.castle_rights[WHITE_QUEEN_SIDE] = false;
-            }
+        if self.board[pos1].is_some() {
+            self.half_move = 0;
+            mres_set_capture(&mut ans, &self.board[pos1]);
         }
-        if self.castle_rights[BLACK_KING_SIDE] {
-            if pos0 == 60 || pos0 == 63 || pos1 == 60 || pos1 == 63 {
-                self.castle_rights[BLACK_KING_SIDE] = false;
-            }
+        // promoted pieces move along, and are pocketed as pawns when captured in crazyhouse.
+        let captured_promoted = self.promoted >> pos1 & 1 == 1;
+        if captured_promoted {
+            ans |= CAPTURED_PROMOTED_FLAG;
         }
-        if self.castle_rights[BLACK_QUEEN_SIDE] {
-            if pos0 == 56 || pos0 == 60 || pos1 == 56 || pos1 == 60 {
-                self.castle_rights[BLACK_QUEEN_SIDE] = false;
-            }
+        let moved_promoted = self.promoted >> pos0 & 1 == 1 || get_promote!(ans).is_some();
+        self.promoted &= !(1 << pos0 | 1 << pos1);
+        if moved_promoted {
+            self.promoted |= 1 << pos1;
         }
-        if self.board[pos1] != None {
-            self.half_move = 0;
-            mres_set_capture(&mut ans, &self.board[pos1]);
+        if let (Variant::Crazyhouse, Some((_, piece))) = (self.variant, mres_get_capture(ans)) {
+            let piece = if captured_promoted { Piece::Pawn } else { piece };
+            self.pockets[self.player as usize][piece as usize] += 1;
         }
         self.board[pos1] = self.board[pos0];
         self.board[pos0] = None;
+        if self.variant == Variant::Atomic && mres_get_capture(ans).is_some() {
+            self.explode(pos1, &mut ans);
+        }
         if self.player == Player::Black {
             self.full_move += 1;
         }
         self.player = self.player.opponent();
-        return ans;
+        ans
     }
     
     // will not update full moves and half moves.
@@ -349,275 +963,297 @@ impl ChessBoard {
         let is_en_passant = mres_get_en_passant(mres);
 
         self.player = self.player.opponent();
+        if mres & GAVE_CHECK_FLAG != 0 {
+            self.checks[self.player as usize] -= 1;
+        }
+        self.castle_rights[WHITE_KING_SIDE] = mres_get_prev_castle_right_wk(mres);
+        self.castle_rights[WHITE_QUEEN_SIDE] = mres_get_prev_castle_right_wq(mres);
+        self.castle_rights[BLACK_KING_SIDE] = mres_get_prev_castle_right_bk(mres);
+        self.castle_rights[BLACK_QUEEN_SIDE] = mres_get_prev_castle_right_bq(mres);
+        self.en_passant = None;
+
+        if mreq_is_drop(mres as MoveRequest) {
+            self.board[pos1] = None;
+            self.pockets[self.player as usize][promote.unwrap().1 as usize] += 1;
+            return;
+        }
+        if is_castle {
+            // the king side rook is always on the king's right, whichever square encodes the move.
+            let side = match (self.player, pos1 > pos0) {
+                (Player::White, true) => WHITE_KING_SIDE,
+                (Player::White, false) => WHITE_QUEEN_SIDE,
+                (Player::Black, true) => BLACK_KING_SIDE,
+                (Player::Black, false) => BLACK_QUEEN_SIDE,
+            };
+            let base = pos0 / self.files * self.files;
+            let (king_to, rook_to) = self.castle_destinations(base, pos1 > pos0);
+            self.board[king_to] = None;
+            self.board[rook_to] = None;
+            self.board[pos0] = Some((self.player, Piece::King));
+            self.board[base + self.castle_files[side]] = Some((self.player, Piece::Rook));
+            return;
+        }
 
-        if let Some(_) = promote {
+        // in atomic the capturer exploded and is only found in the move result.
+        let exploded = cell_from_u8(((mres & EXPLODED_CAPTURER_FLAG) >> EXPLODED_CAPTURER_SHIFT) as u8);
+        if exploded.is_some() {
+            self.unexplode(mres);
+        }
+        if promote.is_some() {
             self.board[pos0] = Some((self.player, Piece::Pawn));
+        } else if exploded.is_some() {
+            self.board[pos0] = exploded;
         } else {
             self.board[pos0] = self.board[pos1];
         }
-        self.en_passant = None;
+        let moved_promoted = self.promoted >> pos1 & 1 == 1 && promote.is_none();
+        let captured_promoted = mres & CAPTURED_PROMOTED_FLAG != 0;
+        self.promoted &= !(1 << pos1);
+        if moved_promoted {
+            self.promoted |= 1 << pos0;
+        }
+        if captured_promoted {
+            self.promoted |= 1 << pos1;
+        }
+        if let (Variant::Crazyhouse, Some((_, piece))) = (self.variant, capture) {
+            let piece = if captured_promoted { Piece::Pawn } else { piece };
+            self.pockets[self.player as usize][piece as usize] -= 1;
+        }
         if is_en_passant {
-            let captured_pos = pos0 / SIZE * SIZE + pos1 % SIZE;
+            let captured_pos = pos0 / self.files * self.files + pos1 % self.files;
             self.board[captured_pos] = capture;
             self.en_passant = Some(captured_pos);
             self.board[pos1] = None;
         } else {
             self.board[pos1] = capture;
         }
-        self.castle_rights[WHITE_KING_SIDE] = mres_get_prev_castle_right_wk(mres);
-        self.castle_rights[WHITE_QUEEN_SIDE] = mres_get_prev_castle_right_wq(mres);
-        self.castle_rights[BLACK_KING_SIDE] = mres_get_prev_castle_right_bk(mres);
-        self.castle_rights[BLACK_QUEEN_SIDE] = mres_get_prev_castle_right_bq(mres);
-
-        if is_castle {
-            if pos1 == WHITE_KING_SIDE_POS || pos1 == BLACK_KING_SIDE_POS {
-                self.board[pos1 + 1] = self.board[pos1 - 1];
-                self.board[pos1 - 1] = None;
-            } else {
-                self.board[pos1 - 2] = self.board[pos1 + 1];
-                self.board[pos1 + 1] = None;
-            }
-        }
     }
     fn rook_move(&self, pos: usize, player: Player, ans: &mut Vec<usize>) {
+        let files = self.files;
+        let squares = self.squares();
         // left
         let mut k = 1;
-        while (pos - k) % SIZE != (SIZE - 1) && self.board[pos - k] == None {
+        while k <= pos % files && self.board[pos - k].is_none() {
             ans.push(pos - k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if (pos - k) % SIZE != (SIZE - 1) && self.board[pos - k].unwrap().0 != player {
+        if k <= pos % files && self.board[pos - k].unwrap().0 != player {
             ans.push(pos - k);
         }
 
         // right
         k = 1;
-        while (pos + k) % SIZE != 0 && self.board[pos + k] == None {
+        while !(pos + k).is_multiple_of(files) && self.board[pos + k].is_none() {
             ans.push(pos + k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if (pos + k) % SIZE != 0 && self.board[pos + k].unwrap().0 != player {
+        if !(pos + k).is_multiple_of(files) && self.board[pos + k].unwrap().0 != player {
             ans.push(pos + k);
         }
 
         // up
         k = 1;
-        while (pos + SIZE * k) < SIZE_2 && self.board[pos + SIZE * k] == None {
-            ans.push(pos + SIZE * k);
+        while (pos + files * k) < squares && self.board[pos + files * k].is_none() {
+            ans.push(pos + files * k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if (pos + SIZE * k) < SIZE_2 && self.board[pos + SIZE * k].unwrap().0 != player {
-            ans.push(pos + SIZE * k);
+        if (pos + files * k) < squares && self.board[pos + files * k].unwrap().0 != player {
+            ans.push(pos + files * k);
         }
         
         // down
         k = 1;
-        while pos >= SIZE * k && self.board[pos - SIZE * k] == None {
-            ans.push(pos - SIZE * k);
+        while pos >= files * k && self.board[pos - files * k].is_none() {
+            ans.push(pos - files * k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if pos >= SIZE * k && self.board[pos - SIZE * k].unwrap().0 != player {
-            ans.push(pos - SIZE * k);
+        if pos >= files * k && self.board[pos - files * k].unwrap().0 != player {
+            ans.push(pos - files * k);
         }
     }
 
     fn bishop_move(&self, pos: usize, player: Player, ans: &mut Vec<usize>) {
-        let i = pos / SIZE;
-        let j = pos % SIZE;
+        let files = self.files;
+        let ranks = self.ranks;
+        let i = pos / files;
+        let j = pos % files;
         // down left
         let mut k = 1;
-        while i >= k && j >= k && self.board[pos - (SIZE + 1) * k] == None {
-            ans.push(pos - (SIZE + 1) * k);
+        while i >= k && j >= k && self.board[pos - (files + 1) * k].is_none() {
+            ans.push(pos - (files + 1) * k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if i >= k && j >= k && self.board[pos - (SIZE + 1) * k].unwrap().0 != player {
-            ans.push(pos - (SIZE + 1) * k);
+        if i >= k && j >= k && self.board[pos - (files + 1) * k].unwrap().0 != player {
+            ans.push(pos - (files + 1) * k);
         }
 
         // up left
         k = 1;
-        while i + k < SIZE && j >= k && self.board[pos + (SIZE - 1) * k] == None {
-            ans.push(pos + (SIZE - 1) * k);
+        while i + k < ranks && j >= k && self.board[pos + (files - 1) * k].is_none() {
+            ans.push(pos + (files - 1) * k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if i + k < SIZE && j >= k && self.board[pos + (SIZE - 1) * k].unwrap().0 != player {
-            ans.push(pos + (SIZE - 1) * k);
+        if i + k < ranks && j >= k && self.board[pos + (files - 1) * k].unwrap().0 != player {
+            ans.push(pos + (files - 1) * k);
         }
 
         // up right
         k = 1;
-        while i + k < SIZE && j + k < SIZE && self.board[pos + (SIZE + 1) * k] == None {
-            ans.push(pos + (SIZE + 1) * k);
+        while i + k < ranks && j + k < files && self.board[pos + (files + 1) * k].is_none() {
+            ans.push(pos + (files + 1) * k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if i + k < SIZE && j + k < SIZE && self.board[pos + (SIZE + 1) * k].unwrap().0 != player {
-            ans.push(pos + (SIZE + 1) * k);
+        if i + k < ranks && j + k < files && self.board[pos + (files + 1) * k].unwrap().0 != player {
+            ans.push(pos + (files + 1) * k);
         }
         
         // down right
         k = 1;
-        while i >= k && j + k < SIZE && self.board[pos - (SIZE - 1) * k] == None {
-            ans.push(pos - (SIZE - 1) * k);
+        while i >= k && j + k < files && self.board[pos - (files - 1) * k].is_none() {
+            ans.push(pos - (files - 1) * k);
             k += 1;
         }
         // if enemy piece, able to capture
-        if i >= k && j + k < SIZE && self.board[pos - (SIZE - 1) * k].unwrap().0 != player {
-            ans.push(pos - (SIZE - 1) * k);
+        if i >= k && j + k < files && self.board[pos - (files - 1) * k].unwrap().0 != player {
+            ans.push(pos - (files - 1) * k);
         }
     }
 
     fn knight_move(&self, pos: usize, player: Player, ans: &mut Vec<usize>) {
-        let i = pos / SIZE;
-        let j = pos % SIZE;
-        if i >= 2 && j >= 1 && (self.board[pos - (2 * SIZE + 1)] == None || self.board[pos - (2 * SIZE + 1)].unwrap().0 != player) {
-            ans.push(pos - (2 * SIZE + 1));
+        let files = self.files;
+        let ranks = self.ranks;
+        let i = pos / files;
+        let j = pos % files;
+        if i >= 2 && j >= 1 && (self.board[pos - (2 * files + 1)].is_none() || self.board[pos - (2 * files + 1)].unwrap().0 != player) {
+            ans.push(pos - (2 * files + 1));
         }
-        if i >= 1 && j >= 2 && (self.board[pos - (SIZE + 2)] == None || self.board[pos - (SIZE + 2)].unwrap().0 != player) {
-            ans.push(pos - (SIZE + 2));
+        if i >= 1 && j >= 2 && (self.board[pos - (files + 2)].is_none() || self.board[pos - (files + 2)].unwrap().0 != player) {
+            ans.push(pos - (files + 2));
         }
-        if i >= 1 && j < SIZE - 2 && (self.board[pos - (SIZE - 2)] == None || self.board[pos - (SIZE - 2)].unwrap().0 != player) {
-            ans.push(pos - (SIZE - 2));
+        if i >= 1 && j < files - 2 && (self.board[pos - (files - 2)].is_none() || self.board[pos - (files - 2)].unwrap().0 != player) {
+            ans.push(pos - (files - 2));
         }
-        if i >= 2 && j < SIZE - 1 && (self.board[pos - (2 * SIZE - 1)] == None || self.board[pos - (2 * SIZE - 1)].unwrap().0 != player) {
-            ans.push(pos - (2 * SIZE - 1));
+        if i >= 2 && j < files - 1 && (self.board[pos - (2 * files - 1)].is_none() || self.board[pos - (2 * files - 1)].unwrap().0 != player) {
+            ans.push(pos - (2 * files - 1));
         }
-        if i < SIZE - 2 && j < SIZE - 1 && (self.board[pos + (2 * SIZE + 1)] == None || self.board[pos + (2 * SIZE + 1)].unwrap().0 != player) {
-            ans.push(pos + (2 * SIZE + 1));
+        if i < ranks - 2 && j < files - 1 && (self.board[pos + (2 * files + 1)].is_none() || self.board[pos + (2 * files + 1)].unwrap().0 != player) {
+            ans.push(pos + (2 * files + 1));
         }
-        if i < SIZE - 1 && j < SIZE - 2 && (self.board[pos + (SIZE + 2)] == None || self.board[pos + (SIZE + 2)].unwrap().0 != player) {
-            ans.push(pos + (SIZE + 2));
+        if i < ranks - 1 && j < files - 2 && (self.board[pos + (files + 2)].is_none() || self.board[pos + (files + 2)].unwrap().0 != player) {
+            ans.push(pos + (files + 2));
         }
-        if i < SIZE - 1 && j >= 2 && (self.board[pos + (SIZE - 2)] == None || self.board[pos + (SIZE - 2)].unwrap().0 != player) {
-            ans.push(pos + (SIZE - 2));
+        if i < ranks - 1 && j >= 2 && (self.board[pos + (files - 2)].is_none() || self.board[pos + (files - 2)].unwrap().0 != player) {
+            ans.push(pos + (files - 2));
         }
-        if i < SIZE - 2 && j >= 1 && (self.board[pos + (2 * SIZE - 1)] == None || self.board[pos + (2 * SIZE - 1)].unwrap().0 != player) {
-            ans.push(pos + (2 * SIZE - 1));
+        if i < ranks - 2 && j >= 1 && (self.board[pos + (2 * files - 1)].is_none() || self.board[pos + (2 * files - 1)].unwrap().0 != player) {
+            ans.push(pos + (2 * files - 1));
         }
     }
     
     fn king_ordinary_move(&self, pos: usize, player: Player, ans: &mut Vec<usize>) {
-        let i = pos / SIZE;
-        let j = pos % SIZE;
+        let files = self.files;
+        let ranks = self.ranks;
+        let i = pos / files;
+        let j = pos % files;
         let i_start = if i == 0 { 0 } else { i - 1 };
-        let i_end = if i == SIZE - 1 { SIZE - 1 } else { i + 1 };
+        let i_end = if i == ranks - 1 { ranks - 1 } else { i + 1 };
         let j_start = if j == 0 { 0 } else { j - 1 };
-        let j_end = if j == SIZE - 1 { SIZE - 1 } else { j + 1 };
+        let j_end = if j == files - 1 { files - 1 } else { j + 1 };
         for move_i in i_start..=i_end {
             for move_j in j_start..=j_end {
                 if move_i == i && move_j == j { continue }
-                let new_pos = move_i * SIZE + move_j;
-                if self.board[new_pos] == None || self.board[new_pos].unwrap().0 != player {
+                let new_pos = move_i * files + move_j;
+                if self.board[new_pos].is_none() || self.board[new_pos].unwrap().0 != player {
                     ans.push(new_pos);
                 }
             }
         }
     }
+
+    // squares reached by a piece other than a pawn, castling aside; compound pieces move as each of their parts.
+    fn piece_moves(&self, pos: usize, player: Player, piece: Piece, ans: &mut Vec<usize>) {
+        match piece {
+            Piece::Pawn => {},
+            Piece::Rook => self.rook_move(pos, player, ans),
+            Piece::Knight => self.knight_move(pos, player, ans),
+            Piece::Bishop => self.bishop_move(pos, player, ans),
+            Piece::Queen => {
+                self.rook_move(pos, player, ans);
+                self.bishop_move(pos, player, ans);
+            },
+            Piece::King => self.king_ordinary_move(pos, player, ans),
+            Piece::Archbishop => {
+                self.bishop_move(pos, player, ans);
+                self.knight_move(pos, player, ans);
+            },
+            Piece::Chancellor => {
+                self.rook_move(pos, player, ans);
+                self.knight_move(pos, player, ans);
+            },
+        }
+    }
+
+    // pieces a pawn may promote to, the compound pieces only on boards wider than 8 files.
+    pub fn promotion_pieces(&self) -> &'static [Piece] {
+        if self.files > 8 {
+            &[Piece::Queen, Piece::Chancellor, Piece::Archbishop, Piece::Rook, Piece::Bishop, Piece::Knight]
+        } else {
+            &[Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight]
+        }
+    }
+
+    // a pawn move of the player to move, one per promotion piece when it reaches the last rank.
+    fn push_pawn_move(&self, pos0: usize, pos1: usize, ans: &mut Vec<MoveRequest>) {
+        if pos1 < self.files || pos1 >= self.last_rank() {
+            for piece in self.promotion_pieces() {
+                ans.push(mreq_new_with_promote(pos0, pos1, &Some((self.player, *piece))));
+            }
+        } else {
+            ans.push(mreq_new(pos0, pos1));
+        }
+    }
     
-    // returns uint64, get i-th by ((x >> i) & 1); also a bool if the defender is been checked.
-    pub fn get_attacking_range(&self, attacker: Player) -> (u64, bool) {
-        let mut ans: u64 = 0;
+    // returns a bitset of the attacked squares, get i-th by ((x >> i) & 1); also a bool if the defender is been checked.
+    pub fn get_attacking_range(&self, attacker: Player) -> (u128, bool) {
+        let mut ans: u128 = 0;
         let mut checked = false;
-        let defender = match attacker {
-            Player::White => Player::Black,
-            Player::Black => Player::White,
-        };
-        for pos in 0..SIZE_2 {
-            if self.board[pos] == None || self.board[pos].unwrap().0 != attacker {
+        let defender = attacker.opponent();
+        let files = self.files;
+        for pos in 0..self.squares() {
+            let Some((player, piece)) = self.board[pos] else { continue };
+            if player != attacker {
                 continue
             }
-            let piece = self.board[pos].unwrap().1;
-
-            match piece {
-                Piece::Pawn => {
-                    match attacker{
-                        Player::White => {
-                            if pos % SIZE >= 1 {
-                                ans |= 1 << (pos + SIZE - 1);
-                                if self.board[pos + SIZE - 1] == Some((defender, Piece::King)) {
-                                    checked = true;
-                                }
-                            }
-                            if pos % SIZE < SIZE - 1 {
-                                ans |= 1 << (pos + SIZE + 1);
-                                if self.board[pos + SIZE + 1] == Some((defender, Piece::King)) {
-                                    checked = true;
-                                }
-                            }
-                        },
-                        Player::Black => {
-                            if pos % SIZE >= 1 {
-                                ans |= 1 << (pos - SIZE - 1);
-                                if self.board[pos - SIZE - 1] == Some((defender, Piece::King)) {
-                                    checked = true;
-                                }
-                            }
-                            if pos % SIZE < SIZE - 1 {
-                                ans |= 1 << (pos - SIZE + 1);
-                                if self.board[pos - SIZE + 1] == Some((defender, Piece::King)) {
-                                    checked = true;
-                                }
-                            }
-                        }
+            let mut attacked: Vec<usize> = Vec::new();
+            if piece == Piece::Pawn {
+                let ahead = match attacker {
+                    Player::White => pos + files,
+                    Player::Black => pos.wrapping_sub(files),
+                };
+                // pawns on the last rank are only found in set up positions, and attack nothing.
+                if ahead < self.squares() {
+                    if pos % files >= 1 {
+                        attacked.push(ahead - 1);
                     }
-                },
-                Piece::Rook => {
-                    let mut attacked: Vec<usize> = Vec::new();
-                    self.rook_move(pos, attacker, &mut attacked);
-                    for i in attacked {
-                        ans |= 1 << i;
-                        if self.board[i] == Some((defender, Piece::King)) {
-                            checked = true;
-                        }
+                    if pos % files < files - 1 {
+                        attacked.push(ahead + 1);
                     }
                 }
-                Piece::Knight => {
-                    let mut attacked: Vec<usize> = Vec::new();
-                    self.knight_move(pos, attacker, &mut attacked);
-                    for i in attacked {
-                        ans |= 1 << i;
-                        if self.board[i] == Some((defender, Piece::King)) {
-                            checked = true;
-                        }
-                    }
-                },
-                Piece::Bishop => {
-                    let mut attacked: Vec<usize> = Vec::new();
-                    self.bishop_move(pos, attacker, &mut attacked);
-                    for i in attacked {
-                        ans |= 1 << i;
-                        if self.board[i] == Some((defender, Piece::King)) {
-                            checked = true;
-                        }
-                    }
-                },
-                Piece::Queen => {
-                    let mut attacked: Vec<usize> = Vec::new();
-                    self.rook_move(pos, attacker, &mut attacked);
-                    self.bishop_move(pos, attacker, &mut attacked);
-                    for i in attacked {
-                        ans |= 1 << i;
-                        if self.board[i] == Some((defender, Piece::King)) {
-                            checked = true;
-                        }
-                    }
-                },
-                Piece::King => {
-                    let mut attacked: Vec<usize> = Vec::new();
-                    self.king_ordinary_move(pos, attacker, &mut attacked);
-                    for i in attacked {
-                        ans |= 1 << i;
-                        if self.board[i] == Some((defender, Piece::King)) {
-                            checked = true;
-                        }
-                    }
-                },
+            } else {
+                self.piece_moves(pos, attacker, piece, &mut attacked);
+            }
+            for i in attacked {
+                ans |= 1 << i;
+                if self.board[i] == Some((defender, Piece::King)) {
+                    checked = true;
+                }
             }
         }
         (ans, checked)
@@ -625,20 +1261,71 @@ impl ChessBoard {
     }
     // Returns all possible moves could be done by the player.
     // The 0th element is guaranteed to be if the player is been checked. 0 is no, 1 is yes.
-    // The following is a list of possible moves. lower 16 bits indicate source, and higher 16 bits indicate destination.
+    // The following is a list of possible moves, encoded as in mreq_new.
     pub fn possible_moves(&self) -> Vec<MoveRequest> {
         let mut ans: Vec<MoveRequest> = vec![];
         let opponent = self.player.opponent();
 
         let (enemy_range, checked) = self.get_attacking_range(opponent);
+        let checked = checked && match self.variant {
+            Variant::Antichess => false,
+            Variant::Atomic => !self.kings_adjacent(),
+            _ => true,
+        };
 
+        if self.variant_outcome().is_some() {
+            return vec![if checked { MOVES_CHECKED_LEADER } else { MOVES_UNCHECKED_LEADER }];
+        }
         if checked {
             ans.push(MOVES_CHECKED_LEADER);
         } else {
             ans.push(MOVES_UNCHECKED_LEADER);
         }
 
-        for pos in 0..SIZE_2 {
+        self.pseudo_legal_moves(enemy_range, &mut ans);
+        
+        if self.variant == Variant::Antichess {
+            return self.antichess_moves(ans);
+        }
+        if self.variant == Variant::Crazyhouse {
+            self.drop_moves(&mut ans);
+        }
+
+        // filter out moves that lead to / maintains check state;
+        let mut filtered_ans = vec![ans[0]];
+        if self.variant == Variant::Atomic {
+            for possible_move in ans.into_iter().skip(1) {
+                // kings can not capture, as they would explode themselves.
+                let pos1 = get_pos1!(possible_move);
+                if self.board[get_pos0!(possible_move)].unwrap().1 == Piece::King && self.board[pos1].is_some_and(|c| c.0 == opponent) {
+                    continue;
+                }
+                let mut new_board = *self;
+                new_board.move_pieces(possible_move);
+                if new_board.atomic_legal_after(self.player) {
+                    filtered_ans.push(possible_move);
+                }
+            }
+            return filtered_ans;
+        }
+        for possible_move in ans.into_iter().skip(1) {
+
+            let mut new_board = *self;
+            new_board.move_pieces(possible_move);
+            let (_, still_checked) = new_board.get_attacking_range(opponent);
+            // giving check is not allowed in racing kings.
+            let gives_check = self.variant == Variant::RacingKings && new_board.get_attacking_range(self.player).1;
+            if !still_checked && !gives_check {
+                filtered_ans.push(possible_move);
+            }
+        }
+        filtered_ans
+    }
+
+    // moves of the pieces of the player to move by how they move, castling included but no drops, whether or not
+    // they leave the king in check; `enemy_range` as in get_attacking_range of the opponent.
+    fn pseudo_legal_moves(&self, enemy_range: u128, ans: &mut Vec<MoveRequest>) {
+        for pos in 0..self.squares() {
             let (player, piece) = match self.board[pos] {
                 None => continue,
                 Some((player, piece)) => {
@@ -650,197 +1337,172 @@ impl ChessBoard {
             };
             match piece {
                 Piece::Pawn => {
-                    use Player::{White, Black};
-                    use Piece::{Queen, Rook, Bishop, Knight};
-                    match player {
-                        White => {
-                            if self.board[pos + SIZE] == None {
-                                let new_pos = pos + SIZE;
-                                if new_pos / SIZE == 7 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                            // if not moved, en passant
-                            if pos / SIZE == 1 && self.board[pos + SIZE] == None && self.board[pos + 2 * SIZE] == None {
-                                let new_pos = pos + 2 * SIZE;
-                                if new_pos / SIZE == 7 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                            // capturing
-                            if pos % SIZE < SIZE - 1 && 
-                                (self.board[pos + SIZE + 1] != None && self.board[pos + SIZE + 1].unwrap().0 != player ||
-                                self.en_passant != None && self.en_passant.unwrap() == pos + SIZE + 1) {
-                                let new_pos = pos + SIZE + 1;
-                                if new_pos / SIZE == 7 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                            if pos % SIZE >= 1 && 
-                                (self.board[pos + SIZE - 1] != None && self.board[pos + SIZE - 1].unwrap().0 != player ||
-                                self.en_passant != None && self.en_passant.unwrap() == pos + SIZE - 1) {
-                                let new_pos = pos + SIZE - 1;
-                                if new_pos / SIZE == 7 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((White, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                        },
-                        Black => {
-                            if self.board[pos - SIZE] == None {
-                                let new_pos = pos - SIZE;
-                                if new_pos / SIZE == 0 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                            // if not moved, en passant
-                            if pos / SIZE == (SIZE - 2) && self.board[pos - SIZE] == None && self.board[pos - 2 * SIZE] == None {
-                                let new_pos = pos - 2 * SIZE;
-                                if new_pos / SIZE == 0 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                            // capturing
-                            if pos % SIZE < SIZE - 1 && 
-                                (self.board[pos - SIZE + 1] != None && self.board[pos - SIZE + 1].unwrap().0 != player ||
-                                self.en_passant != None && self.en_passant.unwrap() == pos - SIZE + 1) {
-                                let new_pos = pos - SIZE + 1;
-                                if new_pos / SIZE == 0 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
-                            if pos % SIZE >= 1 && 
-                                (self.board[pos - SIZE - 1] != None && self.board[pos - SIZE - 1].unwrap().0 != player ||
-                                self.en_passant != None && self.en_passant.unwrap() == pos - SIZE - 1) {
-                                let new_pos = pos - SIZE - 1;
-                                if new_pos / SIZE == 0 {
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Queen))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Rook))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Bishop))));
-                                    ans.push(mreq_new_with_promote(pos, new_pos, &Some((Black, Knight))));
-                                } else {
-                                    ans.push(mreq_new(pos, new_pos));
-                                }
-                            }
+                    let files = self.files;
+                    let forward = |p: usize| if player == Player::White { p + files } else { p - files };
+                    let new_pos = forward(pos);
+                    if self.board[new_pos].is_none() {
+                        self.push_pawn_move(pos, new_pos, ans);
+                        // if not moved, two squares; in horde also from the first rank, though without en passant.
+                        let unmoved = match player {
+                            Player::White => pos / files == 1 || pos / files == 0 && self.variant == Variant::Horde,
+                            Player::Black => pos / files == self.ranks - 2,
+                        };
+                        if unmoved && self.board[forward(new_pos)].is_none() {
+                            self.push_pawn_move(pos, forward(new_pos), ans);
                         }
                     }
-                },
-                Piece::Rook => {
-                    let mut new_poses = vec![];
-                    self.rook_move(pos, player, &mut new_poses);
-                    for new_pos in new_poses {
-                        ans.push(mreq_new(pos, new_pos));
+                    // capturing, en passant included
+                    if pos % files < files - 1 && (self.board[new_pos + 1].is_some_and(|c| c.0 != player) || self.en_passant == Some(new_pos + 1)) {
+                        self.push_pawn_move(pos, new_pos + 1, ans);
                     }
-                },
-                Piece::Knight => {
-                    let mut new_poses = vec![];
-                    self.knight_move(pos, player, &mut new_poses);
-                    for new_pos in new_poses {
-                        ans.push(mreq_new(pos, new_pos));
+                    if pos % files >= 1 && (self.board[new_pos - 1].is_some_and(|c| c.0 != player) || self.en_passant == Some(new_pos - 1)) {
+                        self.push_pawn_move(pos, new_pos - 1, ans);
                     }
                 },
-                Piece::Bishop => {
+                Piece::King => {
                     let mut new_poses = vec![];
-                    self.bishop_move(pos, player, &mut new_poses);
+                    self.king_ordinary_move(pos, player, &mut new_poses);
                     for new_pos in new_poses {
                         ans.push(mreq_new(pos, new_pos));
                     }
-                },
-                Piece::Queen => {
-                    let mut new_poses = vec![];
-                    self.rook_move(pos, player, &mut new_poses);
-                    self.bishop_move(pos, player, &mut new_poses);
-                    for new_pos in new_poses {
-                        ans.push(mreq_new(pos, new_pos));
+                    // castle: the squares between the king, the rook and their destinations must be empty, and the king
+                    // may not pass through or land on an attacked square.
+                    let sides = match player {
+                        Player::White => [WHITE_KING_SIDE, WHITE_QUEEN_SIDE],
+                        Player::Black => [BLACK_KING_SIDE, BLACK_QUEEN_SIDE],
+                    };
+                    for side in sides {
+                        if !self.castle_rights[side] {
+                            continue;
+                        }
+                        let Some((king_from, king_to, rook_from, rook_to)) = self.castle_squares(side) else { continue };
+                        let lo = king_from.min(king_to).min(rook_from).min(rook_to);
+                        let hi = king_from.max(king_to).max(rook_from).max(rook_to);
+                        if king_from != pos || (lo..=hi).any(|p| p != king_from && p != rook_from && self.board[p].is_some()) {
+                            continue;
+                        }
+                        if (king_from.min(king_to)..=king_from.max(king_to)).any(|p| enemy_range >> p & 1 == 1) {
+                            continue;
+                        }
+                        ans.push(mreq_new(pos, if self.chess960 { rook_from } else { king_to }));
                     }
                 },
-                Piece::King => {
+                _ => {
                     let mut new_poses = vec![];
-                    self.king_ordinary_move(pos, player, &mut new_poses);
+                    self.piece_moves(pos, player, piece, &mut new_poses);
                     for new_pos in new_poses {
                         ans.push(mreq_new(pos, new_pos));
                     }
-                    // castle
-                    match player {
-                        Player::White => {
-                            if self.castle_rights[WHITE_KING_SIDE] && self.board[WHITE_KING_SIDE_POS - 1] == None && self.board[WHITE_KING_SIDE_POS] == None &&
-                                (enemy_range >> (WHITE_KING_SIDE_POS - 2) & 1 != 1) &&
-                                (enemy_range >> (WHITE_KING_SIDE_POS - 1) & 1 != 1) &&
-                                (enemy_range >> (WHITE_KING_SIDE_POS) & 1 != 1) {
-                                ans.push(mreq_new(pos, WHITE_KING_SIDE_POS));
-                            }
-                            if self.castle_rights[WHITE_QUEEN_SIDE] && self.board[WHITE_QUEEN_SIDE_POS - 1] == None && self.board[WHITE_QUEEN_SIDE_POS] == None && self.board[WHITE_QUEEN_SIDE_POS + 1] == None &&
-                                (enemy_range >> (WHITE_QUEEN_SIDE_POS + 2) & 1 != 1) &&
-                                (enemy_range >> (WHITE_QUEEN_SIDE_POS + 1) & 1 != 1) &&
-                                (enemy_range >> (WHITE_QUEEN_SIDE_POS) & 1 != 1) {
-                                ans.push(mreq_new(pos, WHITE_QUEEN_SIDE_POS));
-                            }
-                        },
-                        Player::Black => {
-                            if self.castle_rights[BLACK_KING_SIDE] && self.board[BLACK_KING_SIDE_POS - 1] == None && self.board[BLACK_KING_SIDE_POS] == None &&
-                                (enemy_range >> (BLACK_KING_SIDE_POS - 2) & 1 != 1) &&
-                                (enemy_range >> (BLACK_KING_SIDE_POS - 1) & 1 != 1) &&
-                                (enemy_range >> (BLACK_KING_SIDE_POS) & 1 != 1) {
-                                    ans.push(mreq_new(pos, BLACK_KING_SIDE_POS));
-                            }
-                            if self.castle_rights[BLACK_QUEEN_SIDE] && self.board[BLACK_QUEEN_SIDE_POS - 1] == None && self.board[BLACK_QUEEN_SIDE_POS] == None && self.board[BLACK_QUEEN_SIDE_POS + 1] == None &&
-                                (enemy_range >> (BLACK_QUEEN_SIDE_POS + 2) & 1 != 1) &&
-                                (enemy_range >> (BLACK_QUEEN_SIDE_POS + 1) & 1 != 1) &&
-                                (enemy_range >> (BLACK_QUEEN_SIDE_POS) & 1 != 1) {
-                                    ans.push(mreq_new(pos, BLACK_QUEEN_SIDE_POS));
-                            }
-                        }
-                    }
                 },
             }
         }
-        
-        // filter out moves that lead to / maintains check state;
-        let mut filtered_ans = vec![ans[0]];
-        for possible_move in ans.into_iter().skip(1) {
+    }
 
-            let mut new_board = self.clone();
-            new_board.do_move(possible_move);
-            let (_, still_checked) = new_board.get_attacking_range(opponent);
-            if !still_checked {
-                filtered_ans.push(possible_move);
+    // pseudo-legal moves are legal in antichess, but captures are forced and pawns may also promote to a king.
+    fn antichess_moves(&self, moves: Vec<MoveRequest>) -> Vec<MoveRequest> {
+        let is_capture = |m: &MoveRequest| {
+            let pos1 = get_pos1!(*m);
+            self.board[pos1].is_some() || (self.board[get_pos0!(*m)].unwrap().1 == Piece::Pawn && self.en_passant == Some(pos1))
+        };
+        let captures = moves[1..].iter().any(is_capture);
+        let mut ans = vec![moves[0]];
+        for m in moves.into_iter().skip(1) {
+            if captures && !is_capture(&m) {
+                continue;
+            }
+            ans.push(m);
+            if matches!(get_promote!(m), Some((_, Piece::Queen))) {
+                ans.push(mreq_new_with_promote(get_pos0!(m), get_pos1!(m), &Some((self.player, Piece::King))));
             }
         }
-        filtered_ans
+        ans
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uci(board: &ChessBoard, san: &str) -> Option<String> {
+        board.parse_san(san).map(|m| board.mreq_to_uci(m))
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        let knights = ChessBoard::from_fen("rnbqkb1r/ppp2ppp/4pn2/3p4/3P4/5N2/PPP1PPPP/RNBQKB1R b KQkq - 0 1").unwrap();
+        assert_eq!(uci(&knights, "Nbd7").as_deref(), Some("b8d7"));
+        assert_eq!(uci(&knights, "Nfd7").as_deref(), Some("f6d7"));
+        assert_eq!(uci(&knights, "Nd7"), None);
+        let rooks = ChessBoard::from_fen("4k3/8/8/8/8/4R3/8/K3R3 w - - 0 1").unwrap();
+        assert_eq!(uci(&rooks, "R1e2").as_deref(), Some("e1e2"));
+        assert_eq!(uci(&rooks, "R3e2").as_deref(), Some("e3e2"));
+        assert_eq!(uci(&rooks, "Re2"), None);
+        let mreq = rooks.parse_san("R1e2").unwrap();
+        assert_eq!(rooks.move_to_san(mreq), "R1e2+");
+    }
+
+    #[test]
+    fn san_promotion() {
+        let board = ChessBoard::from_fen("3r4/4P3/8/8/8/8/k7/4K3 w - - 0 1").unwrap();
+        assert_eq!(uci(&board, "e8=Q").as_deref(), Some("e7e8q"));
+        assert_eq!(uci(&board, "e8N").as_deref(), Some("e7e8n"));
+        assert_eq!(uci(&board, "exd8=R+").as_deref(), Some("e7d8r"));
+        assert_eq!(uci(&board, "e8"), None);
+        assert_eq!(board.move_to_san(board.parse_san("e8=Q").unwrap()), "e8=Q");
+    }
+
+    #[test]
+    fn san_castling() {
+        let board = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(uci(&board, "O-O").as_deref(), Some("e1g1"));
+        assert_eq!(uci(&board, "O-O-O").as_deref(), Some("e1c1"));
+        assert_eq!(uci(&board, "0-0").as_deref(), Some("e1g1"));
+        let black = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(uci(&black, "O-O-O+").as_deref(), Some("e8c8"));
+        assert_eq!(black.move_to_san(black.parse_san("O-O").unwrap()), "O-O");
+    }
+
+    #[test]
+    fn san_check_marks() {
+        let board = ChessBoard::from_fen("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq g3 0 2").unwrap();
+        for san in ["Qh4#", "Qh4+", "Qh4", "Qh4#!", "d8h4"] {
+            assert_eq!(uci(&board, san).as_deref(), Some("d8h4"), "{}", san);
+        }
+        assert_eq!(board.move_to_san(board.parse_san("Qh4").unwrap()), "Qh4#");
+        let check = ChessBoard::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(check.move_to_san(check.parse_san("Ra8").unwrap()), "Ra8+");
+        assert_eq!(uci(&board, "Qh5"), None);
+    }
+
+    #[test]
+    fn san_round_trip() {
+        let board = ChessBoard::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for mreq in board.possible_moves().into_iter().skip(1) {
+            assert_eq!(board.parse_san(&board.move_to_san(mreq)), Some(mreq), "{}", board.move_to_san(mreq));
+        }
+    }
+
+    #[test]
+    fn chess960_perft() {
+        // from the Chess960 perft suite, with king-takes-rook castling on the f- and h-files.
+        for (fen, counts) in [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", [12189, 326672]),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", [18002, 667366]),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", [10471, 273318]),
+        ] {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            assert_eq!([board.perft(3), board.perft(4)], counts, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn chess960_start_positions() {
+        assert_eq!(ChessBoard::new_chess960(518).to_fen(), ChessBoard::new().to_fen());
+        let mut starts: Vec<String> = (0..960).map(|i| ChessBoard::new_chess960(i).to_fen()).collect();
+        starts.sort();
+        starts.dedup();
+        assert_eq!(starts.len(), 960);
+        assert!(ChessBoard::new_chess960(0).to_fen().starts_with("bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w"));
+    }
+}
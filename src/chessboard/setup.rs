@@ -0,0 +1,214 @@
+// Setting up a position square by square, as in a board editor, and checking that the result is a legal position.
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PositionError {
+    MissingKing(Player),
+    TooManyKings(Player),
+    // the player is in check, but not to move.
+    InCheck(Player),
+    PawnOnBackRank(usize),
+    // a castling right without the king on the back rank or the rook on its file.
+    CastlingRight { player: Player, king_side: bool },
+    EnPassant(usize),
+}
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = |player: &Player| if *player == Player::White { "white" } else { "black" };
+        match self {
+            PositionError::MissingKing(player) => write!(f, "{} has no king", name(player)),
+            PositionError::TooManyKings(player) => write!(f, "{} has more than one king", name(player)),
+            PositionError::InCheck(player) => write!(f, "{} is in check but not to move", name(player)),
+            PositionError::PawnOnBackRank(pos) => write!(f, "pawn on the back rank at square {}", pos),
+            PositionError::CastlingRight { player, king_side } => {
+                write!(f, "{} can not castle {} side", name(player), if *king_side { "king" } else { "queen" })
+            },
+            PositionError::EnPassant(pos) => write!(f, "invalid en passant square {}", pos),
+        }
+    }
+}
+
+impl ChessBoard {
+    // put a piece on `pos`, or empty it with None; the piece counts as not promoted.
+    pub fn set_piece(&mut self, pos: usize, cell: Cell) {
+        self.board[pos] = cell;
+        self.promoted &= !(1 << pos);
+    }
+
+    // remove every piece and right, keeping the size of the board and the variant.
+    pub fn clear(&mut self) {
+        self.board = [None; MAX_SQUARES];
+        self.promoted = 0;
+        self.pockets = [[0; 8]; 2];
+        self.castle_rights = [false; 4];
+        self.en_passant = None;
+    }
+
+    pub fn set_player(&mut self, player: Player) {
+        self.player = player;
+    }
+
+    /**
+     * Grant or take away a castling right. A right is granted with the outermost rook on that side of the king, as KQkq
+     * in X-FEN, and false is returned without a king on the back rank or such a rook.
+     */
+    pub fn set_castle_right(&mut self, player: Player, king_side: bool, enabled: bool) -> bool {
+        let side = match (player, king_side) {
+            (Player::White, true) => WHITE_KING_SIDE,
+            (Player::White, false) => WHITE_QUEEN_SIDE,
+            (Player::Black, true) => BLACK_KING_SIDE,
+            (Player::Black, false) => BLACK_QUEEN_SIDE,
+        };
+        if !enabled {
+            self.castle_rights[side] = false;
+            return true;
+        }
+        let base = if player == Player::White { 0 } else { self.last_rank() };
+        let Some(king_file) = (0..self.files).find(|j| self.board[base + j] == Some((player, Piece::King))) else { return false };
+        let is_rook = |j: &usize| self.board[base + j] == Some((player, Piece::Rook));
+        let file = if king_side { (king_file + 1..self.files).rev().find(is_rook) } else { (0..king_file).find(is_rook) };
+        let Some(file) = file else { return false };
+        if king_file != self.files / 2 || (file != 0 && file != self.files - 1) || self.files < 8 {
+            self.chess960 = true;
+        }
+        self.castle_rights[side] = true;
+        self.castle_files[side] = file;
+        true
+    }
+
+    // the square a pawn of the player not to move skipped with its double step, if any.
+    pub fn set_en_passant(&mut self, pos: Option<usize>) {
+        self.en_passant = pos.filter(|p| *p < self.squares());
+    }
+
+    /**
+     * Check that the position could be played from: one king each (none in antichess, and none for white in horde),
+     * the player not to move not in check, no pawns on the first or last rank, castling rights with the king and rook
+     * in place, and an en passant square just behind a pawn of the player not to move.
+     */
+    pub fn validate(&self) -> Result<(), PositionError> {
+        for player in [Player::White, Player::Black] {
+            let kings = self.board[..self.squares()].iter().filter(|c| **c == Some((player, Piece::King))).count();
+            let required = !(self.variant == Variant::Antichess || (self.variant == Variant::Horde && player == Player::White));
+            if kings == 0 && required {
+                return Err(PositionError::MissingKing(player));
+            }
+            if kings > 1 && self.variant != Variant::Antichess {
+                return Err(PositionError::TooManyKings(player));
+            }
+        }
+        let opponent = self.player.opponent();
+        let in_check = |player: Player| self.get_attacking_range(player.opponent()).1;
+        match self.variant {
+            Variant::Antichess => (),
+            Variant::Atomic if self.kings_adjacent() => (),
+            // giving check is not allowed at all in racing kings.
+            Variant::RacingKings if in_check(self.player) => return Err(PositionError::InCheck(self.player)),
+            _ if in_check(opponent) => return Err(PositionError::InCheck(opponent)),
+            _ => (),
+        }
+        for pos in (0..self.files).chain(self.last_rank()..self.squares()) {
+            if let Some((player, Piece::Pawn)) = self.board[pos] {
+                // horde pawns may start on the first rank, and step two squares from there as from the second.
+                if !(self.variant == Variant::Horde && player == Player::White && pos < self.files) {
+                    return Err(PositionError::PawnOnBackRank(pos));
+                }
+            }
+        }
+        for (side, player, king_side) in [(WHITE_KING_SIDE, Player::White, true), (WHITE_QUEEN_SIDE, Player::White, false),
+                                          (BLACK_KING_SIDE, Player::Black, true), (BLACK_QUEEN_SIDE, Player::Black, false)] {
+            if self.castle_rights[side] && self.castle_squares(side).is_none() {
+                return Err(PositionError::CastlingRight { player, king_side });
+            }
+        }
+        if let Some(pos) = self.en_passant {
+            // the pawn stands in front of the square, seen from the player to move, and came from behind it.
+            let (rank, pawn, from) = match self.player {
+                Player::White => (self.ranks - 3, pos.wrapping_sub(self.files), pos + self.files),
+                Player::Black => (2, pos + self.files, pos.wrapping_sub(self.files)),
+            };
+            if pos / self.files != rank || self.board[pos].is_some() || self.board[from].is_some() || self.board[pawn] != Some((opponent, Piece::Pawn)) {
+                return Err(PositionError::EnPassant(pos));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(fen: &str) -> Result<(), PositionError> {
+        ChessBoard::from_fen(fen).unwrap().validate()
+    }
+
+    fn square(s: &str) -> usize {
+        ChessBoard::new().pos_from_string(s).unwrap()
+    }
+
+    #[test]
+    fn valid_positions() {
+        assert_eq!(ChessBoard::new().validate(), Ok(()));
+        assert_eq!(validate("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3"), Ok(()));
+        assert_eq!(ChessBoard::new_variant(Variant::Horde).validate(), Ok(()));
+        assert_eq!(ChessBoard::new_variant(Variant::RacingKings).validate(), Ok(()));
+    }
+
+    #[test]
+    fn kings() {
+        assert_eq!(validate("8/8/8/8/8/8/8/4K3 w - - 0 1"), Err(PositionError::MissingKing(Player::Black)));
+        assert_eq!(validate("4k3/8/8/8/8/8/8/8 w - - 0 1"), Err(PositionError::MissingKing(Player::White)));
+        assert_eq!(validate("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"), Err(PositionError::TooManyKings(Player::White)));
+        // no king needed in antichess, and any number of them.
+        let mut board = ChessBoard::new_variant(Variant::Antichess);
+        board.set_piece(square("e1"), None);
+        board.set_piece(square("a3"), Some((Player::Black, Piece::King)));
+        board.set_piece(square("h3"), Some((Player::Black, Piece::King)));
+        assert_eq!(board.validate(), Ok(()));
+    }
+
+    #[test]
+    fn in_check() {
+        // black is in check with white to move.
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), Err(PositionError::InCheck(Player::Black)));
+        assert_eq!(validate("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1"), Ok(()));
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        assert_eq!(validate("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"), Err(PositionError::PawnOnBackRank(square("a1"))));
+        assert_eq!(validate("3pk3/8/8/8/8/8/8/4K3 w - - 0 1"), Err(PositionError::PawnOnBackRank(square("d8"))));
+    }
+
+    #[test]
+    fn castling_right() {
+        let mut board = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(board.validate(), Ok(()));
+        board.set_piece(square("h1"), None);
+        assert_eq!(board.validate(), Err(PositionError::CastlingRight { player: Player::White, king_side: true }));
+        assert!(!board.set_castle_right(Player::White, true, true));
+        board.set_castle_right(Player::White, true, false);
+        assert_eq!(board.validate(), Ok(()));
+        board.set_piece(square("a8"), Some((Player::Black, Piece::Knight)));
+        assert_eq!(board.validate(), Err(PositionError::CastlingRight { player: Player::Black, king_side: false }));
+    }
+
+    #[test]
+    fn en_passant() {
+        let mut board = ChessBoard::from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3").unwrap();
+        // no pawn in front of the square.
+        board.set_en_passant(Some(square("c6")));
+        assert_eq!(board.validate(), Err(PositionError::EnPassant(square("c6"))));
+        // not on the sixth rank.
+        board.set_en_passant(Some(square("d5")));
+        assert_eq!(board.validate(), Err(PositionError::EnPassant(square("d5"))));
+        board.set_en_passant(Some(square("d6")));
+        assert_eq!(board.validate(), Ok(()));
+        // the pawn could not have come from d7 with a piece there.
+        board.set_piece(square("d7"), Some((Player::Black, Piece::Knight)));
+        assert_eq!(board.validate(), Err(PositionError::EnPassant(square("d6"))));
+    }
+}
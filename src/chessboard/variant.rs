@@ -0,0 +1,434 @@
+// Rule variants. They differ in the start position, how games are won and which moves are legal; Capablanca and
+// Gothic chess add the archbishop and the chancellor on a 10x8 board but otherwise play by the orthodox rules.
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // a king reaching one of the four center squares wins.
+    KingOfTheHill,
+    // giving check for the third time wins.
+    ThreeCheck,
+    // captures are forced, the king is an ordinary piece, and losing all pieces (or being stalemated) wins.
+    Antichess,
+    // captured pieces go to the capturer's pocket and may be dropped back on the board instead of moving.
+    Crazyhouse,
+    // a capture explodes the capturer and every piece but pawns around the destination; exploding the king wins.
+    Atomic,
+    // 36 white pawns without a king against the usual black army; black wins by capturing all of them.
+    Horde,
+    // no checks, and the first king to reach the eighth rank wins, unless black's king follows right after.
+    RacingKings,
+    // 10x8 with an archbishop (bishop and knight) and a chancellor (rook and knight) next to the bishops.
+    Capablanca,
+    // Capablanca chess with the archbishop and the chancellor next to the king.
+    Gothic,
+}
+
+pub const VARIANTS: [Variant; 10] = [
+    Variant::Standard, Variant::KingOfTheHill, Variant::ThreeCheck, Variant::Antichess, Variant::Crazyhouse, Variant::Atomic,
+    Variant::Horde, Variant::RacingKings, Variant::Capablanca, Variant::Gothic,
+];
+
+// pieces that can be in a pocket, in the order of FEN
+pub const POCKET_PIECES: [Piece; 7] = [Piece::Queen, Piece::Chancellor, Piece::Archbishop, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn];
+
+const HORDE_FEN: &str = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1";
+const RACING_KINGS_FEN: &str = "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1";
+const CAPABLANCA_FEN: &str = "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1";
+const GOTHIC_FEN: &str = "rnbqckabnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNBQCKABNR w KQkq - 0 1";
+
+impl Variant {
+    // as in UCI_Variant
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::ThreeCheck => "3check",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+            Variant::Horde => "horde",
+            Variant::RacingKings => "racingkings",
+            Variant::Capablanca => "capablanca",
+            Variant::Gothic => "gothic",
+        }
+    }
+
+    // accepts the UCI_Variant names and the PGN ones, e.g. "King of the Hill", "Three-check".
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+        match name.as_str() {
+            "chess" | "standard" => Some(Variant::Standard),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "3check" | "threecheck" => Some(Variant::ThreeCheck),
+            "antichess" | "giveaway" | "suicide" => Some(Variant::Antichess),
+            "crazyhouse" | "zh" => Some(Variant::Crazyhouse),
+            "atomic" => Some(Variant::Atomic),
+            "horde" => Some(Variant::Horde),
+            "racingkings" => Some(Variant::RacingKings),
+            "capablanca" => Some(Variant::Capablanca),
+            "gothic" => Some(Variant::Gothic),
+            _ => None,
+        }
+    }
+
+    // as the PGN Variant tag
+    pub fn pgn_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Antichess => "Antichess",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::Atomic => "Atomic",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+            Variant::Capablanca => "Capablanca",
+            Variant::Gothic => "Gothic",
+        }
+    }
+
+    // the start position, if it is not the orthodox one.
+    pub fn start_fen(&self) -> Option<&'static str> {
+        match self {
+            Variant::Horde => Some(HORDE_FEN),
+            Variant::RacingKings => Some(RACING_KINGS_FEN),
+            Variant::Capablanca => Some(CAPABLANCA_FEN),
+            Variant::Gothic => Some(GOTHIC_FEN),
+            _ => None,
+        }
+    }
+
+    // by index in VARIANTS, for javascript
+    pub fn from_u8(value: u8) -> Option<Self> {
+        VARIANTS.get(value as usize).copied()
+    }
+}
+
+impl ChessBoard {
+    pub fn new_variant(variant: Variant) -> Self {
+        if let Some(fen) = variant.start_fen() {
+            return ChessBoard::from_variant_fen(fen, variant).unwrap();
+        }
+        let mut ans = ChessBoard::new();
+        ans.set_variant(variant);
+        ans
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    // play the position under other rules; there is no castling in antichess.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.checks = [0, 0];
+        self.pockets = [[0; 8]; 2];
+        if variant == Variant::Antichess {
+            self.castle_rights = [false; 4];
+        }
+    }
+
+    // number of checks given by the player, counted in three-check only.
+    pub fn checks_given(&self, player: Player) -> usize {
+        self.checks[player as usize] as usize
+    }
+
+    // number of pieces of this kind the player can drop, in crazyhouse.
+    pub fn pocket(&self, player: Player, piece: Piece) -> usize {
+        if piece == Piece::King { 0 } else { self.pockets[player as usize][piece as usize] as usize }
+    }
+
+    // whether the piece on `pos` was promoted from a pawn.
+    pub fn is_promoted(&self, pos: usize) -> bool {
+        self.promoted >> pos & 1 == 1
+    }
+
+    // drops of the player to move on every empty square, except pawns on the first and last ranks.
+    pub(super) fn drop_moves(&self, ans: &mut Vec<MoveRequest>) {
+        for piece in POCKET_PIECES {
+            if self.pocket(self.player, piece) == 0 {
+                continue;
+            }
+            let squares = if piece == Piece::Pawn { self.files..self.last_rank() } else { 0..self.squares() };
+            for pos in squares {
+                if self.board[pos].is_none() {
+                    ans.push(mreq_new_drop(pos, &Some((self.player, piece))));
+                }
+            }
+        }
+    }
+
+    // the winner if the game is over by a rule of the variant, whether or not there are legal moves left.
+    pub fn variant_winner(&self) -> Option<Player> {
+        self.variant_outcome().flatten()
+    }
+
+    // Some(winner) if the game is over by a rule of the variant, where a winner of None is a draw.
+    pub fn variant_outcome(&self) -> Option<Option<Player>> {
+        let winner = match self.variant {
+            Variant::Standard | Variant::Crazyhouse | Variant::Capablanca | Variant::Gothic => None,
+            Variant::KingOfTheHill => self.hill().into_iter().find_map(|p| match self.board[p] {
+                Some((player, Piece::King)) => Some(player),
+                _ => None,
+            }),
+            Variant::ThreeCheck => [Player::White, Player::Black].into_iter().find(|p| self.checks[*p as usize] >= 3),
+            Variant::Atomic => [Player::White, Player::Black].into_iter().find(|p| !self.has_king(p.opponent())),
+            Variant::Antichess => {
+                if self.board.iter().flatten().all(|(player, _)| *player != self.player) {
+                    Some(self.player)
+                } else {
+                    None
+                }
+            },
+            Variant::Horde => {
+                if self.board.iter().flatten().all(|(player, _)| *player != Player::White) {
+                    Some(Player::Black)
+                } else {
+                    None
+                }
+            },
+            Variant::RacingKings => {
+                let arrived = |player: Player| (self.last_rank()..self.squares()).any(|p| self.board[p] == Some((player, Piece::King)));
+                match (arrived(Player::White), arrived(Player::Black)) {
+                    (true, true) => return Some(None),
+                    (false, true) => Some(Player::Black),
+                    // black moves second, so gets one move to catch up.
+                    (true, false) if self.player == Player::White || !self.can_reach_goal(Player::Black) => Some(Player::White),
+                    _ => None,
+                }
+            },
+        };
+        winner.map(Some)
+    }
+
+    // the winner when the player to move has no legal move, None for a draw.
+    pub fn no_moves_winner(&self) -> Option<Player> {
+        if let Some(outcome) = self.variant_outcome() {
+            return outcome;
+        }
+        match self.variant {
+            Variant::Antichess => Some(self.player),
+            _ if self.is_checked() => Some(self.player.opponent()),
+            _ => None,
+        }
+    }
+
+    /**
+     * Whether the player could still win by some sequence of legal moves, e.g. to decide if running out of time loses.
+     * In standard chess a lone king, a lone knight against a lone king and bishops all on squares of one colour (unless
+     * the opponent has a piece to block with, other than such bishops) can not mate.
+     */
+    pub fn has_mating_material(&self, player: Player) -> bool {
+        let pieces = |player: Player| self.board[..self.squares()].iter().enumerate()
+            .filter_map(move |(pos, cell)| cell.filter(|c| c.0 == player && c.1 != Piece::King).map(|c| (pos, c.1)));
+        match self.variant {
+            // the king alone can walk to its goal.
+            Variant::KingOfTheHill | Variant::RacingKings | Variant::Antichess => return true,
+            Variant::Crazyhouse if POCKET_PIECES.iter().any(|p| self.pocket(player, *p) > 0) => return true,
+            // a lone king wins the horde by taking the last white piece.
+            Variant::Horde if player == Player::Black => return true,
+            Variant::ThreeCheck | Variant::Atomic | Variant::Horde => return pieces(player).next().is_some(),
+            _ => (),
+        }
+        let color = |pos: usize| (pos / self.files + pos % self.files) % 2;
+        let (mut knights, mut bishops) = (0, [0, 0]);
+        for (pos, piece) in pieces(player) {
+            match piece {
+                Piece::Knight => knights += 1,
+                Piece::Bishop => bishops[color(pos)] += 1,
+                _ => return true,
+            }
+        }
+        let defenders: Vec<(usize, Piece)> = pieces(player.opponent()).collect();
+        match (knights, bishops) {
+            (0, [0, 0]) => false,
+            (1, [0, 0]) => !defenders.is_empty(),
+            (0, [_, 0]) | (0, [0, _]) => {
+                // the king can only be blocked in by a piece that stands, or can stand, on the other colour.
+                let own = if bishops[0] > 0 { 0 } else { 1 };
+                defenders.iter().any(|(pos, piece)| *piece != Piece::Bishop || color(*pos) != own)
+            },
+            _ => true,
+        }
+    }
+
+    // the four center squares, d4, e4, d5 and e5 on 8x8.
+    fn hill(&self) -> [usize; 4] {
+        let (i, j) = (self.ranks / 2 - 1, self.files / 2 - 1);
+        let pos = i * self.files + j;
+        [pos, pos + 1, pos + self.files, pos + self.files + 1]
+    }
+
+    // whether the king of the player to move can legally step onto the last rank, in racing kings.
+    fn can_reach_goal(&self, player: Player) -> bool {
+        let Some(pos) = self.board.iter().position(|c| *c == Some((player, Piece::King))) else { return false };
+        let mut targets = vec![];
+        self.king_ordinary_move(pos, player, &mut targets);
+        targets.into_iter().filter(|p| *p >= self.last_rank()).any(|p| {
+            let mut t = *self;
+            t.move_pieces(mreq_new(pos, p));
+            !t.get_attacking_range(player.opponent()).1 && !t.get_attacking_range(player).1
+        })
+    }
+
+    fn has_king(&self, player: Player) -> bool {
+        self.board.contains(&Some((player, Piece::King)))
+    }
+
+    // in atomic kings next to each other can not be checked, as capturing one would explode the other.
+    pub(super) fn kings_adjacent(&self) -> bool {
+        let white = self.board.iter().position(|c| *c == Some((Player::White, Piece::King)));
+        let black = self.board.iter().position(|c| *c == Some((Player::Black, Piece::King)));
+        match (white, black) {
+            (Some(w), Some(b)) => (w / self.files).abs_diff(b / self.files) <= 1 && (w % self.files).abs_diff(b % self.files) <= 1,
+            _ => false,
+        }
+    }
+
+    // atomic capture on `pos`: the capturer, now on `pos`, and the pieces but pawns around it are removed, and
+    // recorded in `ans` for undo_move.
+    pub(super) fn explode(&mut self, pos: usize, ans: &mut MoveResult) {
+        *ans |= (cell_to_u8(&self.board[pos]) as MoveResult) << EXPLODED_CAPTURER_SHIFT;
+        self.board[pos] = None;
+        for (k, neighbor) in self.neighbors(pos).into_iter().enumerate() {
+            let Some(p) = neighbor else { continue };
+            if matches!(self.board[p], None | Some((_, Piece::Pawn))) {
+                continue;
+            }
+            *ans |= (cell_to_u8(&self.board[p]) as MoveResult) << (EXPLODED_SHIFT + 5 * k);
+            self.board[p] = None;
+            for side in 0..4 {
+                let base = if side < 2 { 0 } else { self.last_rank() };
+                if p == base + self.castle_files[side] {
+                    self.castle_rights[side] = false;
+                }
+            }
+        }
+    }
+
+    // put the exploded pieces back, except the capturer which undo_move restores on its origin.
+    pub(super) fn unexplode(&mut self, mres: MoveResult) {
+        for (pos, cell) in self.exploded_pieces(mres) {
+            self.board[pos] = Some(cell);
+        }
+    }
+
+    // whether the move of `player` that led here was legal in atomic: its king survived, and is not in check unless
+    // the enemy king exploded.
+    pub(super) fn atomic_legal_after(&self, player: Player) -> bool {
+        self.has_king(player) && (!self.has_king(player.opponent()) || self.kings_adjacent() || !self.get_attacking_range(player.opponent()).1)
+    }
+
+    // the pieces around the destination that exploded with an atomic capture, not counting the capturer.
+    pub fn exploded_pieces(&self, mres: MoveResult) -> Vec<(usize, (Player, Piece))> {
+        self.neighbors(get_pos1!(mres)).into_iter().enumerate().filter_map(|(k, neighbor)| {
+            Some((neighbor?, cell_from_u8((mres >> (EXPLODED_SHIFT + 5 * k) & 0x1f) as u8)?))
+        }).collect()
+    }
+
+    // the 8 squares around `pos`, None off the board.
+    fn neighbors(&self, pos: usize) -> [Option<usize>; 8] {
+        let mut ans = [None; 8];
+        let (i, j) = ((pos / self.files) as isize, (pos % self.files) as isize);
+        let mut k = 0;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                if di == 0 && dj == 0 {
+                    continue;
+                }
+                let (ni, nj) = (i + di, j + dj);
+                if (0..self.ranks as isize).contains(&ni) && (0..self.files as isize).contains(&nj) {
+                    ans[k] = Some(ni as usize * self.files + nj as usize);
+                }
+                k += 1;
+            }
+        }
+        ans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft(variant: Variant, fen: &str, depth: usize) -> u64 {
+        ChessBoard::from_variant_fen(fen, variant).unwrap().perft(depth)
+    }
+
+    #[test]
+    fn king_of_the_hill_perft() {
+        let start = ChessBoard::new_variant(Variant::KingOfTheHill);
+        assert_eq!([start.perft(3), start.perft(4)], [8902, 197281]);
+        // Kd4 and Ke4 win, which takes the 5 replies of the black king away from each.
+        let fen = "4k3/8/8/8/8/4K3/8/8 w - - 0 1";
+        assert_eq!(perft(Variant::Standard, fen, 2), 40);
+        assert_eq!(perft(Variant::KingOfTheHill, fen, 2), 30);
+    }
+
+    #[test]
+    fn three_check_perft() {
+        let start = ChessBoard::new_variant(Variant::ThreeCheck);
+        assert_eq!([start.perft(3), start.perft(4)], [8902, 197281]);
+        // white needs one more check, so Ra8+ wins and the king's 3 replies to it are gone.
+        assert_eq!(perft(Variant::Standard, "4k3/8/8/8/8/8/8/R3K3 w - - 0 1", 2) - 3, perft(Variant::ThreeCheck, "4k3/8/8/8/8/8/8/R3K3 w - - 1+3 0 1", 2));
+    }
+
+    #[test]
+    fn antichess_perft() {
+        let start = ChessBoard::new_variant(Variant::Antichess);
+        assert_eq!([start.perft(3), start.perft(4)], [8067, 153299]);
+        // the capture is forced, and the king may be taken.
+        assert_eq!(perft(Variant::Antichess, "8/8/8/8/3k4/4Q3/8/8 b - - 0 1", 1), 1);
+    }
+
+    #[test]
+    fn crazyhouse_perft() {
+        let start = ChessBoard::new_variant(Variant::Crazyhouse);
+        assert_eq!([start.perft(3), start.perft(4)], [8902, 197281]);
+        // 4 pieces on 62 squares, pawns on the 48 of ranks 2 to 7, and 5 king moves.
+        let fen = "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1";
+        assert_eq!([perft(Variant::Crazyhouse, fen, 1), perft(Variant::Crazyhouse, fen, 2)], [301, 75353]);
+    }
+
+    #[test]
+    fn atomic_perft() {
+        // explosions first change the count at depth 4.
+        let start = ChessBoard::new_variant(Variant::Atomic);
+        assert_eq!([start.perft(3), start.perft(4)], [8902, 197326]);
+        // Kxb2 is the only escape, but a king cannot capture.
+        let fen = "8/8/8/8/8/8/1q6/K6k w - - 0 1";
+        assert_eq!(perft(Variant::Standard, fen, 1), 1);
+        assert_eq!(perft(Variant::Atomic, fen, 1), 0);
+    }
+
+    #[test]
+    fn horde_perft() {
+        let start = ChessBoard::new_variant(Variant::Horde);
+        assert_eq!([start.perft(3), start.perft(4)], [1274, 23310]);
+        // first-rank pawns may step two squares.
+        assert_eq!(perft(Variant::Horde, "4k3/8/8/8/8/8/8/P7 w - - 0 1", 1), 2);
+    }
+
+    #[test]
+    fn racing_kings_perft() {
+        let start = ChessBoard::new_variant(Variant::RacingKings);
+        assert_eq!([start.perft(3), start.perft(4)], [11264, 296242]);
+        // no move may give check, so Ra2 and Rb1 are out.
+        let fen = "8/8/8/8/8/8/1k6/R6K w - - 0 1";
+        assert_eq!(perft(Variant::Standard, fen, 1), 16);
+        assert_eq!(perft(Variant::RacingKings, fen, 1), 14);
+    }
+
+    #[test]
+    fn capablanca_perft() {
+        let start = ChessBoard::new_variant(Variant::Capablanca);
+        assert_eq!([start.perft(3), start.perft(4)], [25228, 805128]);
+        // the archbishop and the chancellor move as a knight plus a bishop or a rook.
+        assert_eq!(perft(Variant::Capablanca, "9k/10/10/10/4A5/10/10/K9 w - - 0 1", 1), 3 + 8 + 14);
+        assert_eq!(perft(Variant::Capablanca, "9k/10/10/10/4C5/10/10/K9 w - - 0 1", 1), 3 + 8 + 16);
+    }
+}
@@ -0,0 +1,35 @@
+// Zobrist keys, generated at compile time with xorshift so that hashes are identical across builds.
+
+use super::MAX_SQUARES;
+
+const fn xorshift(mut x: u64) -> u64 {
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+const fn generate<const N: usize>(seed: u64) -> [u64; N] {
+    let mut ans = [0u64; N];
+    let mut x = seed;
+    let mut i = 0;
+    while i < N {
+        x = xorshift(x);
+        ans[i] = x;
+        i += 1;
+    }
+    ans
+}
+
+// indexed by cell_to_u8(cell) * MAX_SQUARES + pos, cell_to_u8 being at most 16.
+pub static PIECE_KEYS: [u64; 17 * MAX_SQUARES] = generate(0x9e3779b97f4a7c15);
+pub const CASTLE_KEYS: [u64; 4] = generate(0xd1b54a32d192ed03);
+// indexed by the column of the en passant square.
+pub const EN_PASSANT_KEYS: [u64; 16] = generate(0x8cb92ba72f3d8dd7);
+pub const BLACK_TO_MOVE_KEY: u64 = 0xf39cc0605cedc834;
+// indexed by player * 4 + number of checks given, in three-check.
+pub const CHECK_KEYS: [u64; 8] = generate(0x4cf5ad432745937f);
+// indexed by (player * 8 + piece) * 32 + number of pieces in the pocket, in crazyhouse.
+pub const POCKET_KEYS: [u64; 16 * 32] = generate(0x2545f4914f6cdd1d);
+// squares of promoted pieces.
+pub const PROMOTED_KEYS: [u64; MAX_SQUARES] = generate(0x5851f42d4c957f2d);
@@ -0,0 +1,399 @@
+// Chess clocks: time controls of one or more stages with an increment or a delay, and the clock running them.
+
+use std::fmt;
+
+use crate::bot::timer;
+use crate::chessboard::{ChessBoard, Player};
+
+// the current time in milliseconds from any fixed point, so that clocks can be driven by hand when testing.
+pub trait TimeSource {
+    fn now_ms(&self) -> f64;
+}
+
+impl<F: Fn() -> f64> TimeSource for F {
+    fn now_ms(&self) -> f64 {
+        self()
+    }
+}
+
+// time given back for each move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bonus {
+    None,
+    // Fischer: added after every move.
+    Increment(f64),
+    // Bronstein: the time used for the move is given back, up to this much.
+    Bronstein(f64),
+    // simple (US) delay: the clock only starts running after this much of each move.
+    Delay(f64),
+}
+
+// one stage of a time control: `moves` moves, or the rest of the game if None, in `time_ms` more milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stage {
+    pub moves: Option<usize>,
+    pub time_ms: f64,
+    pub bonus: Bonus,
+}
+
+/**
+ * A time control as in the PGN TimeControl tag, in seconds, with stages separated by ':', e.g. `300+3` for 5 minutes
+ * with a 3 second increment, or `40/5400+30:1800+30` for 90 minutes for 40 moves then 30 more, with 30 seconds per
+ * move from the start. A delay is written with `d` (simple) or `b` (Bronstein) instead of `+`, e.g. `300d5`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeControl {
+    pub stages: Vec<Stage>,
+}
+
+impl TimeControl {
+    pub fn sudden_death(time_ms: f64, bonus: Bonus) -> Self {
+        TimeControl { stages: vec![Stage { moves: None, time_ms, bonus }] }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let seconds = |x: &str| x.parse::<f64>().ok().filter(|x| x.is_finite() && *x >= 0.0).map(|x| x * 1000.0)
+            .ok_or(format!("invalid time '{}'", x));
+        let mut stages = vec![];
+        for stage in s.trim().split(':') {
+            let (moves, rest) = match stage.split_once('/') {
+                Some((moves, rest)) => (Some(moves.parse::<usize>().ok().filter(|m| *m > 0).ok_or(format!("invalid number of moves '{}'", moves))?), rest),
+                None => (None, stage),
+            };
+            let (time, bonus) = match rest.find(['+', 'd', 'b']) {
+                Some(k) => {
+                    let value = seconds(&rest[k + 1..])?;
+                    let bonus = match &rest[k..k + 1] {
+                        "+" => Bonus::Increment(value),
+                        "d" => Bonus::Delay(value),
+                        _ => Bonus::Bronstein(value),
+                    };
+                    (&rest[..k], bonus)
+                },
+                None => (rest, Bonus::None),
+            };
+            stages.push(Stage { moves, time_ms: seconds(time)?, bonus });
+        }
+        if stages[..stages.len() - 1].iter().any(|stage| stage.moves.is_none()) {
+            return Err(format!("only the last stage of '{}' may be for the rest of the game", s));
+        }
+        Ok(TimeControl { stages })
+    }
+
+    // the stage of a player who has made `moves` moves; the last one repeats if it has a number of moves.
+    fn stage(&self, moves: usize) -> &Stage {
+        let mut moves = moves;
+        for stage in &self.stages {
+            match stage.moves {
+                Some(n) if moves >= n => moves -= n,
+                _ => return stage,
+            }
+        }
+        self.stages.last().unwrap()
+    }
+
+    // whether a stage ends with the move just made, its time being added to the clock.
+    fn stage_ends(&self, moves: usize) -> bool {
+        let mut moves = moves;
+        for stage in &self.stages {
+            let Some(n) = stage.moves else { return false };
+            if moves <= n {
+                return moves == n;
+            }
+            moves -= n;
+        }
+        let n = self.stages.last().unwrap().moves.unwrap();
+        moves.is_multiple_of(n)
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (k, stage) in self.stages.iter().enumerate() {
+            if k > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{}/", moves)?;
+            }
+            write!(f, "{}", stage.time_ms / 1000.0)?;
+            match stage.bonus {
+                Bonus::None => (),
+                Bonus::Increment(ms) => write!(f, "+{}", ms / 1000.0)?,
+                Bonus::Delay(ms) => write!(f, "d{}", ms / 1000.0)?,
+                Bonus::Bronstein(ms) => write!(f, "b{}", ms / 1000.0)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * The clocks of both players. `start` starts the clock of the player to move, `press` ends their move and starts the
+ * opponent's. A clock that reaches zero stays there, and both stop.
+ */
+pub struct Clock {
+    control: TimeControl,
+    source: Box<dyn TimeSource>,
+    // milliseconds left when the current move of the player began, by Player as usize.
+    remaining: [f64; 2],
+    moves: [usize; 2],
+    running: Option<Player>,
+    // when the move of the running player began.
+    started: f64,
+    flagged: Option<Player>,
+}
+
+impl Clock {
+    // a clock driven by the wall clock.
+    pub fn new(control: TimeControl) -> Self {
+        Self::with_time_source(control, timer::now_ms)
+    }
+
+    pub fn with_time_source(control: TimeControl, source: impl TimeSource + 'static) -> Self {
+        let time = control.stages[0].time_ms;
+        Clock { control, source: Box::new(source), remaining: [time, time], moves: [0, 0], running: None, started: 0.0, flagged: None }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    pub fn running(&self) -> Option<Player> {
+        self.running
+    }
+
+    // moves made by the player on this clock.
+    pub fn moves(&self, player: Player) -> usize {
+        self.moves[player as usize]
+    }
+
+    // start the clock of the player, or keep the one already running. nothing happens once a player has flagged.
+    pub fn start(&mut self, player: Player) {
+        if self.running.is_some() || self.flagged().is_some() {
+            return;
+        }
+        self.running = Some(player);
+        self.started = self.source.now_ms();
+    }
+
+    // pause; the move in progress is charged as if it ended here, without its bonus.
+    pub fn stop(&mut self) {
+        if self.flagged().is_some() {
+            return;
+        }
+        if let Some(player) = self.running {
+            self.remaining[player as usize] = self.remaining_ms(player);
+            self.running = None;
+        }
+    }
+
+    /**
+     * End the move of the running player: charge the time used, add the bonus and the time of a new stage, then start
+     * the opponent's clock. false, and the clock stops, if the player ran out of time first.
+     */
+    pub fn press(&mut self) -> bool {
+        let Some(player) = self.running else { return false };
+        if self.flagged().is_some() {
+            return false;
+        }
+        let now = self.source.now_ms();
+        let elapsed = now - self.started;
+        let p = player as usize;
+        let stage = *self.control.stage(self.moves[p]);
+        self.remaining[p] = self.left_after(player, elapsed);
+        match stage.bonus {
+            Bonus::Increment(ms) => self.remaining[p] += ms,
+            Bonus::Bronstein(ms) => self.remaining[p] += elapsed.min(ms),
+            Bonus::None | Bonus::Delay(_) => (),
+        }
+        self.moves[p] += 1;
+        if self.control.stage_ends(self.moves[p]) {
+            self.remaining[p] += self.control.stage(self.moves[p]).time_ms;
+        }
+        self.running = Some(player.opponent());
+        self.started = now;
+        true
+    }
+
+    // milliseconds left on the clock of the player right now, never below zero.
+    pub fn remaining_ms(&self, player: Player) -> f64 {
+        let p = player as usize;
+        if self.running != Some(player) {
+            return self.remaining[p];
+        }
+        self.left_after(player, self.source.now_ms() - self.started)
+    }
+
+    // time left to the player after thinking `elapsed` milliseconds on the current move.
+    fn left_after(&self, player: Player, elapsed: f64) -> f64 {
+        let used = match self.control.stage(self.moves[player as usize]).bonus {
+            Bonus::Delay(ms) => (elapsed - ms).max(0.0),
+            _ => elapsed,
+        };
+        (self.remaining[player as usize] - used).max(0.0)
+    }
+
+    // the player whose time ran out, if any. the clock stops there.
+    pub fn flagged(&mut self) -> Option<Player> {
+        if self.flagged.is_none() {
+            self.flagged = self.running.filter(|player| self.remaining_ms(*player) <= 0.0);
+            if let Some(player) = self.flagged {
+                self.remaining[player as usize] = 0.0;
+                self.running = None;
+            }
+        }
+        self.flagged
+    }
+
+    /**
+     * Some(winner) once a player has run out of time, where the winner is None (a draw) if the opponent could not
+     * mate in the position by any sequence of legal moves.
+     */
+    pub fn timeout_outcome(&mut self, board: &ChessBoard) -> Option<Option<Player>> {
+        let loser = self.flagged()?;
+        Some(Some(loser.opponent()).filter(|winner| board.has_mating_material(*winner)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // a clock on a time source moved by hand.
+    fn manual_clock(control: &str) -> (Clock, Rc<Cell<f64>>) {
+        let time = Rc::new(Cell::new(0.0));
+        let source = time.clone();
+        (Clock::with_time_source(TimeControl::parse(control).unwrap(), move || source.get()), time)
+    }
+
+    // let the running player think `ms` milliseconds, then press.
+    fn think(clock: &mut Clock, time: &Cell<f64>, ms: f64) -> bool {
+        time.set(time.get() + ms);
+        clock.press()
+    }
+
+    #[test]
+    fn parse_display_round_trip() {
+        for s in ["300", "300+3", "180+2", "300d5", "600b10", "40/5400+30:1800+30", "40/7200:20/3600:900", "0.5+0.1"] {
+            assert_eq!(TimeControl::parse(s).unwrap().to_string(), s);
+        }
+        let control = TimeControl::parse("40/5400+30:1800+30").unwrap();
+        assert_eq!(control.stages, vec![
+            Stage { moves: Some(40), time_ms: 5400000.0, bonus: Bonus::Increment(30000.0) },
+            Stage { moves: None, time_ms: 1800000.0, bonus: Bonus::Increment(30000.0) },
+        ]);
+        for s in ["", "abc", "300+", "0/300", "-5", "300:40/300", "inf"] {
+            assert!(TimeControl::parse(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn fischer_increment() {
+        let (mut clock, time) = manual_clock("60+2");
+        clock.start(Player::White);
+        time.set(5000.0);
+        assert_eq!(clock.remaining_ms(Player::White), 55000.0);
+        assert!(think(&mut clock, &time, 0.0));
+        assert_eq!(clock.remaining_ms(Player::White), 57000.0);
+        assert_eq!(clock.running(), Some(Player::Black));
+        // the increment is added even beyond the starting time.
+        assert!(think(&mut clock, &time, 500.0));
+        assert_eq!(clock.remaining_ms(Player::Black), 61500.0);
+    }
+
+    #[test]
+    fn bronstein_delay() {
+        let (mut clock, time) = manual_clock("60b3");
+        clock.start(Player::White);
+        assert!(think(&mut clock, &time, 2000.0));
+        assert_eq!(clock.remaining_ms(Player::White), 60000.0);
+        assert!(think(&mut clock, &time, 5000.0));
+        assert_eq!(clock.remaining_ms(Player::Black), 58000.0);
+    }
+
+    #[test]
+    fn simple_delay() {
+        let (mut clock, time) = manual_clock("60d3");
+        clock.start(Player::White);
+        time.set(2000.0);
+        assert_eq!(clock.remaining_ms(Player::White), 60000.0);
+        assert!(think(&mut clock, &time, 0.0));
+        assert_eq!(clock.remaining_ms(Player::White), 60000.0);
+        assert!(think(&mut clock, &time, 5000.0));
+        assert_eq!(clock.remaining_ms(Player::Black), 58000.0);
+        // stopping charges the move without its delay.
+        time.set(time.get() + 4000.0);
+        clock.stop();
+        assert_eq!(clock.remaining_ms(Player::White), 59000.0);
+        assert_eq!(clock.running(), None);
+    }
+
+    #[test]
+    fn stage_rollover() {
+        let (mut clock, time) = manual_clock("40/5400+30:1800+30");
+        clock.start(Player::White);
+        for _ in 0..39 {
+            assert!(think(&mut clock, &time, 60000.0));
+            assert!(think(&mut clock, &time, 0.0));
+        }
+        assert_eq!(clock.remaining_ms(Player::White), 5400000.0 - 39.0 * 30000.0);
+        // the 40th move ends the first stage, and its time is added.
+        assert!(think(&mut clock, &time, 60000.0));
+        assert_eq!(clock.moves(Player::White), 40);
+        assert_eq!(clock.remaining_ms(Player::White), 5400000.0 - 40.0 * 30000.0 + 1800000.0);
+        assert_eq!(clock.remaining_ms(Player::Black), 5400000.0 + 39.0 * 30000.0);
+        // the last stage is for the rest of the game, with the same increment.
+        assert!(think(&mut clock, &time, 0.0));
+        assert_eq!(clock.remaining_ms(Player::Black), 5400000.0 + 40.0 * 30000.0 + 1800000.0);
+        assert!(think(&mut clock, &time, 60000.0));
+        assert_eq!(clock.remaining_ms(Player::White), 5400000.0 - 41.0 * 30000.0 + 1800000.0);
+        assert!(!(41..200).any(|moves| clock.control().stage_ends(moves)));
+    }
+
+    #[test]
+    fn repeating_last_stage() {
+        let control = TimeControl::parse("40/7200:20/3600").unwrap();
+        let ends: Vec<usize> = (1..=100).filter(|moves| control.stage_ends(*moves)).collect();
+        assert_eq!(ends, vec![40, 60, 80, 100]);
+        assert_eq!(control.stage(39).time_ms, 7200000.0);
+        assert_eq!(control.stage(40).time_ms, 3600000.0);
+        assert_eq!(control.stage(85).time_ms, 3600000.0);
+
+        let (mut clock, time) = manual_clock("2/10:1/5");
+        clock.start(Player::White);
+        for _ in 0..4 {
+            assert!(think(&mut clock, &time, 1000.0));
+            assert!(think(&mut clock, &time, 0.0));
+        }
+        // 10 seconds, 2 used by the first stage, then 5 more after the 2nd, 3rd and 4th moves, 1 second used for each.
+        assert_eq!(clock.remaining_ms(Player::White), 10000.0 - 4000.0 + 3.0 * 5000.0);
+    }
+
+    #[test]
+    fn timeout_against_lone_king() {
+        let lone_king = ChessBoard::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+
+        // black only has a king, so white running out of time draws.
+        let (mut clock, time) = manual_clock("10");
+        clock.start(Player::White);
+        assert_eq!(clock.timeout_outcome(&lone_king), None);
+        assert!(!think(&mut clock, &time, 10000.0));
+        assert_eq!(clock.flagged(), Some(Player::White));
+        assert_eq!(clock.remaining_ms(Player::White), 0.0);
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.timeout_outcome(&lone_king), Some(None));
+
+        // white can still mate, so black running out of time loses.
+        let (mut clock, time) = manual_clock("10");
+        clock.start(Player::White);
+        assert!(think(&mut clock, &time, 1000.0));
+        time.set(time.get() + 20000.0);
+        assert_eq!(clock.timeout_outcome(&lone_king), Some(Some(Player::White)));
+        // a flagged clock does not start again.
+        clock.start(Player::White);
+        assert_eq!(clock.running(), None);
+    }
+}
@@ -0,0 +1,258 @@
+// A game as played in the UI, on top of the rules in `chessboard`.
+
+pub mod tree;
+pub mod clock;
+pub mod status;
+
+use crate::chessboard::*;
+use crate::chessboard::legality::MoveError;
+use clock::Clock;
+use status::Status;
+use tree::{GameTree, NodeId};
+
+/**
+ * The moves of a game with its clock, if played on time, and how it ended off the board. Moves can still be explored
+ * in the tree after the end; only those made at the end of the main line run the clock.
+ */
+pub struct Game {
+    pub tree: GameTree,
+    pub clock: Option<Clock>,
+    // resignation, agreement or timeout
+    ended: Option<Status>,
+    // moves queued by one player during the turn of the opponent, played in order one per turn.
+    premover: Option<Player>,
+    premoves: Vec<MoveRequest>,
+    // the last premove found illegal when its turn came, with why; the moves queued after it went with it.
+    discarded: Option<(MoveRequest, MoveError)>,
+}
+
+impl Game {
+    pub fn new(board: ChessBoard) -> Self {
+        Game { tree: GameTree::new(board), clock: None, ended: None, premover: None, premoves: vec![], discarded: None }
+    }
+
+    pub fn with_clock(board: ChessBoard, clock: Clock) -> Self {
+        Game { tree: GameTree::new(board), clock: Some(clock), ended: None, premover: None, premoves: vec![], discarded: None }
+    }
+
+    // the last node of the main line, where the game is being played.
+    pub fn live_node(&self) -> NodeId {
+        self.tree.mainline().last().copied().unwrap_or(tree::ROOT)
+    }
+
+    /**
+     * Play a legal move from the current node as in GameTree::play. A move continuing the game presses the clock of the
+     * player, starting it first if needed; the move stands even if it was made too late, the game being lost on time.
+     * The first premove of the opponent is then played at once, or discarded if illegal, going to its node.
+     */
+    pub fn play(&mut self, mreq: MoveRequest) -> Result<NodeId, MoveError> {
+        let live = self.live_node() == self.tree.current() && !self.status().is_over();
+        let player = self.tree.board().player;
+        let id = self.tree.play(mreq)?;
+        if live {
+            if let Some(clock) = self.clock.as_mut() {
+                clock.start(player);
+                clock.press();
+            }
+            if self.status().is_over() {
+                self.stop_clock();
+            }
+            self.play_premove();
+        }
+        Ok(id)
+    }
+
+    /**
+     * Queue a move of the player to be played as soon as it is their turn at the end of the main line, where it is
+     * checked against the position then. Moves of the other player that were queued are dropped. If it is already
+     * their turn there, the move is played now from there, and its node returned.
+     */
+    pub fn premove(&mut self, player: Player, mreq: MoveRequest) -> Result<Option<NodeId>, MoveError> {
+        let current = self.tree.current();
+        self.tree.jump(self.live_node());
+        let over = self.status().is_over();
+        if !over && self.tree.board().player == player {
+            return self.play(mreq).map(Some);
+        }
+        self.tree.jump(current);
+        if over {
+            return Err(MoveError::GameOver);
+        }
+        self.tree.board().check_request(mreq)?;
+        if self.premover != Some(player) {
+            self.premoves.clear();
+            self.premover = Some(player);
+        }
+        self.premoves.push(mreq);
+        Ok(None)
+    }
+
+    // the queued moves in the order they will be played, and whose they are.
+    pub fn premoves(&self) -> (Option<Player>, &[MoveRequest]) {
+        (self.premover, &self.premoves)
+    }
+
+    pub fn clear_premoves(&mut self) {
+        self.premoves.clear();
+    }
+
+    // the premove last discarded and why, once.
+    pub fn take_discarded(&mut self) -> Option<(MoveRequest, MoveError)> {
+        self.discarded.take()
+    }
+
+    // after a move at the end of the main line, the first premove if it is now the turn of its player.
+    fn play_premove(&mut self) {
+        if self.premoves.is_empty() || self.premover != Some(self.tree.board().player) {
+            return;
+        }
+        let mreq = self.premoves.remove(0);
+        let checked = if self.status().is_over() { Err(MoveError::GameOver) } else { self.tree.board().check_move(mreq) };
+        match checked {
+            Ok(()) => {
+                self.play(mreq).unwrap();
+            },
+            Err(e) => {
+                self.discarded = Some((mreq, e));
+                self.premoves.clear();
+            },
+        }
+    }
+
+    // the player gives up; nothing happens once the game is over.
+    pub fn resign(&mut self, player: Player) {
+        if !self.status().is_over() {
+            self.ended = Some(Status::Resignation { winner: player.opponent() });
+            self.stop_clock();
+            self.premoves.clear();
+        }
+    }
+
+    pub fn agree_draw(&mut self) {
+        if !self.status().is_over() {
+            self.ended = Some(Status::Agreement);
+            self.stop_clock();
+            self.premoves.clear();
+        }
+    }
+
+    fn stop_clock(&mut self) {
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop();
+        }
+    }
+
+    /**
+     * How the game stands: ended by resignation, agreement or time, otherwise as decided by the rules in the current
+     * position, with repetitions counted along its line.
+     */
+    pub fn status(&mut self) -> Status {
+        if let Some(ended) = self.ended {
+            return ended;
+        }
+        let live = self.live_node();
+        if let Some(clock) = self.clock.as_mut() {
+            if let Some(winner) = clock.timeout_outcome(&self.tree.node(live).unwrap().board) {
+                self.ended = Some(Status::Timeout { winner });
+                return self.ended.unwrap();
+            }
+        }
+        Status::of_position(self.tree.board(), self.tree.repetitions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(board: &ChessBoard, uci: &str) -> MoveRequest {
+        mreq_new(board.pos_from_string(&uci[..2]).unwrap(), board.pos_from_string(&uci[2..4]).unwrap())
+    }
+
+    fn premove(game: &mut Game, player: Player, uci: &str) -> Result<Option<NodeId>, MoveError> {
+        let mreq = request(game.tree.board(), uci);
+        game.premove(player, mreq)
+    }
+
+    fn play(game: &mut Game, uci: &str) -> NodeId {
+        let mreq = request(game.tree.board(), uci);
+        game.play(mreq).unwrap()
+    }
+
+    fn fen(game: &Game) -> String {
+        game.tree.board().to_fen()
+    }
+
+    #[test]
+    fn premove_played_when_its_turn_comes() {
+        let mut game = Game::new(ChessBoard::new());
+        assert_eq!(premove(&mut game, Player::Black, "e7e5"), Ok(None));
+        assert_eq!(premove(&mut game, Player::Black, "g8f6"), Ok(None));
+        assert_eq!(game.tree.mainline().len(), 0);
+        assert_eq!(game.premoves().1.len(), 2);
+
+        // one premove per turn, played at once and going to its node.
+        play(&mut game, "e2e4");
+        assert_eq!(game.tree.mainline().len(), 2);
+        assert_eq!(game.tree.current(), game.live_node());
+        assert_eq!(fen(&game), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+        assert_eq!(game.premoves(), (Some(Player::Black), &[request(game.tree.board(), "g8f6")][..]));
+        play(&mut game, "d2d4");
+        assert_eq!(fen(&game), "rnbqkb1r/pppp1ppp/5n2/4p3/3PP3/8/PPP2PPP/RNBQKBNR w KQkq - 1 3");
+        assert!(game.premoves().1.is_empty());
+        assert_eq!(game.take_discarded(), None);
+    }
+
+    #[test]
+    fn illegal_premove_discarded_with_the_following_ones() {
+        let mut game = Game::new(ChessBoard::from_fen("4k3/8/8/8/8/8/7r/R3K3 w - - 0 1").unwrap());
+        let h2h8 = request(game.tree.board(), "h2h8");
+        assert_eq!(premove(&mut game, Player::Black, "h2h8"), Ok(None));
+        assert_eq!(premove(&mut game, Player::Black, "h8h1"), Ok(None));
+        // the rook on a8 gives check, which the premove does not answer.
+        play(&mut game, "a1a8");
+        assert_eq!(game.tree.mainline().len(), 1);
+        assert_eq!(game.tree.board().player, Player::Black);
+        assert!(game.premoves().1.is_empty());
+        assert_eq!(game.take_discarded(), Some((h2h8, MoveError::LeavesKingInCheck)));
+        assert_eq!(game.take_discarded(), None);
+
+        // a premove can not be checked at all when queued if it is no move on the board.
+        let mut game = Game::new(ChessBoard::new());
+        assert_eq!(game.premove(Player::Black, mreq_new(12, 100)), Err(MoveError::OffBoard(100)));
+        assert!(game.premoves().1.is_empty());
+        // otherwise only when its turn comes.
+        let e5 = game.tree.board().pos_from_string("e5").unwrap();
+        assert_eq!(premove(&mut game, Player::Black, "e5e4"), Ok(None));
+        play(&mut game, "e2e4");
+        assert_eq!(game.take_discarded().map(|(_, e)| e), Some(MoveError::NoPiece(e5)));
+    }
+
+    #[test]
+    fn premoves_of_the_other_player_replaced() {
+        let mut game = Game::new(ChessBoard::new());
+        play(&mut game, "e2e4");
+        assert_eq!(premove(&mut game, Player::White, "d2d4"), Ok(None));
+        assert_eq!(premove(&mut game, Player::White, "g1f3"), Ok(None));
+        // back to the start, where black may now queue moves in place of white's.
+        assert!(game.tree.take_back());
+        assert_eq!(game.premoves().0, Some(Player::White));
+        assert_eq!(premove(&mut game, Player::Black, "c7c5"), Ok(None));
+        assert_eq!(game.premoves(), (Some(Player::Black), &[request(game.tree.board(), "c7c5")][..]));
+        play(&mut game, "e2e4");
+        assert_eq!(fen(&game), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2");
+    }
+
+    #[test]
+    fn premove_on_own_turn_played_at_once() {
+        let mut game = Game::new(ChessBoard::new());
+        let id = premove(&mut game, Player::White, "e2e4").unwrap();
+        assert_eq!(id, Some(game.tree.current()));
+        assert_eq!(game.tree.mainline().len(), 1);
+        assert!(game.premoves().1.is_empty());
+        // checked like any move.
+        let e7 = game.tree.board().pos_from_string("e7").unwrap();
+        assert_eq!(premove(&mut game, Player::Black, "e7e4"), Err(MoveError::IllegalDestination { from: e7, to: e7 - 24 }));
+        assert_eq!(game.tree.mainline().len(), 1);
+    }
+}
@@ -0,0 +1,197 @@
+// Whether a game is over, how, and who won: the one place the end of a game is decided, for the UI and bot games.
+
+use std::fmt;
+
+use crate::chessboard::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrawRule {
+    ThreefoldRepetition,
+    FiftyMoves,
+    // neither player can mate by any sequence of legal moves.
+    InsufficientMaterial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    InProgress,
+    Checkmate { winner: Player },
+    // no legal move but not in check: a draw, except in antichess where the stalemated player wins.
+    Stalemate { winner: Option<Player> },
+    // by a rule of the variant, e.g. a king on the hill or a third check; racing kings may end in a draw.
+    VariantEnd { winner: Option<Player> },
+    Draw(DrawRule),
+    Resignation { winner: Player },
+    Agreement,
+    // a draw if the opponent of the player out of time can not mate.
+    Timeout { winner: Option<Player> },
+}
+
+impl Status {
+    /**
+     * The status by the rules alone, given how many times the position has occurred, counting this one. A game that
+     * ends on the board by several rules at once ends by the first of: the variant, no legal move, repetition, the
+     * fifty-move rule, insufficient material.
+     */
+    pub fn of_position(board: &ChessBoard, repetitions: usize) -> Self {
+        if let Some(winner) = board.variant_outcome() {
+            return Status::VariantEnd { winner };
+        }
+        if board.possible_moves().len() == 1 {
+            let winner = board.no_moves_winner();
+            return match winner {
+                Some(winner) if board.is_checked() => Status::Checkmate { winner },
+                _ => Status::Stalemate { winner },
+            };
+        }
+        if repetitions >= 3 {
+            return Status::Draw(DrawRule::ThreefoldRepetition);
+        }
+        if board.half_move() >= 100 {
+            return Status::Draw(DrawRule::FiftyMoves);
+        }
+        if !board.has_mating_material(Player::White) && !board.has_mating_material(Player::Black) {
+            return Status::Draw(DrawRule::InsufficientMaterial);
+        }
+        Status::InProgress
+    }
+
+    pub fn is_over(&self) -> bool {
+        *self != Status::InProgress
+    }
+
+    // None for a draw or a game in progress.
+    pub fn winner(&self) -> Option<Player> {
+        match *self {
+            Status::Checkmate { winner } | Status::Resignation { winner } => Some(winner),
+            Status::Stalemate { winner } | Status::VariantEnd { winner } | Status::Timeout { winner } => winner,
+            Status::InProgress | Status::Draw(_) | Status::Agreement => None,
+        }
+    }
+
+    // how the game ended, as in the PGN Termination tag, e.g. "checkmate".
+    pub fn termination(&self) -> &'static str {
+        match self {
+            Status::InProgress => "unterminated",
+            Status::Checkmate { .. } => "checkmate",
+            Status::Stalemate { .. } => "stalemate",
+            Status::VariantEnd { .. } => "variant rules",
+            Status::Draw(DrawRule::ThreefoldRepetition) => "threefold repetition",
+            Status::Draw(DrawRule::FiftyMoves) => "fifty-move rule",
+            Status::Draw(DrawRule::InsufficientMaterial) => "insufficient material",
+            Status::Resignation { .. } => "resignation",
+            Status::Agreement => "agreement",
+            Status::Timeout { .. } => "time forfeit",
+        }
+    }
+
+    // "1-0", "0-1", "1/2-1/2", or "*" while in progress.
+    pub fn to_pgn(&self) -> &'static str {
+        match self.winner() {
+            _ if !self.is_over() => "*",
+            Some(Player::White) => "1-0",
+            Some(Player::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+// e.g. "white wins by checkmate", "draw by stalemate"
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.winner() {
+            _ if !self.is_over() => write!(f, "in progress"),
+            Some(Player::White) => write!(f, "white wins by {}", self.termination()),
+            Some(Player::Black) => write!(f, "black wins by {}", self.termination()),
+            None => write!(f, "draw by {}", self.termination()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chessboard::variant::Variant;
+    use crate::game::tree::GameTree;
+
+    fn status(fen: &str) -> Status {
+        status_in(Variant::Standard, fen)
+    }
+
+    fn status_in(variant: Variant, fen: &str) -> Status {
+        Status::of_position(&ChessBoard::from_variant_fen(fen, variant).unwrap(), 1)
+    }
+
+    #[test]
+    fn checkmate_and_stalemate() {
+        assert_eq!(status("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), Status::InProgress);
+        let fools_mate = status("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(fools_mate, Status::Checkmate { winner: Player::Black });
+        assert_eq!((fools_mate.to_pgn(), fools_mate.to_string().as_str()), ("0-1", "black wins by checkmate"));
+        assert_eq!(status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), Status::Stalemate { winner: None });
+        // the stalemated player wins in antichess.
+        assert_eq!(status_in(Variant::Antichess, "8/8/8/8/8/p7/P7/8 w - - 0 1"), Status::Stalemate { winner: Some(Player::White) });
+    }
+
+    #[test]
+    fn variant_end() {
+        assert_eq!(status_in(Variant::KingOfTheHill, "4k3/8/8/8/4K3/8/8/8 b - - 0 1"), Status::VariantEnd { winner: Some(Player::White) });
+        // the same position in standard chess is a draw.
+        assert_eq!(status("4k3/8/8/8/4K3/8/8/8 b - - 0 1"), Status::Draw(DrawRule::InsufficientMaterial));
+        assert_eq!(status_in(Variant::ThreeCheck, "4k3/8/8/8/8/8/4Q3/4K3 b - - 0+3 0 1"), Status::VariantEnd { winner: Some(Player::White) });
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut tree = GameTree::new(ChessBoard::new());
+        let mut counts = vec![];
+        for _ in 0..2 {
+            for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                let mreq = tree.board().parse_uci_move(uci).unwrap();
+                tree.play(mreq).unwrap();
+                counts.push(Status::of_position(tree.board(), tree.repetitions()));
+            }
+        }
+        assert_eq!(counts[..7], [Status::InProgress; 7]);
+        assert_eq!(counts[7], Status::Draw(DrawRule::ThreefoldRepetition));
+        // the position occurs a third time only in the main line, not when going back.
+        tree.back();
+        assert_eq!(Status::of_position(tree.board(), tree.repetitions()), Status::InProgress);
+    }
+
+    #[test]
+    fn fifty_moves() {
+        assert_eq!(status("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 99 80"), Status::InProgress);
+        assert_eq!(status("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 100 80"), Status::Draw(DrawRule::FiftyMoves));
+        // mate on the hundredth half move still counts.
+        assert_eq!(status("3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 100 80"), Status::Checkmate { winner: Player::White });
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let draw = Status::Draw(DrawRule::InsufficientMaterial);
+        for fen in [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KN3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KB3/8/8 w - - 0 1",
+            // bishops all on squares of one colour.
+            "5b2/8/4k3/8/8/3K4/8/2B5 w - - 0 1",
+            "8/8/4k3/8/8/3KB3/8/2B5 w - - 0 1",
+        ] {
+            assert_eq!(status(fen), draw, "{}", fen);
+        }
+        for fen in [
+            // a bishop of the other colour, or a knight, can block the king in.
+            "4b3/8/4k3/8/8/3K4/8/2B5 w - - 0 1",
+            "8/8/4k1n1/8/8/3KN3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KNN2/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1",
+        ] {
+            assert_eq!(status(fen), Status::InProgress, "{}", fen);
+        }
+        // a king alone may still win some variants.
+        assert_eq!(status_in(Variant::ThreeCheck, "8/8/4k3/8/8/3K4/8/8 w - - 0 1"), draw);
+        assert_eq!(status_in(Variant::Crazyhouse, "8/8/4k3/8/8/3K4/8/8[N] w - - 0 1"), Status::InProgress);
+        assert_eq!(status_in(Variant::KingOfTheHill, "8/8/8/8/8/8/k7/7K w - - 0 1"), Status::InProgress);
+    }
+}
@@ -0,0 +1,375 @@
+// The moves of a game as a tree: the main line with its variations, each node caching the position after its move so
+// that any of them can be shown at once.
+
+use crate::chessboard::*;
+use crate::chessboard::legality::MoveError;
+
+pub type NodeId = usize;
+
+// the start position, which has no move.
+pub const ROOT: NodeId = 0;
+
+pub struct Node {
+    pub parent: Option<NodeId>,
+    // the first child continues the line, the others are its variations in order.
+    pub children: Vec<NodeId>,
+    // the move leading here and its result from do_move, 0 at the root.
+    pub mreq: MoveRequest,
+    pub mres: MoveResult,
+    pub san: String,
+    pub board: ChessBoard,
+}
+
+// the tree in the order of PGN movetext, e.g. `1. e4 e5 (1... c5 2. Nf3) 2. Nf3`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token {
+    Move(NodeId),
+    StartVariation,
+    EndVariation,
+}
+
+pub struct GameTree {
+    // deleted nodes are left as None, so that the ids of the others stay valid.
+    nodes: Vec<Option<Node>>,
+    current: NodeId,
+}
+
+impl GameTree {
+    pub fn new(board: ChessBoard) -> Self {
+        let root = Node { parent: None, children: vec![], mreq: 0, mres: 0, san: String::new(), board };
+        GameTree { nodes: vec![Some(root)], current: ROOT }
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id)?.as_ref()
+    }
+
+    fn get(&self, id: NodeId) -> &Node {
+        self.nodes[id].as_ref().unwrap()
+    }
+
+    fn get_mut(&mut self, id: NodeId) -> &mut Node {
+        self.nodes[id].as_mut().unwrap()
+    }
+
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+
+    // the position at the current node.
+    pub fn board(&self) -> &ChessBoard {
+        &self.get(self.current).board
+    }
+
+    /**
+     * Play a legal move from the current node and go to the node it leads to: the existing one if the move was played
+     * here before, otherwise a new last variation (or the continuation of the line, at its end).
+     */
+    pub fn play(&mut self, mreq: MoveRequest) -> Result<NodeId, MoveError> {
+        let node = self.get(self.current);
+        if let Some(child) = node.children.iter().copied().find(|c| self.get(*c).mreq == mreq) {
+            self.current = child;
+            return Ok(child);
+        }
+        node.board.check_move(mreq)?;
+        let mut board = node.board;
+        let san = board.move_to_san(mreq);
+        let mres = board.do_move(mreq);
+        let id = self.nodes.len();
+        self.nodes.push(Some(Node { parent: Some(self.current), children: vec![], mreq, mres, san, board }));
+        self.get_mut(self.current).children.push(id);
+        self.current = id;
+        Ok(id)
+    }
+
+    // one move back; false at the start.
+    pub fn back(&mut self) -> bool {
+        match self.get(self.current).parent {
+            Some(parent) => {
+                self.current = parent;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // one move forward along the current line; false at its end.
+    pub fn forward(&mut self) -> bool {
+        match self.get(self.current).children.first() {
+            Some(child) => {
+                self.current = *child;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn to_start(&mut self) {
+        self.current = ROOT;
+    }
+
+    // the last move of the current line.
+    pub fn to_end(&mut self) {
+        while self.forward() {}
+    }
+
+    // false if there is no such node.
+    pub fn jump(&mut self, id: NodeId) -> bool {
+        if self.node(id).is_none() {
+            return false;
+        }
+        self.current = id;
+        true
+    }
+
+    // whether the node is on the main line, that is the first child of each of its ancestors.
+    pub fn is_mainline(&self, id: NodeId) -> bool {
+        let mut id = id;
+        while let Some(parent) = self.get(id).parent {
+            if self.get(parent).children[0] != id {
+                return false;
+            }
+            id = parent;
+        }
+        true
+    }
+
+    /**
+     * Move the variation the node is in one place up among its siblings where it branches off, so that it continues
+     * the line once it is first. Repeat to make it the main line. false if the node is on the main line.
+     */
+    pub fn promote_variation(&mut self, id: NodeId) -> bool {
+        if self.node(id).is_none() {
+            return false;
+        }
+        let mut child = id;
+        while let Some(parent) = self.get(child).parent {
+            let children = &mut self.get_mut(parent).children;
+            let k = children.iter().position(|c| *c == child).unwrap();
+            if k > 0 {
+                children.swap(k - 1, k);
+                return true;
+            }
+            child = parent;
+        }
+        false
+    }
+
+    /**
+     * Remove the node with the moves after it, going to its parent if the current node was among them. false for the
+     * root, which can not be removed.
+     */
+    pub fn delete_variation(&mut self, id: NodeId) -> bool {
+        let Some(parent) = self.node(id).and_then(|n| n.parent) else { return false };
+        self.get_mut(parent).children.retain(|c| *c != id);
+        let mut removed = vec![id];
+        while let Some(k) = removed.pop() {
+            if k == self.current {
+                self.current = parent;
+            }
+            let node = self.nodes[k].take().unwrap();
+            removed.extend(node.children);
+        }
+        true
+    }
+
+    // take back the last move played, forgetting it and the moves after it; false at the start.
+    pub fn take_back(&mut self) -> bool {
+        self.delete_variation(self.current)
+    }
+
+    // the nodes from the first move to the current one.
+    pub fn line(&self) -> Vec<NodeId> {
+        let mut ans = vec![];
+        let mut id = self.current;
+        while let Some(parent) = self.get(id).parent {
+            ans.push(id);
+            id = parent;
+        }
+        ans.reverse();
+        ans
+    }
+
+    // how many times the current position occurred on the way here, counting this one.
+    pub fn repetitions(&self) -> usize {
+        let hash = self.board().hash();
+        let mut ans = 0;
+        let mut id = Some(self.current);
+        while let Some(k) = id {
+            if self.get(k).board.hash() == hash {
+                ans += 1;
+            }
+            id = self.get(k).parent;
+        }
+        ans
+    }
+
+    pub fn mainline(&self) -> Vec<NodeId> {
+        let mut ans = vec![];
+        let mut id = ROOT;
+        while let Some(child) = self.get(id).children.first() {
+            ans.push(*child);
+            id = *child;
+        }
+        ans
+    }
+
+    // the whole tree in the order of PGN movetext: each move is followed by the variations to it, then the line goes on.
+    pub fn tokens(&self) -> Vec<Token> {
+        let mut ans = vec![];
+        self.line_tokens(ROOT, &mut ans);
+        ans
+    }
+
+    fn line_tokens(&self, from: NodeId, ans: &mut Vec<Token>) {
+        let mut id = from;
+        while let Some((first, others)) = self.get(id).children.split_first() {
+            ans.push(Token::Move(*first));
+            for variation in others {
+                ans.push(Token::StartVariation);
+                ans.push(Token::Move(*variation));
+                self.line_tokens(*variation, ans);
+                ans.push(Token::EndVariation);
+            }
+            id = *first;
+        }
+    }
+
+    // the number of the move leading to the node as written before it, e.g. `12.` or `12...` for black.
+    pub fn move_number(&self, id: NodeId) -> String {
+        let Some(parent) = self.get(id).parent else { return String::new() };
+        let board = &self.get(parent).board;
+        format!("{}{}", board.full_move(), if board.player == Player::White { "." } else { "..." })
+    }
+
+    // movetext with the variations in parentheses; black moves only get a number at the start of a line.
+    pub fn to_pgn_movetext(&self) -> String {
+        let mut ans: Vec<String> = vec![];
+        let mut numbered = true;
+        for token in self.tokens() {
+            match token {
+                Token::Move(id) => {
+                    if numbered || self.get(self.get(id).parent.unwrap()).board.player == Player::White {
+                        ans.push(self.move_number(id));
+                    }
+                    ans.push(self.get(id).san.clone());
+                    numbered = false;
+                },
+                Token::StartVariation => {
+                    ans.push("(".to_string());
+                    numbered = true;
+                },
+                Token::EndVariation => {
+                    ans.push(")".to_string());
+                    numbered = true;
+                },
+            }
+        }
+        ans.join(" ").replace("( ", "(").replace(" )", ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(tree: &mut GameTree, san: &str) -> NodeId {
+        let mreq = tree.board().parse_san(san).unwrap();
+        tree.play(mreq).unwrap()
+    }
+
+    #[test]
+    fn replaying_a_move_reuses_its_node() {
+        let mut tree = GameTree::new(ChessBoard::new());
+        let e4 = play(&mut tree, "e4");
+        assert!(tree.back());
+        assert_eq!(play(&mut tree, "e4"), e4);
+        assert_eq!(tree.get(ROOT).children, [e4]);
+        assert!(tree.node(e4 + 1).is_none());
+        // an illegal move leaves the tree as it was.
+        let e4e6 = mreq_new(tree.board().pos_from_string("e4").unwrap(), tree.board().pos_from_string("e6").unwrap());
+        assert!(tree.play(e4e6).is_err());
+        assert_eq!(tree.current(), e4);
+    }
+
+    #[test]
+    fn promoting_a_variation_twice_makes_it_the_main_line() {
+        let mut tree = GameTree::new(ChessBoard::new());
+        let e4 = play(&mut tree, "e4");
+        tree.to_start();
+        let d4 = play(&mut tree, "d4");
+        tree.to_start();
+        let c4 = play(&mut tree, "c4");
+        let nf6 = play(&mut tree, "Nf6");
+        assert!(!tree.is_mainline(nf6));
+        assert!(tree.promote_variation(nf6));
+        assert_eq!(tree.get(ROOT).children, [e4, c4, d4]);
+        assert!(!tree.is_mainline(nf6));
+        assert!(tree.promote_variation(nf6));
+        assert_eq!(tree.get(ROOT).children, [c4, e4, d4]);
+        assert!(tree.is_mainline(nf6));
+        assert_eq!(tree.mainline(), [c4, nf6]);
+        assert!(!tree.promote_variation(nf6));
+    }
+
+    #[test]
+    fn deleting_the_current_node_goes_to_its_parent() {
+        let mut tree = GameTree::new(ChessBoard::new());
+        let e4 = play(&mut tree, "e4");
+        let e5 = play(&mut tree, "e5");
+        let nf3 = play(&mut tree, "Nf3");
+        assert!(tree.delete_variation(e5));
+        assert_eq!(tree.current(), e4);
+        assert!(tree.node(e5).is_none() && tree.node(nf3).is_none());
+        assert_eq!(tree.mainline(), [e4]);
+        // a node other than the current one and its ancestors leaves it where it is.
+        let c5 = play(&mut tree, "c5");
+        assert!(tree.back());
+        let e5 = play(&mut tree, "e5");
+        assert!(tree.delete_variation(c5));
+        assert_eq!(tree.current(), e5);
+        assert!(tree.take_back());
+        assert_eq!(tree.current(), e4);
+        assert!(tree.take_back());
+        assert!(!tree.take_back());
+        assert!(!tree.delete_variation(ROOT));
+    }
+
+    #[test]
+    fn repetitions() {
+        let mut tree = GameTree::new(ChessBoard::new());
+        assert_eq!(tree.repetitions(), 1);
+        for _ in 0..2 {
+            for san in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+                play(&mut tree, san);
+            }
+        }
+        assert_eq!(tree.repetitions(), 3);
+        assert_eq!(tree.line().len(), 8);
+        assert!(tree.back());
+        assert_eq!(tree.repetitions(), 2);
+    }
+
+    #[test]
+    fn movetext() {
+        let mut tree = GameTree::new(ChessBoard::new());
+        let e4 = play(&mut tree, "e4");
+        let e5 = play(&mut tree, "e5");
+        tree.back();
+        let c5 = play(&mut tree, "c5");
+        let nf3 = play(&mut tree, "Nf3");
+        let nc6 = play(&mut tree, "Nc6");
+        tree.back();
+        let d6 = play(&mut tree, "d6");
+        tree.jump(e5);
+        let main_nf3 = play(&mut tree, "Nf3");
+        assert_eq!(tree.to_pgn_movetext(), "1. e4 e5 (1... c5 2. Nf3 Nc6 (2... d6)) 2. Nf3");
+        use Token::*;
+        assert_eq!(tree.tokens(), [
+            Move(e4), Move(e5), StartVariation, Move(c5), Move(nf3), Move(nc6), StartVariation, Move(d6), EndVariation,
+            EndVariation, Move(main_nf3),
+        ]);
+        assert_eq!(tree.move_number(main_nf3), "2.");
+        assert_eq!(tree.move_number(d6), "2...");
+    }
+}
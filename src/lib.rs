@@ -1,11 +1,14 @@
-use bot::SyncChessMover;
 use wasm_bindgen::prelude::*;
-use chessboard::{ChessBoard, Player};
+use chessboard::{cell_from_u8, ChessBoard, MoveRequest, Player};
+use chessboard::variant::{Variant, POCKET_PIECES};
+use game::clock::{Clock, TimeControl};
+use game::status::{DrawRule, Status};
+use game::tree::Token;
+use bot::{ChessMover, random_bot::RANDOM_BOT, naive_bot};
 
-use crate::{bot::{random_bot::RANDOM_BOT, naive_bot::NAIVE_BOT}, chessboard::{MoveRequest, MoveResult}};
-mod chessboard;
-mod game;
-mod bot;
+pub mod chessboard;
+pub mod game;
+pub mod bot;
 
 #[wasm_bindgen]
 extern {
@@ -13,67 +16,387 @@ extern {
     pub fn movePiece(pos1: usize, pos2: usize, robot: bool);
 }
 
+// 0 for white, 1 for black, as in javascript.
+fn player_from_u8(player: u8) -> Result<Player, JsError> {
+    match player {
+        0 => Ok(Player::White),
+        1 => Ok(Player::Black),
+        _ => Err(JsError::new(&format!("invalid player {}", player))),
+    }
+}
+
+// a position, owned by javascript and freed with `free()`.
 #[wasm_bindgen]
-pub fn cb_new() -> *mut ChessBoard {
-    let cb = ChessBoard::new();
-    Box::into_raw(Box::new(cb))
+pub struct Board {
+    board: ChessBoard,
 }
 
 #[wasm_bindgen]
-pub fn cb_delete(cb: *mut ChessBoard) {
-    let b = unsafe {
-        Box::from_raw(cb)
-    };
-    drop(b);
+impl Board {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Board {
+        Board { board: ChessBoard::new() }
+    }
+
+    // Chess960 start position by its index in 0..960.
+    pub fn chess960(index: usize) -> Board {
+        Board { board: ChessBoard::new_chess960(index) }
+    }
+
+    // start position of a variant by its index in chessboard::variant::VARIANTS.
+    pub fn variant(variant: u8) -> Result<Board, JsError> {
+        let variant = Variant::from_u8(variant).ok_or_else(|| JsError::new(&format!("invalid variant {}", variant)))?;
+        Ok(Board { board: ChessBoard::new_variant(variant) })
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Board, JsError> {
+        Ok(Board { board: ChessBoard::from_fen(fen).map_err(|e| JsError::new(&e))? })
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.board.to_fen()
+    }
+
+    // as in ChessBoard::serialize, starting with the files and ranks.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.board.serialize()
+    }
+
+    // crazyhouse pockets: the number of queens, chancellors, archbishops, rooks, bishops, knights and pawns of white,
+    // then of black.
+    pub fn pockets(&self) -> Vec<u8> {
+        [Player::White, Player::Black].iter()
+            .flat_map(|player| POCKET_PIECES.iter().map(|piece| self.board.pocket(*player, *piece) as u8))
+            .collect()
+    }
+
+    // whether the player to move is in check, then the legal moves, as in ChessBoard::possible_moves.
+    pub fn possible_moves(&self) -> Vec<MoveRequest> {
+        self.board.possible_moves()
+    }
+
+    // the low 32 bits of the move result; the others only hold what undo_move needs in atomic.
+    pub fn do_move(&mut self, m: MoveRequest) -> Result<u32, JsError> {
+        Ok(self.board.try_move(m).map_err(|e| JsError::new(&format!("illegal move: {}", e)))? as u32)
+    }
+
+    // the squares attacked by the player.
+    pub fn attacking_range(&self, attacker: u8) -> Result<Vec<u8>, JsError> {
+        let range = self.board.get_attacking_range(player_from_u8(attacker)?).0;
+        Ok((0..self.board.squares()).filter(|pos| range >> pos & 1 == 1).map(|pos| pos as u8).collect())
+    }
+
+    // board editor: put the piece cell_to_u8(cell) on `pos`, 0 to empty it.
+    pub fn set_piece(&mut self, pos: usize, cell: u8) -> Result<(), JsError> {
+        if pos >= self.board.squares() || cell > 16 {
+            return Err(JsError::new(&format!("invalid piece {} on square {}", cell, pos)));
+        }
+        self.board.set_piece(pos, cell_from_u8(cell));
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.board.clear()
+    }
+
+    pub fn set_player(&mut self, player: u8) -> Result<(), JsError> {
+        self.board.set_player(player_from_u8(player)?);
+        Ok(())
+    }
+
+    // fails if the right can not be granted, for lack of the king or a rook on that side.
+    pub fn set_castle_right(&mut self, player: u8, king_side: bool, enabled: bool) -> Result<(), JsError> {
+        if !self.board.set_castle_right(player_from_u8(player)?, king_side, enabled) {
+            return Err(JsError::new("no king and rook to castle with"));
+        }
+        Ok(())
+    }
+
+    pub fn set_en_passant(&mut self, pos: Option<usize>) {
+        self.board.set_en_passant(pos)
+    }
+
+    // fails with the reason the position can not be played from.
+    pub fn validate(&self) -> Result<(), JsError> {
+        self.board.validate().map_err(|e| JsError::new(&e.to_string()))
+    }
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+// a bot, which may keep state between the moves of a game and think during the opponent's turn.
 #[wasm_bindgen]
-pub fn serialize(cb: *const ChessBoard, data_ptr: *mut[u8; 36]) {
-    let buf = unsafe{
-        (*cb).serialize()
-    };
-    unsafe{
-        *data_ptr = buf;
+pub struct Engine {
+    mover: Box<dyn ChessMover>,
+}
+
+#[wasm_bindgen]
+impl Engine {
+    pub fn naive() -> Engine {
+        Engine { mover: Box::new(naive_bot::NaiveBot::new(Default::default())) }
+    }
+
+    /**
+     * naive bot with a custom depth and selectivity, `flags` being a combination of naive_bot::*_FLAG.
+     * `time_limit` is in milliseconds per move, 0 for none.
+     */
+    pub fn naive_with_options(max_depth: usize, flags: u32, time_limit: u32) -> Engine {
+        let mut options = naive_bot::SearchOptions::from_flags(max_depth, flags);
+        options.time_limit = if time_limit == 0 { None } else { Some(time_limit) };
+        Engine { mover: Box::new(naive_bot::naive_bot_with_options(options)) }
+    }
+
+    // naive bot weakened to a skill level from 0 to bot::skill::MAX_SKILL_LEVEL (full strength).
+    pub fn skill(level: usize) -> Engine {
+        Engine { mover: Box::new(bot::skill::SkillBot::new(level)) }
+    }
+
+    pub fn random() -> Engine {
+        Engine { mover: Box::new(RANDOM_BOT) }
+    }
+
+    // the move in the low 32 bits and the evaluation (white is max) in the high 32 bits.
+    pub fn make_move(&mut self, board: &Board) -> Result<i64, JsError> {
+        if board.board.possible_moves().len() == 1 {
+            return Err(JsError::new("no legal move"));
+        }
+        Ok(self.mover.make_move(&board.board))
+    }
+
+    // think during the opponent's turn, one bounded step at a time. returns false when there is nothing left to do.
+    pub fn ponder(&mut self) -> bool {
+        self.mover.ponder()
+    }
+
+    // forget the state kept from the previous game.
+    pub fn new_game(&mut self) {
+        self.mover.new_game()
     }
 }
 
+// estimated Elo of a skill level, relative to the random bot.
 #[wasm_bindgen]
-pub fn cb_do_move(cb: *mut ChessBoard, m: MoveRequest) -> MoveResult {
-    
-    unsafe{
-        (*cb).do_move(m)
+pub fn am_skill_elo(level: usize) -> i32 {
+    bot::skill::SKILL_ELO[level.min(bot::skill::MAX_SKILL_LEVEL)]
+}
+
+/**
+ * Multi-PV analysis with the naive bot, stopping with the last completed depth after `time_limit` milliseconds. For each
+ * of the best `multi_pv` moves, best first, the result holds [evaluation (white is max, encoded as in
+ * Engine.make_move), length of the principal variation, moves of the variation...]
+ */
+#[wasm_bindgen]
+pub fn am_analyze(board: &Board, max_depth: usize, multi_pv: usize, time_limit: u32) -> Vec<i32> {
+    let board = &board.board;
+    let options = naive_bot::SearchOptions { max_depth, multi_pv, time_limit: Some(time_limit), ..Default::default() };
+    let mut ans = vec![];
+    if board.possible_moves().len() == 1 {
+        return ans;
+    }
+    for line in naive_bot::analyze(board, options).lines {
+        ans.push(line.score.to_value());
+        ans.push(line.moves.len() as i32);
+        ans.extend(line.moves.iter().map(|m| *m as i32));
     }
+    ans
+}
+
+// load evaluation parameters (as written by the tuner) for every naive bot. returns false if the data is malformed.
+#[wasm_bindgen]
+pub fn am_load_eval_params(data: &[u8]) -> bool {
+    naive_bot::load_eval_params(data)
 }
 
 #[wasm_bindgen]
-pub fn cb_get_possible_moves(cb: *const ChessBoard) -> Vec<MoveRequest> {
-    return unsafe {
-        (*cb).possible_moves()
-    };
+pub fn am_reset_eval_params() {
+    naive_bot::reset_eval_params()
 }
 
+// load a network file for the naive bot; it is used once enabled with `am_use_nnue(true)`.
 #[wasm_bindgen]
-pub fn cb_get_attacking_range(cb: *const ChessBoard, attacker: i32) -> u64 {
-    let player = if attacker == 0 { Player::White } else { Player::Black };
-    return unsafe {
-        (*cb).get_attacking_range(player).0
-    };
+pub fn am_load_nnue(data: &[u8]) -> bool {
+    naive_bot::load_nnue(data)
 }
 
+// switch the naive bot between the network and the handcrafted evaluation. returns false if no network is loaded.
 #[wasm_bindgen]
-pub fn am_naive(cb: *mut ChessBoard) -> *mut SyncChessMover {
-    let am = SyncChessMover::new(NAIVE_BOT, cb);
-    Box::into_raw(Box::new(am))
+pub fn am_use_nnue(enable: bool) -> bool {
+    naive_bot::use_nnue(enable)
 }
 
+// the game of the history panel: its tree of moves and how it stands. `board()` is a copy of the current position.
 #[wasm_bindgen]
-pub fn am_random(cb: *mut ChessBoard) -> *mut SyncChessMover {
-    let am = SyncChessMover::new(RANDOM_BOT, cb);
-    Box::into_raw(Box::new(am))
+pub struct Game {
+    game: game::Game,
 }
 
 #[wasm_bindgen]
-pub fn am_make_move(am: *mut SyncChessMover) -> i64 {
-    unsafe {(*am).sync_make_move()}
-}
\ No newline at end of file
+impl Game {
+    #[wasm_bindgen(constructor)]
+    pub fn new(board: &Board) -> Game {
+        Game { game: game::Game::new(board.board) }
+    }
+
+    // a game played on time, e.g. "40/5400+30" or "300+2" as in the PGN TimeControl tag, with times in seconds.
+    pub fn with_time_control(board: &Board, control: &str) -> Result<Game, JsError> {
+        let control = TimeControl::parse(control).map_err(|e| JsError::new(&e))?;
+        Ok(Game { game: game::Game::with_clock(board.board, Clock::new(control)) })
+    }
+
+    // milliseconds left to the player, 0 for white and 1 for black; undefined for a game without a clock.
+    pub fn remaining_ms(&self, player: u8) -> Result<Option<f64>, JsError> {
+        let player = player_from_u8(player)?;
+        Ok(self.game.clock.as_ref().map(|clock| clock.remaining_ms(player)))
+    }
+
+    // the player whose clock is running, -1 if none is.
+    pub fn clock_running(&self) -> i32 {
+        self.game.clock.as_ref().and_then(|clock| clock.running()).map_or(-1, |player| player as i32)
+    }
+
+    pub fn board(&self) -> Board {
+        Board { board: *self.game.tree.board() }
+    }
+
+    // play a move from the current node; the low 32 bits of its move result as in Board.do_move.
+    pub fn play(&mut self, m: MoveRequest) -> Result<u32, JsError> {
+        let id = self.game.play(m).map_err(|e| JsError::new(&format!("illegal move: {}", e)))?;
+        Ok(self.game.tree.node(id).unwrap().mres as u32)
+    }
+
+    pub fn back(&mut self) -> bool {
+        self.game.tree.back()
+    }
+
+    pub fn forward(&mut self) -> bool {
+        self.game.tree.forward()
+    }
+
+    pub fn to_start(&mut self) {
+        self.game.tree.to_start()
+    }
+
+    pub fn to_end(&mut self) {
+        self.game.tree.to_end()
+    }
+
+    pub fn jump(&mut self, id: usize) -> Result<(), JsError> {
+        if !self.game.tree.jump(id) {
+            return Err(JsError::new(&format!("no node {}", id)));
+        }
+        Ok(())
+    }
+
+    pub fn take_back(&mut self) -> bool {
+        self.game.tree.take_back()
+    }
+
+    pub fn promote_variation(&mut self, id: usize) -> bool {
+        self.game.tree.promote_variation(id)
+    }
+
+    pub fn delete_variation(&mut self, id: usize) -> bool {
+        self.game.tree.delete_variation(id)
+    }
+
+    pub fn current(&self) -> usize {
+        self.game.tree.current()
+    }
+
+    // the last node of the main line, where the game is being played whatever node is shown.
+    pub fn live(&self) -> usize {
+        self.game.live_node()
+    }
+
+    // a copy of the position at the live node, the one the bots play from.
+    pub fn live_board(&self) -> Board {
+        Board { board: self.game.tree.node(self.game.live_node()).unwrap().board }
+    }
+
+    // the tree in movetext order: node ids, -1 where a variation starts and -2 where it ends.
+    pub fn tokens(&self) -> Vec<i32> {
+        self.game.tree.tokens().into_iter().map(|token| match token {
+            Token::Move(id) => id as i32,
+            Token::StartVariation => -1,
+            Token::EndVariation => -2,
+        }).collect()
+    }
+
+    // the move leading to the node in standard algebraic notation.
+    pub fn san(&self, id: usize) -> Result<String, JsError> {
+        let node = self.game.tree.node(id).ok_or_else(|| JsError::new(&format!("no node {}", id)))?;
+        Ok(node.san.clone())
+    }
+
+    // e.g. "12." before a white move, "12..." before a black one.
+    pub fn move_number(&self, id: usize) -> Result<String, JsError> {
+        if self.game.tree.node(id).is_none() {
+            return Err(JsError::new(&format!("no node {}", id)));
+        }
+        Ok(self.game.tree.move_number(id))
+    }
+
+    pub fn pgn(&self) -> String {
+        self.game.tree.to_pgn_movetext()
+    }
+
+    // [status, winner] where the status is 0 in progress, 1 checkmate, 2 stalemate, 3 by the rules of the variant, 4
+    // threefold repetition, 5 the fifty-move rule, 6 insufficient material, 7 resignation, 8 agreement, 9 timeout, and
+    // the winner 0 for white, 1 for black and -1 for none (a draw).
+    pub fn status(&mut self) -> Vec<i32> {
+        let status = self.game.status();
+        let code = match status {
+            Status::InProgress => 0,
+            Status::Checkmate { .. } => 1,
+            Status::Stalemate { .. } => 2,
+            Status::VariantEnd { .. } => 3,
+            Status::Draw(DrawRule::ThreefoldRepetition) => 4,
+            Status::Draw(DrawRule::FiftyMoves) => 5,
+            Status::Draw(DrawRule::InsufficientMaterial) => 6,
+            Status::Resignation { .. } => 7,
+            Status::Agreement => 8,
+            Status::Timeout { .. } => 9,
+        };
+        vec![code, status.winner().map_or(-1, |player| player as i32)]
+    }
+
+    // e.g. "white wins by checkmate"
+    pub fn status_text(&mut self) -> String {
+        self.game.status().to_string()
+    }
+
+    pub fn resign(&mut self, player: u8) -> Result<(), JsError> {
+        self.game.resign(player_from_u8(player)?);
+        Ok(())
+    }
+
+    pub fn agree_draw(&mut self) {
+        self.game.agree_draw()
+    }
+
+    // queue a move of the player for their next turn; true if it was their turn and the move was played at once.
+    pub fn premove(&mut self, player: u8, m: MoveRequest) -> Result<bool, JsError> {
+        let played = self.game.premove(player_from_u8(player)?, m).map_err(|e| JsError::new(&format!("invalid premove: {}", e)))?;
+        Ok(played.is_some())
+    }
+
+    // the queued moves in the order they will be played.
+    pub fn premoves(&self) -> Vec<MoveRequest> {
+        self.game.premoves().1.to_vec()
+    }
+
+    pub fn clear_premoves(&mut self) {
+        self.game.clear_premoves()
+    }
+
+    // why the premove last found illegal was discarded with those after it, e.g. "e7e5: no piece on square 52"; once.
+    pub fn take_discarded(&mut self) -> Option<String> {
+        let (m, e) = self.game.take_discarded()?;
+        let board = self.game.tree.board();
+        Some(format!("{}: {}", board.mreq_to_uci(m), e))
+    }
+}
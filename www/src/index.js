@@ -5,247 +5,214 @@ import s3 from './assets/3.svg'
 import s4 from './assets/4.svg'
 import s5 from './assets/5.svg'
 import s6 from './assets/6.svg'
+import s7 from './assets/7.svg'
+import s8 from './assets/8.svg'
 import s9 from './assets/9.svg'
 import s10 from './assets/10.svg'
 import s11 from './assets/11.svg'
 import s12 from './assets/12.svg'
 import s13 from './assets/13.svg'
 import s14 from './assets/14.svg'
-
-let chess_ptr;
-let array_ptr, array;
+import s15 from './assets/15.svg'
+import s16 from './assets/16.svg'
+
+// the moves played and explored and how the game stands, a wasm.Game
+let game;
+// the position shown, a copy of the current node of the game
+let board;
+let array;
+// size of the board, 10x8 in Capablanca chess
+let files = 8, ranks = 8;
 let canvas;
 let canvasWrapper;
 let fen;
 let pgn;
 let historyMoves;
+let analysis;
+let premoveMessage;
+// the time left to both players, shown when the game is played on time
+let clock;
+let analysisLines = 0;
+const analysisDepth = 6;
+// the analysis runs on the main thread, so it is cut short after this many milliseconds
+const analysisTime = 200;
+// counts the positions analysis was asked for, so that only the latest one is analyzed
+let analysisRequest = 0;
 
 const animationDelay = 200;
 const isRobot = [false, true];
 let rotated = isRobot[0] && !(isRobot[1]);
 const movers = [null, null];
+// whether a robot move is on its way, from the moment it is asked for until it is played or dropped
+let robotThinking = false;
+// whether bots think on their expected reply during the human's turn
+let pondering = false;
 
-const pieceSet = [null, s1, s2, s3, s4, s5, s6, null, null, s9, s10, s11, s12, s13, s14]
-const pieceName = [null, 'P', 'R', 'N', 'B', 'Q', 'K', null, null, 'p', 'r', 'n', 'b', 'q', 'k']
+const pieceSet = [null, s1, s2, s3, s4, s5, s6, s7, s8, s9, s10, s11, s12, s13, s14, s15, s16]
+const pieceName = [null, 'P', 'R', 'N', 'B', 'Q', 'K', 'A', 'C', 'p', 'r', 'n', 'b', 'q', 'k', 'a', 'c']
 let boardSize = 640
-let cellSize = boardSize / 8;
+let cellSize = boardSize / files;
 const darkBg = "#8c8c8c"
 const lightBg = "#d9d9d9"
 
 let status = 'normal';
 let possibleMoves = {}
-let prevPossibleMoves;
+// crazyhouse drops of the player to move, and the piece picked from the pocket to drop
+let dropMoves = [];
+let selectedDrop = null;
+let crazyhouse = false;
+// piece types in the order of cb_pockets: queen, chancellor, archbishop, rook, bishop, knight, pawn (white; black is
+// 8 more)
+const pocketPieces = [5, 8, 7, 2, 4, 3, 1];
 
 
 let mask;
 
 const selectionWindow = document.createElement("div");
 selectionWindow.id = "selection-window";
-const buttonQueen = document.createElement("button");
-const buttonRook = document.createElement("button");
-const buttonBishop = document.createElement("button");
-const buttonKnight = document.createElement("button");
-
-selectionWindow.appendChild(buttonQueen);
-selectionWindow.appendChild(buttonRook);
-selectionWindow.appendChild(buttonBishop);
-selectionWindow.appendChild(buttonKnight);
 
 
 /**
- * @argument array {Uint8Array}
+ * @argument array {Uint8Array} as returned by Board.serialize: files, ranks, flags, halfmove, en passant, fullmove
+ * (2 bytes), then the squares
  * @argument i {number}
  * @argument j {number}
  * @returns {number}
  */
 function getPiece(array, i, j) {
-    const offset = (i * 8 + j) >> 1;
-    const isHigh = j & 1;
-    if (isHigh) {
-        return array[offset] >> 4;
-    } else {
-        return array[offset] & 0xf;
-    }
+    return array[7 + i * files + j];
 }
 /**
  * @argument array {Uint8Array}
  * @returns {int}
  */
 function getPlayer(array) {
-    return array[32] & 1;
-}
-function getHalfMove(array) {
-    return array[33] & 0x7f;
-}
-function getFullMove(array) {
-    return array[34] | (array[35] << 8);
-}
-function getCastleRights(array) {
-    let ans = ""
-    if (array[32] & 0x2) ans += 'K';
-    if (array[32] & 0x4) ans += 'Q';
-    if (array[32] & 0x8) ans += 'k';
-    if (array[32] & 0x10) ans += 'q';
-    if (ans === "") return "-";
-    return ans;
-}
-function getEnPassant(array) {
-    if (array[33] & 0x80) {
-        let ans = String.fromCharCode('a'.charCodeAt(0) + (array[32] >> 5));
-        if (getPlayer(array) === 0) {
-            ans += '6';
-        } else {
-            ans += '3'
-        }
-        return ans;
-    } else return "-"
+    return array[2] & 1;
+}
+function posToString(pos) {
+    const i = Math.floor(pos / files);
+    const j = pos % files;
+    return String.fromCharCode('a'.charCodeAt(0) + j) + (i + 1);
+}
+// fields of a move request, the low bits of a move result
+function movePos0(move) {
+    return move & 0x7f;
+}
+function movePos1(move) {
+    return (move >> 7) & 0x7f;
+}
+function movePromotion(move) {
+    return (move >> 14) & 0x1f;
+}
+// crazyhouse drops are encoded as a promotion on the spot
+function isDrop(move) {
+    return movePromotion(move) !== 0 && movePos0(move) === movePos1(move);
 }
 /**
- * @param {Uint8Array} array 
+ * @param {number} evaluation 32 bit evaluation from the bot, white is max
+ * @returns {string}
  */
-function toFEN(array) {
-    let ans = ""
-    let counter = 0
-    for (let i = 7; i >= 0; i--) {
-        for (let j = 0; j < 8; j++) {
-            const piece = getPiece(array, i, j);
-            if (piece !== 0) {
-                if (counter > 0) {
-                    ans += '' + counter;
-                    counter = 0;
-                }
-                ans += pieceName[piece];
-            } else {
-                counter++;
-            }
-        }
-        if (counter > 0) ans += '' + counter;
-        counter = 0;
-        if (i !== 0)
-            ans += "/"
-    }
-    ans += getPlayer(array) === 0 ? ' w ' : ' b '
-    ans += getCastleRights(array) + " "
-    ans += getEnPassant(array) + " "
-    ans += getHalfMove(array) + " "
-    ans += getFullMove(array)
-    return ans
-}
-function posToString(pos) {
-    const i = Math.floor(pos / 8);
-    const j = pos % 8;
-    return String.fromCharCode('a'.charCodeAt(0) + j, '1'.charCodeAt(0) + i)
+function evaluationToString(evaluation) {
+    const mateLimit = 1 << 30;
+    if (Math.abs(evaluation) > mateLimit) {
+        const plies = 0x7fffffff - Math.abs(evaluation);
+        const moves = Math.floor((plies + 1) / 2);
+        return evaluation > 0 ? `white mates in ${moves}` : `black mates in ${moves}`;
+    }
+    return (evaluation / 1600).toFixed(2);
 }
 /**
- * @param {number} piece 
- * @param {number} move 
+ * @param {number} move 32 bit move request
+ * @returns {string} move in long algebraic notation
  */
-function toPGN(array, move, prevPossibleMoves, checked) {
-    const pos0 = move & 0x3f;
-    const pos1 = (move & 0xfc0) >> 6;
-    const capture = (move & 0xf0000) != 0;
-    const promotion = (move & 0xf000) >> 12;
-    const isCastle = (move & 0x200000) !== 0;
-
-    if (isCastle) {
-        if (pos0 === 4 && pos1 === 2) {
-            return "O-O-O"
-        }
-        if (pos0 === 4 && pos1 === 6) {
-            return "O-O"
-        }
-        if (pos0 === 60 && pos1 === 58) {
-            return "o-o-o"
-        }
-        if (pos0 === 60 && pos1 === 62) {
-            return "o-o"
-        }
-    }
-
-    let piece;
-    if (!promotion) {
-        piece = getPiece(array, Math.floor(pos1 / 8), pos1 % 8);
-    } else {
-        piece = getPlayer(array) === 0 ? 9 : 1;
-    }
-
-    let collisionPositions = [];
-    for (let src in prevPossibleMoves) {
-        const i = Math.floor(src / 8);
-        const j = src % 8;
-        const anotherPiece = getPiece(array, i, j);
-        if (anotherPiece !== piece) continue;
-        if (prevPossibleMoves[src].find((v) => {
-            return (v & 0xfc0) >> 6 === pos1;
-        })) {
-            collisionPositions.push(src);
-        }
-    }
-    let uniqueI = true;
-    let uniqueJ = true;
-    const pos0i = Math.floor(pos0 / 8);
-    const pos0j = pos0 % 8;
-    for (let x of collisionPositions) {
-        const xi = Math.floor(x / 8);
-        const xj = x % 8;
-        if (xi === pos0i) uniqueI = false;
-        if (xj === pos0j) uniqueJ = false;
+function moveToString(move) {
+    const promotion = movePromotion(move);
+    if (isDrop(move)) {
+        return pieceName[promotion].toUpperCase() + "@" + posToString(movePos0(move));
     }
-
-    let ans = "";
-    if (piece !== 1 && piece !== 9) {
-        ans += pieceName[piece].toUpperCase()
-    }
-    if ((collisionPositions.length > 0 && uniqueJ) || ((piece === 1 || piece === 9) && capture)) {
-        ans += String.fromCharCode('a'.charCodeAt(0) + pos0j);
-    }
-    if (!uniqueJ) {
-        ans += String.fromCharCode('1'.charCodeAt(0) + pos0i);
-    }
-    if (capture) {
-        ans += 'x'
-    }
-    ans += posToString(pos1)
-    if (promotion !== 0) {
-        ans += "=" + pieceName[promotion].toUpperCase()
-    }
-    if (checked === 1) {
-        ans += "+";
-    }
-    if (checked === 2) {
-        ans += "#"
+    return posToString(movePos0(move)) + posToString(movePos1(move)) + (promotion ? pieceName[promotion].toLowerCase() : "");
+}
+/**
+ * show the best `analysisLines` moves of the current position, with their evaluation and principal variation, once
+ * the position is on screen; a newer position cancels the analysis still waiting.
+ */
+function renderAnalysis() {
+    if (analysisLines === 0) return;
+    const request = ++analysisRequest;
+    analysis.innerHTML = "";
+    requestAnimationFrame(() => setTimeout(() => {
+        if (request === analysisRequest) analyze();
+    }, 0));
+}
+function analyze() {
+    const data = wasm.am_analyze(board, analysisDepth, analysisLines, analysisTime);
+    analysis.innerHTML = "";
+    let i = 0;
+    while (i < data.length) {
+        const evaluation = data[i];
+        const len = data[i + 1];
+        const moves = Array.from(data.slice(i + 2, i + 2 + len), moveToString);
+        i += 2 + len;
+        const line = document.createElement("div");
+        line.className = "analysis-line";
+        line.innerHTML = `${evaluationToString(evaluation)}: ${moves.join(" ")}`;
+        analysis.appendChild(line);
     }
-    return ans;
 }
 async function am_make_move() {
     await new Promise((res, _) => {
-        const ans = wasm.am_make_move(movers[getPlayer(array)]);
-        const move = Number(ans & 0xffffn); // 16 bit move request
+        // the robot plays at the live node, whatever position is shown; it is started again on the way back there
+        const node = game.live();
+        const position = game.live_board();
+        const player = getPlayer(position.serialize());
+        if (game.current() !== node || status !== 'normal' || !isRobot[player] || movers[player] === null) {
+            position.free();
+            robotThinking = false;
+            res();
+            return;
+        }
+        const ans = movers[player].make_move(position);
+        position.free();
+        const move = Number(ans & 0xffffffffn); // 32 bit move request
         const evaluation = Number(ans >> 32n); // 32 bit value
-        console.log(`Robot ${getPlayer(array) === 1 ? "Black" : "White"} evaluates the situation as ${evaluation / 1000}`)
-        const pos0 = move & 0x3f;
-        const pos1 = (move & 0xfc0) >> 6;
+        console.log(`Robot ${player === 1 ? "Black" : "White"} evaluates the situation as ${evaluationToString(evaluation)}`)
+        const pos0 = movePos0(move);
+        const pos1 = movePos1(move);
 
         // play animation
-        const pos0i = Math.floor(pos0 / 8);
-        const pos0j = pos0 % 8;
-        const pos1i = Math.floor(pos1 / 8);
-        const pos1j = pos1 % 8;
+        const pos0i = Math.floor(pos0 / files);
+        const pos0j = pos0 % files;
+        const pos1i = Math.floor(pos1 / files);
+        const pos1j = pos1 % files;
         const id = `piece-${pos0i}-${pos0j}`
         const piece = document.getElementById(id);
-        if (animationDelay === 0) {
+        // drops come from the pocket, there is nothing to animate
+        if (animationDelay === 0 || piece === null) {
+            robotThinking = false;
             movePiece(move, true);
             res();
             return;
         }
+        // the move is dropped if the user went to another position in the meantime
+        const play = () => {
+            robotThinking = false;
+            if (game.current() === node) movePiece(move, true);
+        };
         piece.style.transform = `translate(${(rotated ? -1 : 1) * (pos1j - pos0j) * cellSize}px, ${(rotated ? 1 : -1) * (pos1i - pos0i) * cellSize}px)`
         piece.style.transition = `transform ${animationDelay}ms ease-out`;
         setTimeout(() => {
-            movePiece(move, true);
+            play();
             res();
         }, animationDelay);
     })
 }
+// one ponder step at a time, so that the page stays responsive; stops as soon as the human has moved.
+function ponder(mover) {
+    if (!pondering || status !== 'normal' || isRobot[getPlayer(array)]) return;
+    if (mover.ponder()) {
+        setTimeout(() => ponder(mover), 0);
+    }
+}
 /**
  * @param {number} moveReq
  * @param {boolean} isRobot
@@ -255,53 +222,233 @@ function movePiece(moveReq, robot) {
         alert("incorrect role");
         return;
     }
-    // const pos0i = Math.floor(pos0 / 8);
-    // const pos0j = pos0 % 8;
-    // const pos1i = Math.floor(pos1 / 8);
-    // const pos1j = pos1 % 8;
-    // console.log(`Piece ${String.fromCharCode(65 + pos0j)}${pos0i + 1} moved to ${String.fromCharCode(65 + pos1j)}${pos1i + 1}`);
-    const src = moveReq & 0x3f;
-    const piece = getPiece(array, Math.floor(src / 8), src % 8);
-    const res = wasm.cb_do_move(chess_ptr, moveReq);
-
-    wasm.serialize(chess_ptr, array_ptr);
-    fen.value = toFEN(array);
-    
-    const checked = fetch_status();
-    let moveStr = toPGN(array, res, prevPossibleMoves, checked);
-    const li = document.createElement('button');
-    li.innerHTML = moveStr;
-    if (getPlayer(array) === 0) {
-        moveStr = " " + moveStr;
-    } else {
-        let idx = getFullMove(array);
-        if (idx !== 1) moveStr = " " + getFullMove(array) + ". " + moveStr;
-        else moveStr = getFullMove(array) + ". " + moveStr;
-    }
-    pgn.innerHTML += moveStr;
-    historyMoves.appendChild(li);
-
-    replacePieces(array);
+    // console.log(`Piece ${posToString(movePos0(moveReq))} moved to ${posToString(movePos1(moveReq))}`);
+    // a move already played from here is followed instead of added again
+    game.play(moveReq);
+    afterMove();
+}
+/**
+ * show the position reached, queued premoves played, and let the robot to move think or the other one ponder.
+ */
+function afterMove() {
+    refresh();
+    think();
+}
+/**
+ * let the robot to move think, unless it already is, or the other one ponder.
+ */
+function think() {
     if (status !== 'normal') return;
-    // start thinking
     if (isRobot[getPlayer(array)]) {
+        if (robotThinking) return;
+        robotThinking = true;
         setTimeout(am_make_move, 100);
+    } else if (isRobot[1 - getPlayer(array)]) {
+        setTimeout(() => ponder(movers[1 - getPlayer(array)]), animationDelay);
     }
 }
 
+/**
+ * show the position of the current node of the game tree, after a move or when going to another node.
+ */
+function refresh() {
+    board.free();
+    board = game.board();
+    array = board.serialize();
+    fen.value = board.to_fen();
+    pgn.innerHTML = game.pgn();
+    fetch_status();
+    replacePieces(array);
+    renderPockets();
+    renderAnalysis();
+    renderHistory();
+    canvasClearHighlight(canvas);
+    canvasHighlightPremoves(canvas);
+    const discarded = game.take_discarded();
+    if (discarded) showPremoveMessage(`discarded, ${discarded}`);
+}
+/**
+ * why the last premove was refused or discarded, or null to hide the message.
+ * @param {string | null} message
+ */
+function showPremoveMessage(message) {
+    premoveMessage.textContent = message ?? '';
+    document.getElementById("premove-container").classList.toggle("invisible", message === null);
+}
+/**
+ * queue a move of the human for the turn after the robot's, checked only then; a pawn reaching the last rank becomes
+ * a queen.
+ */
+function queuePremove(pos0, pos1) {
+    const piece = getPiece(array, Math.floor(pos0 / files), pos0 % files);
+    const player = getPieceColor(piece);
+    const lastRank = Math.floor(pos1 / files) === (player === 0 ? ranks - 1 : 0);
+    const promotion = (piece - 1) % 8 === 0 && lastRank ? 5 + 8 * player : 0;
+    try {
+        // the robot may have moved in the meantime, then the move is played at once
+        const played = game.premove(player, pos0 | (pos1 << 7) | (promotion << 14));
+        showPremoveMessage(null);
+        if (played) {
+            afterMove();
+            return;
+        }
+    } catch (e) {
+        showPremoveMessage(e.message);
+    }
+    canvasClearHighlight(canvas);
+    canvasHighlightPremoves(canvas);
+}
+/**
+ * @param {number} ms
+ * @returns {string} e.g. "1:30:00", "4:05", or "9.3" under ten seconds
+ */
+function timeToString(ms) {
+    if (ms < 10000) return (Math.floor(ms / 100) / 10).toFixed(1);
+    const s = Math.floor(ms / 1000);
+    const minutes = `${Math.floor(s / 60) % 60}:${String(s % 60).padStart(2, '0')}`;
+    return s >= 3600 ? `${Math.floor(s / 3600)}:${minutes.padStart(5, '0')}` : minutes;
+}
+/**
+ * the time left to both players, the running clock in bold; the game is shown as lost on time once it runs out.
+ */
+function renderClock() {
+    const running = game.clock_running();
+    clock.innerHTML = ["White", "Black"].map((name, player) =>
+        `<span class="${player === running ? "clock-running" : ""}">${name} ${timeToString(game.remaining_ms(player))}</span>`
+    ).join(" ");
+    if (running !== -1 && game.remaining_ms(running) <= 0) refresh();
+}
+/**
+ * @param {function} navigate moves in the tree of the game it is given
+ */
+function goTo(navigate) {
+    if (movement.id !== null) return;
+    navigate(game);
+    canvasClearHighlight(canvas);
+    refresh();
+    // a robot move dropped while another position was shown is made once the live node is shown again
+    if (game.current() === game.live()) think();
+}
+/**
+ * the main line in rows of a move number, the white move and the black one; variations are shown in a row of their
+ * own after the move they replace, in movetext order.
+ */
+function renderHistory() {
+    const tokens = game.tokens();
+    const current = game.current();
+    historyMoves.innerHTML = "";
+    // the row of a white main line move waits for the black one
+    let rowOpen = false;
+    let depth = 0;
+    let variation = null;
+    let numbered = true;
+    const cell = (className, text) => {
+        const span = document.createElement("span");
+        span.className = className;
+        span.innerHTML = text;
+        return span;
+    };
+    for (const token of tokens) {
+        if (token === -1) {
+            if (depth === 0) {
+                if (rowOpen) historyMoves.appendChild(cell("move-number", "..."));
+                rowOpen = false;
+                variation = document.createElement("div");
+                variation.className = "variation";
+                historyMoves.appendChild(variation);
+            } else {
+                variation.appendChild(cell("", "("));
+            }
+            depth++;
+            numbered = true;
+            continue;
+        }
+        if (token === -2) {
+            depth--;
+            if (depth > 0) variation.appendChild(cell("", ")"));
+            numbered = true;
+            continue;
+        }
+        const number = game.move_number(token);
+        const white = !number.endsWith("...");
+        const button = document.createElement("button");
+        button.innerHTML = game.san(token);
+        if (token === current) button.className = "current";
+        button.addEventListener('click', () => goTo((g) => g.jump(token)));
+        if (depth > 0) {
+            if (white || numbered) variation.appendChild(cell("move-number", number));
+            variation.appendChild(button);
+        } else if (white) {
+            historyMoves.appendChild(cell("move-number", number.slice(0, -1)));
+            historyMoves.appendChild(button);
+        } else {
+            if (!rowOpen) {
+                historyMoves.appendChild(cell("move-number", number.slice(0, -3)));
+                historyMoves.appendChild(cell("move-number", "..."));
+            }
+            historyMoves.appendChild(button);
+        }
+        rowOpen = depth === 0 && white;
+        numbered = false;
+    }
+    const currentButton = historyMoves.querySelector("button.current");
+    if (currentButton !== null) currentButton.scrollIntoView({ block: "nearest" });
+}
+/**
+ * show the pieces in hand in crazyhouse; the human to move picks one, then clicks the square to drop it on.
+ */
+function renderPockets() {
+    if (!crazyhouse) return;
+    const counts = board.pockets();
+    selectedDrop = null;
+    for (const player of [0, 1]) {
+        const container = document.getElementById((player === 1) !== rotated ? "pocket-top" : "pocket-bottom");
+        container.innerHTML = "";
+        pocketPieces.forEach((type, k) => {
+            const count = counts[player * pocketPieces.length + k];
+            if (count === 0) return;
+            const piece = type + 8 * player;
+            const div = document.createElement("div");
+            div.className = "pocket-piece";
+            const img = document.createElement("img");
+            img.src = pieceSet[piece];
+            img.width = 48;
+            img.height = 48;
+            img.draggable = false;
+            const label = document.createElement("span");
+            label.className = "pocket-count";
+            label.innerHTML = count;
+            div.appendChild(img);
+            div.appendChild(label);
+            div.addEventListener('click', () => {
+                if (isRobot[getPlayer(array)] || getPlayer(array) !== player || status !== 'normal') return;
+                canvasClearHighlight(canvas);
+                document.querySelectorAll(".pocket-piece").forEach((d) => d.classList.remove("selected"));
+                if (selectedDrop === piece) {
+                    selectedDrop = null;
+                    return;
+                }
+                selectedDrop = piece;
+                div.classList.add("selected");
+                canvasHighlight(canvas, dropMoves.filter((m) => movePromotion(m) === piece));
+            });
+            container.appendChild(div);
+        });
+    }
+}
 /**
  * @argument canvas {HTMLCanvasElement} 
  */
 function canvasInit(canvas) {
-    canvas.width = boardSize;
-    canvas.height = boardSize;
+    canvas.width = cellSize * files;
+    canvas.height = cellSize * ranks;
     canvasClearHighlight(canvas);
-    for (let i = 0; i < 8; i++) {
+    for (let i = 0; i < files; i++) {
         const text = document.createElement('span')
         if (!rotated)
             text.innerHTML = String.fromCharCode('a'.charCodeAt(0) + i);
         else
-            text.innerHTML = String.fromCharCode('h'.charCodeAt(0) - i);
+            text.innerHTML = String.fromCharCode('a'.charCodeAt(0) + files - 1 - i);
 
         text.className = "board-label";
         text.style.bottom = `4px`;
@@ -310,10 +457,10 @@ function canvasInit(canvas) {
         canvasWrapper.appendChild(text);
     }
     
-    for (let i = 0; i < 8; i++) {
+    for (let i = 0; i < ranks; i++) {
         const text = document.createElement('span')
         if (!rotated)
-            text.innerHTML = 8 - i;
+            text.innerHTML = ranks - i;
         else
             text.innerHTML = i + 1;
         text.className = "board-label";
@@ -328,25 +475,40 @@ function canvasInit(canvas) {
  */
 function canvasClearHighlight(canvas) {
     const context = canvas.getContext("2d");
-    for (let i = 0; i < boardSize; i += cellSize) {
-        for (let j = 0; j < boardSize; j += cellSize) {
-            context.fillStyle = (((i + j) / cellSize) & 1) ? darkBg : lightBg;
-            context.fillRect(j, i, cellSize, cellSize);
+    for (let i = 0; i < ranks; i++) {
+        for (let j = 0; j < files; j++) {
+            context.fillStyle = ((i + j) & 1) ? darkBg : lightBg;
+            context.fillRect(j * cellSize, i * cellSize, cellSize, cellSize);
         }
     }
 }
 /**
  * @argument canvas {HTMLCanvasElement}
- * @argument highlightList {Uint16Array}
+ * @argument highlightList {Uint32Array}
  */
 function canvasHighlight(canvas, highlightList) {
     const context = canvas.getContext("2d");
     for (const move of highlightList) {
-        const pos = (move & 0xfc0) >> 6;
-        let i = Math.floor(pos / 8);
-        let j = pos % 8;
+        const pos = movePos1(move);
+        let i = Math.floor(pos / files);
+        let j = pos % files;
         context.fillStyle = 'rgba(80, 180, 80, 0.8)';
-        context.fillRect((rotated ? (7 - j) : j) * cellSize, (rotated ? i : (7 - i)) * cellSize, cellSize, cellSize);
+        context.fillRect((rotated ? (files - 1 - j) : j) * cellSize, (rotated ? i : (ranks - 1 - i)) * cellSize, cellSize, cellSize);
+    }
+}
+/**
+ * the squares of the queued premoves, from and to.
+ * @argument canvas {HTMLCanvasElement}
+ */
+function canvasHighlightPremoves(canvas) {
+    const context = canvas.getContext("2d");
+    for (const move of game.premoves()) {
+        for (const pos of [movePos0(move), movePos1(move)]) {
+            let i = Math.floor(pos / files);
+            let j = pos % files;
+            context.fillStyle = 'rgba(200, 90, 80, 0.6)';
+            context.fillRect((rotated ? (files - 1 - j) : j) * cellSize, (rotated ? i : (ranks - 1 - i)) * cellSize, cellSize, cellSize);
+        }
     }
 }
 
@@ -357,14 +519,16 @@ function canvasHighlight(canvas, highlightList) {
  * @returns {int}
  */
 function getPieceColor(piece) {
-    return piece >> 3;
+    return (piece - 1) >> 3;
 }
 
 const movement = {
     id: null,
     startMouseX: 0,
     startMouseY: 0,
-    possibleMoves: []
+    possibleMoves: [],
+    // a piece of the human moved during the robot's turn, to be queued
+    premove: false
 }
 function placePiece(piece, i, j) {
     const image = pieceSet[piece];
@@ -372,23 +536,26 @@ function placePiece(piece, i, j) {
     img.className = "piece";
     img.setAttribute("type", piece);
     const id = `piece-${i}-${j}`
-    const pos = i * 8 + j;
+    const pos = i * files + j;
     img.id = id
     img.src = image;
     img.width = cellSize;
     img.height = cellSize;
     img.draggable = false;
-    img.style.left = `${(rotated ? (7 - j) : j) * cellSize}px`;
-    img.style.top = `${(rotated ? i : (7 - i)) * cellSize}px`;
+    img.style.left = `${(rotated ? (files - 1 - j) : j) * cellSize}px`;
+    img.style.top = `${(rotated ? i : (ranks - 1 - i)) * cellSize}px`;
     img.addEventListener('mousedown', (e) => {
-        if (isRobot[getPlayer(array)]) return;
         if (movement.id !== null) return;
-        if (getPlayer(array) !== getPieceColor(piece)) return;
-        if (!(pos in possibleMoves)) return;
-
-        canvasHighlight(canvas, possibleMoves[pos]);
+        const premove = status === 'normal' && isRobot[getPlayer(array)] && !isRobot[getPieceColor(piece)];
+        if (!premove) {
+            if (isRobot[getPlayer(array)]) return;
+            if (getPlayer(array) !== getPieceColor(piece)) return;
+            if (!(pos in possibleMoves)) return;
+            canvasHighlight(canvas, possibleMoves[pos]);
+        }
 
-        movement.possibleMoves = possibleMoves[pos];
+        movement.possibleMoves = premove ? [] : possibleMoves[pos];
+        movement.premove = premove;
 
         movement.id = id;
         img.style.zIndex = 10;
@@ -401,8 +568,8 @@ function placePiece(piece, i, j) {
  * @argument array {Uint8Array}
  */
 function placePieces(array) {
-    for (let i = 0; i < 8; i++) {
-        for (let j = 0; j < 8; j++) {
+    for (let i = 0; i < ranks; i++) {
+        for (let j = 0; j < files; j++) {
             const piece = getPiece(array, i, j);
             if (piece == 0) continue;
             placePiece(piece, i, j);
@@ -410,8 +577,8 @@ function placePieces(array) {
     }
 }
 function replacePieces(array) {
-    for (let i = 0; i < 8; i++) {
-        for (let j = 0; j < 8; j++) {
+    for (let i = 0; i < ranks; i++) {
+        for (let j = 0; j < files; j++) {
             const piece = getPiece(array, i, j);
             const oldImg = document.getElementById(`piece-${i}-${j}`);
 
@@ -426,34 +593,72 @@ function replacePieces(array) {
 }
 
 window.onload = async () => {
-    const { memory } = await wasm.default();
-    chess_ptr = wasm.cb_new();
+    await wasm.default();
     const searchParams = new URLSearchParams(window.location.search);
+    // ?chess960=N starts from Chess960 position N (0 to 959), ?chess960=random from a random one
+    const chess960 = searchParams.get('chess960');
+    // ?variant=kingofthehill|3check|antichess|crazyhouse|atomic|horde|racingkings|capablanca|gothic
+    const variant = ['chess', 'kingofthehill', '3check', 'antichess', 'crazyhouse', 'atomic', 'horde', 'racingkings', 'capablanca', 'gothic'].indexOf(searchParams.get('variant'));
+    crazyhouse = variant === 4 && chess960 === null;
+    if (chess960 === null) {
+        board = variant > 0 ? wasm.Board.variant(variant) : new wasm.Board();
+    } else {
+        const index = chess960 === 'random' ? Math.floor(Math.random() * 960) : parseInt(chess960);
+        console.log(`Chess960 position ${index}`);
+        board = wasm.Board.chess960(index);
+    }
+    // ?time=300+2 plays on time, as in the PGN TimeControl tag with times in seconds, e.g. 40/5400+30:1800+30
+    const time = searchParams.get('time');
+    try {
+        game = time === null ? new wasm.Game(board) : wasm.Game.with_time_control(board, time);
+    } catch (e) {
+        alert(`${time} is not a valid time control, playing without a clock: ${e.message}`);
+        game = new wasm.Game(board);
+    }
+    array = board.serialize();
+    files = array[0];
+    ranks = array[1];
     if (window.visualViewport.width < boardSize) {
         boardSize = window.visualViewport.width;
-        cellSize = boardSize / 8;
     }
+    cellSize = boardSize / files;
 
+    // ?skill=N weakens the naive bots, from 0 up to 6 (full strength)
+    const skill = parseInt(searchParams.get('skill'));
+    const newNaive = () => {
+        if (isNaN(skill)) return wasm.Engine.naive();
+        console.log(`Robot skill level ${skill}, about ${wasm.am_skill_elo(skill)} Elo above the random bot`);
+        return wasm.Engine.skill(skill);
+    };
     const w = searchParams.get('white');
     if (w == 'human') {
         isRobot[0] = false;
     } else if (w === 'naive') {
         isRobot[0] = true;
-        movers[0] = wasm.am_naive(chess_ptr);
+        movers[0] = newNaive();
     } else if (w === 'random') {
         isRobot[0] = true;
-        movers[0] = wasm.am_random(chess_ptr);
+        movers[0] = wasm.Engine.random();
     }
     const b = searchParams.get('black');
     if (b == 'human') {
         isRobot[1] = false;
     } else if (b === 'naive') {
         isRobot[1] = true;
-        movers[1] = wasm.am_naive(chess_ptr);
+        movers[1] = newNaive();
     } else if (b === 'random') {
         isRobot[1] = true;
-        movers[1] = wasm.am_random(chess_ptr);
+        movers[1] = wasm.Engine.random();
+    }
+    const params = searchParams.get('params');
+    if (params !== null) {
+        const response = await fetch(params);
+        const data = new Uint8Array(await response.arrayBuffer());
+        if (!wasm.am_load_eval_params(data)) {
+            alert(`${params} is not a valid parameter file, using built-in evaluation`);
+        }
     }
+    pondering = searchParams.get('ponder') === 'true';
     rotated = isRobot[0] && !(isRobot[1]);
     const r = searchParams.get('rotated');
     if (r === 'true') {
@@ -462,31 +667,100 @@ window.onload = async () => {
         rotated = false;
     }
     if (isRobot[0] && movers[0] === null) {
-        movers[0] = wasm.am_naive(chess_ptr);
+        movers[0] = newNaive();
     }
     if (isRobot[1] && movers[1] === null) {
-        movers[1] = wasm.am_naive(chess_ptr);
+        movers[1] = newNaive();
     }
     document.title="White move";
     canvas = document.querySelector("canvas");
     canvasWrapper = document.getElementById("canvas-wrapper");
+    // a right click on the board cancels the premoves
+    canvasWrapper.addEventListener('contextmenu', (e) => {
+        if (game.premoves().length === 0) return;
+        e.preventDefault();
+        game.clear_premoves();
+        showPremoveMessage(null);
+        canvasClearHighlight(canvas);
+        canvasHighlightPremoves(canvas);
+    });
     fen = document.getElementById("fen");
+    premoveMessage = document.getElementById("premove-message");
+    clock = document.getElementById("clock");
+    if (game.remaining_ms(0) !== undefined) {
+        document.getElementById("clock-container").classList.remove("invisible");
+        renderClock();
+        setInterval(renderClock, 100);
+    }
     pgn = document.getElementById("pgn");
     historyMoves = document.getElementById("history-moves");
+    document.getElementById("history-start").addEventListener('click', () => goTo((g) => g.to_start()));
+    document.getElementById("history-back").addEventListener('click', () => goTo((g) => g.back()));
+    document.getElementById("history-forward").addEventListener('click', () => goTo((g) => g.forward()));
+    document.getElementById("history-end").addEventListener('click', () => goTo((g) => g.to_end()));
+    // back to the last position the human was to move in, taking back the bot's reply too
+    document.getElementById("history-takeback").addEventListener('click', () => goTo((g) => {
+        g.take_back();
+        const b = g.board();
+        const player = getPlayer(b.serialize());
+        b.free();
+        if (isRobot[player] && !isRobot[1 - player]) g.take_back();
+    }));
+    document.getElementById("history-promote").addEventListener('click', () => goTo((g) => g.promote_variation(g.current())));
+    document.getElementById("history-delete").addEventListener('click', () => goTo((g) => g.delete_variation(g.current())));
+    // the human to move gives up, or both humans agree to a draw
+    document.getElementById("game-resign").addEventListener('click', () => {
+        game.resign(isRobot[getPlayer(array)] ? 1 - getPlayer(array) : getPlayer(array));
+        refresh();
+    });
+    document.getElementById("game-draw").addEventListener('click', () => {
+        game.agree_draw();
+        refresh();
+    });
+    document.addEventListener('keydown', (e) => {
+        if (e.key === "ArrowLeft") goTo((g) => g.back());
+        if (e.key === "ArrowRight") goTo((g) => g.forward());
+        if (e.key === "Home") goTo((g) => g.to_start());
+        if (e.key === "End") goTo((g) => g.to_end());
+    });
     mask = document.getElementById("mask");
+    analysis = document.getElementById("analysis");
+    const a = parseInt(searchParams.get('analysis'));
+    if (a > 0) {
+        analysisLines = a;
+        document.getElementById("analysis-container").classList.remove("invisible");
+    }
 
-    selectionWindow.style.height = `${cellSize * 4}px`;
     selectionWindow.style.width = `${cellSize}px`;
     canvasInit(canvas);
-    array = new Uint8Array(memory.buffer, 0, 36);
-    array_ptr = array.byteOffset;
 
-    wasm.serialize(chess_ptr, array_ptr);
-    fen.value = toFEN(array);
+    fen.value = board.to_fen();
     placePieces(array);
     fetch_status();
+    if (crazyhouse) {
+        document.getElementById("pocket-top").classList.remove("invisible");
+        document.getElementById("pocket-bottom").classList.remove("invisible");
+        canvas.addEventListener('click', (e) => {
+            if (selectedDrop === null) return;
+            const rect = canvas.getBoundingClientRect();
+            const x = Math.floor((e.clientX - rect.left) / cellSize);
+            const y = Math.floor((e.clientY - rect.top) / cellSize);
+            const pos = rotated ? y * files + (files - 1 - x) : (ranks - 1 - y) * files + x;
+            const move = dropMoves.find((m) => movePos0(m) === pos && movePromotion(m) === selectedDrop);
+            canvasClearHighlight(canvas);
+            selectedDrop = null;
+            if (move !== undefined) {
+                movePiece(move, false);
+            } else {
+                renderPockets();
+            }
+        });
+    }
+    renderPockets();
+    renderAnalysis();
     if (isRobot[0]) {
-        setTimeout(() => am_make_move(movers[0]), 1)
+        robotThinking = true;
+        setTimeout(am_make_move, 1)
     }
 }
 document.body.addEventListener('mousemove', (e) => {
@@ -508,15 +782,23 @@ document.body.addEventListener('mouseup', async (e) => {
         element.style.transform = "";
         element.style.zIndex = 1;
         movement.id = null;
-        const pos0 = parseInt(i) * 8 + parseInt(j);
+        const pos0 = parseInt(i) * files + parseInt(j);
         const pos1i = parseInt(i) + deltaY;
         const pos1j = parseInt(j) + deltaX;
-        if (pos1i < 0 || pos1i >= 8 || pos1j < 0 || pos1j >= 8) {
+        if (movement.premove) {
+            movement.premove = false;
+            if (pos1i >= 0 && pos1i < ranks && pos1j >= 0 && pos1j < files && pos1i * files + pos1j !== pos0) {
+                queuePremove(pos0, pos1i * files + pos1j);
+            }
             res();
+            return;
         }
-        const pos1 = pos1i * 8 + pos1j;
+        if (pos1i < 0 || pos1i >= ranks || pos1j < 0 || pos1j >= files) {
+            res();
+        }
+        const pos1 = pos1i * files + pos1j;
         if (pos1 == pos0) res();
-        let moves = movement.possibleMoves.filter((pred) => ((pred & 0xfc0) >> 6) === pos1);
+        let moves = movement.possibleMoves.filter((pred) => movePos1(pred) === pos1);
         if (moves.length === 0) {
             res();
         }
@@ -528,79 +810,28 @@ document.body.addEventListener('mouseup', async (e) => {
             canvasWrapper.removeChild(e.target);
             mask.classList.remove("invisible");
             canvasWrapper.appendChild(selectionWindow);
-            selectionWindow.style.left = `${pos1j * cellSize}px`;
-            if (getPlayer(array) === 0) {
+            selectionWindow.style.left = `${(rotated ? files - 1 - pos1j : pos1j) * cellSize}px`;
+            selectionWindow.style.height = `${cellSize * moves.length}px`;
+            // the promotion is shown on the side of the board it happens on
+            if ((getPlayer(array) === 0) !== rotated) {
                 selectionWindow.style.bottom = "unset";
                 selectionWindow.style.top = "0";
-                buttonQueen.style.backgroundImage = `url(${pieceSet[5]})`;
-                buttonRook.style.backgroundImage = `url(${pieceSet[2]})`;
-                buttonBishop.style.backgroundImage = `url(${pieceSet[4]})`;
-                buttonKnight.style.backgroundImage = `url(${pieceSet[3]})`;
-
-                // depending on 0, 1, 2, 3 is always consecutive in order of queen, rook, bishop and knight.
-                buttonQueen.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[0], false);
-                    res();
-                }
-
-                buttonRook.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[1], false);
-                    res();
-                }
-
-                buttonBishop.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[2], false);
-                    res();
-                }
-
-                buttonKnight.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[3], false);
-                    res();
-                }
             } else {
                 selectionWindow.style.top = "unset";
                 selectionWindow.style.bottom = "0";
-                buttonQueen.style.backgroundImage = `url(${pieceSet[13]})`;
-                buttonRook.style.backgroundImage = `url(${pieceSet[10]})`;
-                buttonBishop.style.backgroundImage = `url(${pieceSet[12]})`;
-                buttonKnight.style.backgroundImage = `url(${pieceSet[11]})`;
-
-                // depending on 0, 1, 2, 3 is always consecutive in order of queen, rook, bishop and knight.
-                buttonQueen.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[0], false);
-                    res();
-                }
-
-                buttonRook.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[1], false);
-                    res();
-                }
-
-                buttonBishop.onclick = () => {
-                    mask.classList.add("invisible");
-                    canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[2], false);
-                    res();
-                }
-
-                buttonKnight.onclick = () => {
+            }
+            // one button per piece to promote to, in the order of the moves: queen first
+            selectionWindow.innerHTML = "";
+            for (const move of moves) {
+                const button = document.createElement("button");
+                button.style.backgroundImage = `url(${pieceSet[movePromotion(move)]})`;
+                button.onclick = () => {
                     mask.classList.add("invisible");
                     canvasWrapper.removeChild(selectionWindow);
-                    movePiece(moves[3], false);
+                    movePiece(move, false);
                     res();
-                }
+                };
+                selectionWindow.appendChild(button);
             }
         }
         res();
@@ -608,37 +839,33 @@ document.body.addEventListener('mouseup', async (e) => {
 });
 
 
+/**
+ * how the game stands, as decided by Game.status, in `status` and the title; and the moves of the player to move.
+ */
 function fetch_status() {
-    const ans = wasm.cb_get_possible_moves(chess_ptr);
-    document.title = getPlayer(array) === 0 ? "White move" : "Black move";
-    prevPossibleMoves = possibleMoves;
-    if (ans[0] === 1) {
-        if (ans.length === 1) {
-            status = "checkmate";
-            document.title = getPlayer(array) === 0 ? "Black wins!" : "White wins!";
-            return 2; // 2 is checkmate, for displaying move
-        } else {
-            document.title = getPlayer(array) === 0 ? "White move, check" : "Black move, check";
-        }
-    }
-    if (ans[0] === 0 && ans.length === 1) {
-        status = "draw"
-        document.title = "Draw!"
-    }
-    if (getHalfMove(array) >= 100) {
-        status = "draw"
-        document.title = "Draw!"
+    const ans = board.possible_moves();
+    const code = game.status()[0];
+    if (code === 0) {
+        status = 'normal';
+        document.title = (getPlayer(array) === 0 ? "White move" : "Black move") + (ans[0] === 1 ? ", check" : "");
+    } else {
+        status = 'over';
+        const text = game.status_text();
+        document.title = text.charAt(0).toUpperCase() + text.slice(1);
     }
     const moves = ans.slice(1);
-    prevPossibleMoves = possibleMoves;
     possibleMoves = {}
+    dropMoves = [];
     for (const move of moves) {
-        const src = move & 0x3f;
+        const src = movePos0(move);
+        if (isDrop(move)) {
+            dropMoves.push(move);
+            continue;
+        }
         if (!(src in possibleMoves)) {
             possibleMoves[src] = [move]
         } else {
             possibleMoves[src].push(move);
         }
     }
-    return ans[0]; // returning if checked.
-}
\ No newline at end of file
+}